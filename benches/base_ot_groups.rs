@@ -0,0 +1,125 @@
+//! Compares the Edwards and the Ristretto version of the Chou-Orlandi base OT.
+//! Both parties run in this process and talk over an in-memory connection.
+
+#[macro_use]
+extern crate criterion;
+extern crate ot;
+extern crate rand;
+
+use criterion::{Bencher, Criterion, Fun};
+use ot::common::digest::sha3::SHA3_256;
+use ot::common::util::create_random_strings;
+use ot::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+use ot::sync::base_ot::chou_ristretto::{
+    ChouOrlandiRistrettoOTReceiver, ChouOrlandiRistrettoOTSender,
+};
+use ot::sync::base_ot::{BaseOTReceiver, BaseOTSender};
+use ot::sync::communication::memory::{channel_pair, MemoryChannel};
+use ot::sync::crypto::aes::AesCryptoProvider;
+use rand::{ChaChaRng, FromEntropy};
+use std::thread;
+
+/// Runs `num` 1-out-of-`n` transfers of strings of length `l`,
+/// the sender runs in its own thread.
+fn run<S, R>(num: usize, n: usize, l: usize, sender: S, receiver: R)
+where
+    S: FnOnce(MemoryChannel, Vec<Vec<u8>>) + Send + 'static,
+    R: FnOnce(MemoryChannel, Vec<usize>) -> Vec<Vec<u8>>,
+{
+    let (sender_conn, receiver_conn) = channel_pair();
+    let values: Vec<Vec<u8>> = create_random_strings(n, l)
+        .into_iter()
+        .map(|s| s.into_bytes())
+        .collect();
+    let server = thread::spawn(move || sender(sender_conn, values));
+    let indices = (0..num).map(|i| i % n).collect();
+    let result = receiver(receiver_conn, indices);
+    server.join().unwrap();
+    assert_eq!(result.len(), num);
+}
+
+fn edwards(b: &mut Bencher, num: usize, n: usize, l: usize) {
+    b.iter(|| {
+        run(
+            num,
+            n,
+            l,
+            move |stream, values| {
+                let mut ot = ChouOrlandiOTSender::new(
+                    stream,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    ChaChaRng::from_entropy(),
+                ).unwrap();
+                let values = (0..num)
+                    .map(|_| values.iter().map(|v| v.as_slice()).collect())
+                    .collect();
+                ot.send_many(values).unwrap();
+            },
+            move |stream, indices| {
+                let mut ot = ChouOrlandiOTReceiver::new(
+                    stream,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    ChaChaRng::from_entropy(),
+                ).unwrap();
+                ot.receive_many(&indices, n).unwrap()
+            },
+        )
+    });
+}
+
+fn ristretto(b: &mut Bencher, num: usize, n: usize, l: usize) {
+    b.iter(|| {
+        run(
+            num,
+            n,
+            l,
+            move |stream, values| {
+                let mut ot = ChouOrlandiRistrettoOTSender::new(
+                    stream,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    ChaChaRng::from_entropy(),
+                ).unwrap();
+                let values = (0..num)
+                    .map(|_| values.iter().map(|v| v.as_slice()).collect())
+                    .collect();
+                ot.send_many(values).unwrap();
+            },
+            move |stream, indices| {
+                let mut ot = ChouOrlandiRistrettoOTReceiver::new(
+                    stream,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    ChaChaRng::from_entropy(),
+                ).unwrap();
+                ot.receive_many(&indices, n).unwrap()
+            },
+        )
+    });
+}
+
+fn base_ot_group_benchmark(c: &mut Criterion) {
+    // the setup of an OT extension with 128 bit security
+    let num = 128;
+    let n = 2;
+    let l = 16;
+
+    let funs = vec![
+        Fun::new("Edwards", move |b: &mut Bencher, _: &()| {
+            edwards(b, num, n, l)
+        }),
+        Fun::new("Ristretto", move |b: &mut Bencher, _: &()| {
+            ristretto(b, num, n, l)
+        }),
+    ];
+    c.bench_functions(
+        &format!("Chou-Orlandi num={},n={},l={}", num, n, l),
+        funs,
+        (),
+    );
+}
+
+criterion_group!(benches, base_ot_group_benchmark);
+criterion_main!(benches);
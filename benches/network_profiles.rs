@@ -0,0 +1,145 @@
+//! Compares Chou-Orlandi with IKNP for the same number of 1-out-of-2 OTs on simulated networks.
+//! The base OT needs fewer rounds but a public key operation per OT, the extension
+//! needs more rounds but only symmetric cryptography once the base OTs are done.
+
+#[macro_use]
+extern crate criterion;
+extern crate ot;
+extern crate rand;
+
+use criterion::{Bencher, Criterion, Fun};
+use ot::common::digest::sha3::SHA3_256;
+use ot::common::util::{generate_random_choices, generate_random_string_pairs};
+use ot::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+use ot::sync::base_ot::{BaseOTReceiver, BaseOTSender};
+use ot::sync::communication::memory::{channel_pair, MemoryChannel};
+use ot::sync::communication::simulated::{NetworkProfile, SimulatedNetworkChannel};
+use ot::sync::crypto::aes::AesCryptoProvider;
+use ot::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
+use ot::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
+use rand::{ChaChaRng, FromEntropy};
+use std::thread;
+
+type Conn = SimulatedNetworkChannel<MemoryChannel>;
+
+/// Runs `m` OTs of strings of length `l` between two threads connected by a link with `profile`.
+fn run<S, R>(m: usize, l: usize, profile: NetworkProfile, sender: S, receiver: R)
+where
+    S: FnOnce(Conn, Vec<(Vec<u8>, Vec<u8>)>) + Send + 'static,
+    R: FnOnce(Conn, Vec<usize>) -> Vec<Vec<u8>>,
+{
+    let (sender_conn, receiver_conn) = channel_pair();
+    let sender_conn = SimulatedNetworkChannel::new(sender_conn, profile);
+    let receiver_conn = SimulatedNetworkChannel::new(receiver_conn, profile);
+    let values = generate_random_string_pairs(l, m)
+        .into_iter()
+        .map(|(s0, s1)| (s0.into_bytes(), s1.into_bytes()))
+        .collect();
+    let choices = generate_random_choices(m)
+        .iter()
+        .map(|c| c as usize)
+        .collect();
+    let server = thread::spawn(move || sender(sender_conn, values));
+    let result = receiver(receiver_conn, choices);
+    server.join().unwrap();
+    assert_eq!(result.len(), m);
+}
+
+fn chou_orlandi(b: &mut Bencher, m: usize, l: usize, profile: NetworkProfile) {
+    b.iter(|| {
+        run(
+            m,
+            l,
+            profile,
+            |conn, values| {
+                let mut ot = ChouOrlandiOTSender::new(
+                    conn,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    ChaChaRng::from_entropy(),
+                ).unwrap();
+                ot.send_many(
+                    values
+                        .iter()
+                        .map(|(s0, s1)| vec![s0.as_slice(), s1.as_slice()])
+                        .collect(),
+                ).unwrap();
+            },
+            |conn, choices| {
+                let mut ot = ChouOrlandiOTReceiver::new(
+                    conn,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    ChaChaRng::from_entropy(),
+                ).unwrap();
+                ot.receive_many(&choices, 2).unwrap()
+            },
+        )
+    });
+}
+
+fn iknp(b: &mut Bencher, m: usize, l: usize, profile: NetworkProfile) {
+    b.iter(|| {
+        run(
+            m,
+            l,
+            profile,
+            |conn, values| {
+                let rng = ChaChaRng::from_entropy();
+                let ot = ChouOrlandiOTReceiver::new(
+                    conn,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    rng.clone(),
+                ).unwrap();
+                let mut ot_ext = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng, 16).unwrap();
+                ot_ext
+                    .send(
+                        values
+                            .iter()
+                            .map(|(s0, s1)| (s0.as_slice(), s1.as_slice()))
+                            .collect(),
+                    )
+                    .unwrap();
+            },
+            |conn, choices| {
+                let rng = ChaChaRng::from_entropy();
+                let ot = ChouOrlandiOTSender::new(
+                    conn,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    rng.clone(),
+                ).unwrap();
+                let mut ot_ext =
+                    IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, rng, 16).unwrap();
+                ot_ext
+                    .receive(&choices.iter().map(|&c| c == 1).collect())
+                    .unwrap()
+            },
+        )
+    });
+}
+
+fn network_benchmark(c: &mut Criterion) {
+    let l = 16;
+
+    for &(name, profile) in &[("LAN", NetworkProfile::lan()), ("WAN", NetworkProfile::wan())] {
+        for &m in &[128, 1024] {
+            let funs = vec![
+                Fun::new("Chou-Orlandi", move |b: &mut Bencher, _: &()| {
+                    chou_orlandi(b, m, l, profile)
+                }),
+                Fun::new("IKNP", move |b: &mut Bencher, _: &()| iknp(b, m, l, profile)),
+            ];
+            c.bench_functions(&format!("{} m={},l={}", name, m, l), funs, ());
+        }
+    }
+}
+
+// every iteration waits for the simulated network, so fewer samples are collected
+criterion_group!{
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = network_benchmark
+}
+criterion_main!(benches);
@@ -1,6 +1,3 @@
-#![feature(test)]
-
-extern crate test;
 
 extern crate bit_vec;
 
@@ -12,13 +9,16 @@ extern crate rand;
 extern crate tungstenite;
 extern crate url;
 
+use ot::common::bitmatrix::BitMatrix;
 use ot::common::digest::sha3::SHA3_256;
 use ot::common::util::{
     create_random_strings, generate_random_choices, generate_random_string_pairs,
 };
 use ot::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
 use ot::sync::base_ot::{BaseOTReceiver, BaseOTSender};
-use ot::sync::communication::{BinaryReceive, BinarySend, GetConn};
+use ot::sync::communication::memory::{channel_pair, MemoryChannel};
+use ot::sync::communication::metered::{MeteredChannel, Stopwatch};
+use ot::sync::communication::{BinaryReceive, BinarySend};
 use ot::sync::crypto::aes::AesCryptoProvider;
 use ot::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
 use ot::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
@@ -34,12 +34,22 @@ use bit_vec::BitVec;
 
 use byte_tools::write_u64_be;
 
+use criterion::black_box;
 use criterion::Bencher;
 use criterion::Criterion;
 use criterion::Fun;
 
+use std::env;
+use std::thread::{sleep, spawn};
 use std::time::Duration;
-use std::thread::sleep;
+
+/// The TCP and WebSocket benchmarks need the benchmarking server on port 8123,
+/// they are only run if this variable is set.
+const SERVER_VAR: &str = "OT_BENCH_SERVER";
+
+fn with_server() -> bool {
+    env::var_os(SERVER_VAR).is_some()
+}
 
 fn conn_setup_ot(
     n: usize,
@@ -95,12 +105,10 @@ fn ws_setup_ot(
     )
 }
 
-fn conn_setup_ote(
-    n: usize,
-    l: usize,
-    is_ws: bool,
-    role: &str,
-) -> (TcpStream, Vec<(Vec<u8>, Vec<u8>)>, BitVec, ChaChaRng) {
+/// Connection, value pairs, choices and rng of one party of an OT extension benchmark.
+type OteInput<T> = (T, Vec<(Vec<u8>, Vec<u8>)>, BitVec, ChaChaRng);
+
+fn conn_setup_ote(n: usize, l: usize, is_ws: bool, role: &str) -> OteInput<TcpStream> {
     let mut stream = TcpStream::connect("127.0.0.1:8123").unwrap();
     let conn_type = if is_ws { "websocket" } else { "tcp" };
 
@@ -120,7 +128,7 @@ fn conn_setup_ote(
         .into_iter()
         .map(|(s0, s1)| (s0.into_bytes(), s1.into_bytes()))
         .collect();
-    let mut rng = ChaChaRng::from_entropy();
+    let rng = ChaChaRng::from_entropy();
 
     let choices = generate_random_choices(n);
 
@@ -129,23 +137,10 @@ fn conn_setup_ote(
     // }
     (stream, vals, choices, rng)
 }
-fn tcp_setup_ote(
-    n: usize,
-    l: usize,
-    role: &str,
-) -> (TcpStream, Vec<(Vec<u8>, Vec<u8>)>, BitVec, ChaChaRng) {
+fn tcp_setup_ote(n: usize, l: usize, role: &str) -> OteInput<TcpStream> {
     conn_setup_ote(n, l, false, role)
 }
-fn ws_setup_ote(
-    n: usize,
-    l: usize,
-    role: &str,
-) -> (
-    WebSocket<TcpStream>,
-    Vec<(Vec<u8>, Vec<u8>)>,
-    BitVec,
-    ChaChaRng,
-) {
+fn ws_setup_ote(n: usize, l: usize, role: &str) -> OteInput<WebSocket<TcpStream>> {
     let (stream, vals, choices, rng) = conn_setup_ote(n, l, true, role);
     (
         client(Url::parse("ws://localhost:8123").unwrap(), stream)
@@ -170,10 +165,7 @@ where
     ).unwrap();
     //println!("chou ot receiver creation");
     if with_send {
-        test::black_box(
-            ot.send(input.1.iter().map(|s| s.as_slice()).collect())
-                .unwrap(),
-        );
+        black_box(ot.send(input.1.iter().map(|s| s.as_slice()).collect())).unwrap();
     }
 }
 
@@ -190,18 +182,16 @@ where
     ).unwrap();
     //println!("chou ot receiver creation");
     if with_receive {
-        test::black_box(ot.receive(input.2, input.1.len()).unwrap());
+        black_box(ot.receive(input.2, input.1.len()).unwrap());
     }
 }
 
-fn extended_ot_receive<T>(
-    input: (T, Vec<(Vec<u8>, Vec<u8>)>, BitVec, ChaChaRng),
-    with_receive: bool,
-) where
+fn extended_ot_receive<T>(input: OteInput<T>, with_receive: bool)
+where
     T: BinaryReceive + BinarySend,
 {
     //println!("Creating BaseOT receiver...");
-    let mut ot = ChouOrlandiOTSender::new(
+    let ot = ChouOrlandiOTSender::new(
         input.0,
         SHA3_256::default(),
         AesCryptoProvider::default(),
@@ -212,17 +202,17 @@ fn extended_ot_receive<T>(
         IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, input.3.clone(), 16).unwrap();
 
     if with_receive {
-        test::black_box(ot_ext.receive(&input.2).unwrap());
+        black_box(ot_ext.receive(&input.2).unwrap());
     }
     //println!("chou ot receiver creation");
 }
 
-fn extended_ot_send<T>(input: (T, Vec<(Vec<u8>, Vec<u8>)>, BitVec, ChaChaRng), with_send: bool)
+fn extended_ot_send<T>(input: OteInput<T>, with_send: bool)
 where
     T: BinaryReceive + BinarySend,
 {
     //println!("Creating BaseOT receiver...");
-    let mut ot = ChouOrlandiOTReceiver::new(
+    let ot = ChouOrlandiOTReceiver::new(
         input.0,
         SHA3_256::default(),
         AesCryptoProvider::default(),
@@ -233,25 +223,113 @@ where
         IKNPExtendedOTSender::new(SHA3_256::default(), ot, input.3.clone(), 16).unwrap();
 
     if with_send {
-        test::black_box(
-            ot_ext
-                .send(
-                    input
-                        .1
-                        .iter()
-                        .map(|(s1, s2)| (s1.as_slice(), s2.as_slice()))
-                        .collect(),
-                )
-                .unwrap(),
-        );
+        black_box(
+            ot_ext.send(
+                input
+                    .1
+                    .iter()
+                    .map(|(s1, s2)| (s1.as_slice(), s2.as_slice()))
+                    .collect(),
+            ),
+        )
+        .unwrap();
     }
     //println!("chou ot receiver creation");
 }
 
+/// Like `tcp_setup_ot`, but the counterpart runs in a thread of this process.
+/// Without `with_transfer` both parties only do the setup.
+fn memory_setup_ot(
+    n: usize,
+    l: usize,
+    role: &str,
+    with_transfer: bool,
+) -> (MemoryChannel, Vec<Vec<u8>>, usize, ChaChaRng) {
+    let (conn, peer_conn) = channel_pair();
+    let strings = create_random_strings(n, l);
+    let vals: Vec<Vec<u8>> = strings.into_iter().map(|s| s.into_bytes()).collect();
+    let mut rng = ChaChaRng::from_entropy();
+    let choice = rng.sample(Range::new(0, n));
+    let peer = (peer_conn, vals.clone(), choice, rng.clone());
+    if role == "receive" {
+        spawn(move || simple_ot_receive(peer, with_transfer));
+    } else {
+        spawn(move || simple_ot_send(peer, with_transfer));
+    }
+    (conn, vals, choice, rng)
+}
+
+/// Like `tcp_setup_ote`, but the counterpart runs in a thread of this process.
+fn memory_setup_ote(n: usize, l: usize, role: &str) -> OteInput<MemoryChannel> {
+    let (conn, peer_conn) = channel_pair();
+    let vals: Vec<(Vec<u8>, Vec<u8>)> = generate_random_string_pairs(l, n)
+        .into_iter()
+        .map(|(s0, s1)| (s0.into_bytes(), s1.into_bytes()))
+        .collect();
+    let rng = ChaChaRng::from_entropy();
+    let choices = generate_random_choices(n);
+    let peer = (peer_conn, vals.clone(), choices.clone(), rng.clone());
+    if role == "receive" {
+        spawn(move || extended_ot_receive(peer, true));
+    } else {
+        spawn(move || extended_ot_send(peer, true));
+    }
+    (conn, vals, choices, rng)
+}
+
+/// Runs every protocol once and prints what each phase costs the sender.
+fn communication_report(_: &mut Criterion) {
+    let n = 1000;
+    let l = 64;
+
+    println!("SimpleOT Sender n=2,l={}", l);
+    let (conn, vals, _, rng) = memory_setup_ot(2, l, "receive", true);
+    let conn = MeteredChannel::new(conn);
+    let mut stopwatch = Stopwatch::start(conn.meter());
+    let mut ot = ChouOrlandiOTSender::new(
+        conn,
+        SHA3_256::default(),
+        AesCryptoProvider::default(),
+        rng,
+    ).unwrap();
+    println!("  base OT setup: {}", stopwatch.lap());
+    ot.send(vals.iter().map(|s| s.as_slice()).collect()).unwrap();
+    println!("  transfer: {}", stopwatch.lap());
+
+    println!("IKNP OTe Sender n={},l={}", n, l);
+    let (conn, vals, _, rng) = memory_setup_ote(n, l, "receive");
+    let conn = MeteredChannel::new(conn);
+    let mut stopwatch = Stopwatch::start(conn.meter());
+    let ot = ChouOrlandiOTReceiver::new(
+        conn,
+        SHA3_256::default(),
+        AesCryptoProvider::default(),
+        rng.clone(),
+    ).unwrap();
+    println!("  base OT setup: {}", stopwatch.lap());
+    let mut ot_ext = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng, 16).unwrap();
+    println!("  extension setup: {}", stopwatch.lap());
+    ot_ext
+        .send(
+            vals.iter()
+                .map(|(s1, s2)| (s1.as_slice(), s2.as_slice()))
+                .collect(),
+        )
+        .unwrap();
+    println!("  transfer: {}", stopwatch.lap());
+}
+
 fn ot_native_send_benchmark(c: &mut Criterion) {
     let n = 2;
     let l = 64;
 
+    let memory_fun = Fun::new("Memory", move |b: &mut Bencher, _: &()| {
+        b.iter_with_setup(
+            move || memory_setup_ot(n, l, "receive", true),
+            |t| simple_ot_send(t, true),
+        )
+    });
+
     let tcp_fun = Fun::new("TCP", move |b: &mut Bencher, _: &()| {
         b.iter_with_setup(
             move || tcp_setup_ot(n, l, "receive"),
@@ -266,27 +344,28 @@ fn ot_native_send_benchmark(c: &mut Criterion) {
         )
     });
 
-    let funs = vec![tcp_fun, ws_fun];
+    let mut funs = vec![memory_fun];
+    if with_server() {
+        funs.push(tcp_fun);
+        funs.push(ws_fun);
+    }
 
     c.bench_functions(&format!("SimpleOT Sender n={},l={}", n, l), funs, ());
 
     c.bench_function(
-        &format!("SimpleOT Sender TCP n={},l={} Setup Only", n, l),
+        &format!("SimpleOT Sender n={},l={} Setup Only", n, l),
         move |b| {
             b.iter_with_setup(
-                move || tcp_setup_ot(n, l, "receive"),
+                move || memory_setup_ot(n, l, "receive", false),
                 |t| simple_ot_send(t, false),
             )
         },
     );
-    // c.bench_function_over_inputs(&format!("ot_native_tcp_n_{}", l), move |b, &&growing_n| {
-    //     b.iter_with_setup(move || tcp_setup(growing_n, l, "receive"), |t| simple_ot_receive(t, true))
-    // }, &[10, 100, 1000, 10000]);
     c.bench_function_over_inputs(
-        &format!("SimpleOT Sender TCP n={}, l", n),
+        &format!("SimpleOT Sender n={}, l", n),
         move |b, &&growing_l| {
             b.iter_with_setup(
-                move || tcp_setup_ot(n, growing_l, "receive"),
+                move || memory_setup_ot(n, growing_l, "receive", true),
                 |t| simple_ot_send(t, true),
             )
         },
@@ -298,6 +377,13 @@ fn ot_native_receive_benchmark(c: &mut Criterion) {
     let n = 2;
     let l = 64;
 
+    let memory_fun = Fun::new("Memory", move |b: &mut Bencher, _: &()| {
+        b.iter_with_setup(
+            move || memory_setup_ot(n, l, "send", true),
+            |t| simple_ot_receive(t, true),
+        )
+    });
+
     let tcp_fun = Fun::new("TCP", move |b: &mut Bencher, _: &()| {
         b.iter_with_setup(
             move || tcp_setup_ot(n, l, "send"),
@@ -312,15 +398,19 @@ fn ot_native_receive_benchmark(c: &mut Criterion) {
         )
     });
 
-    let funs = vec![tcp_fun, ws_fun];
+    let mut funs = vec![memory_fun];
+    if with_server() {
+        funs.push(tcp_fun);
+        funs.push(ws_fun);
+    }
 
     c.bench_functions(&format!("SimpleOT Receiver n={},l={}", n, l), funs, ());
 
     c.bench_function(
-        &format!("SimpleOT Receiver TCP n={},l={}, Setup only", n, l),
+        &format!("SimpleOT Receiver n={},l={}, Setup only", n, l),
         move |b| {
             b.iter_with_setup(
-                move || tcp_setup_ot(n, l, "send"),
+                move || memory_setup_ot(n, l, "send", false),
                 |t| simple_ot_receive(t, false),
             )
         },
@@ -329,17 +419,17 @@ fn ot_native_receive_benchmark(c: &mut Criterion) {
         &format!("SimpleOT Receiver n, l={}", l),
         move |b, &&growing_n| {
             b.iter_with_setup(
-                move || tcp_setup_ot(growing_n, l, "receive"),
+                move || memory_setup_ot(growing_n, l, "send", true),
                 |t| simple_ot_receive(t, true),
             )
         },
         &[10, 100, 1000],
     );
     c.bench_function_over_inputs(
-        &format!("SimpleOT Receiver TCP n={}, l", n),
+        &format!("SimpleOT Receiver n={}, l", n),
         move |b, &&growing_l| {
             b.iter_with_setup(
-                move || tcp_setup_ot(n, growing_l, "send"),
+                move || memory_setup_ot(n, growing_l, "send", true),
                 |t| simple_ot_receive(t, true),
             )
         },
@@ -351,6 +441,13 @@ fn ote_native_send_benchmark(c: &mut Criterion) {
     let n = 1000;
     let l = 64;
 
+    let memory_fun = Fun::new("Memory", move |b: &mut Bencher, _: &()| {
+        b.iter_with_setup(
+            move || memory_setup_ote(n, l, "receive"),
+            |t| extended_ot_send(t, true),
+        )
+    });
+
     let tcp_fun = Fun::new("TCP", move |b: &mut Bencher, _: &()| {
         b.iter_with_setup(
             move || tcp_setup_ote(n, l, "receive"),
@@ -365,39 +462,26 @@ fn ote_native_send_benchmark(c: &mut Criterion) {
         )
     });
 
-    let funs = vec![tcp_fun, ws_fun];
+    let mut funs = vec![memory_fun];
+    if with_server() {
+        funs.push(tcp_fun);
+        funs.push(ws_fun);
+    }
 
     c.bench_functions(&format!("IKNP OTe Sender n={},l={}", n, l), funs, ());
-
-    c.bench_function(
-        &format!("SimpleOT Sender TCP n={},l={} Setup Only", n, l),
-        move |b| {
-            b.iter_with_setup(
-                move || tcp_setup_ot(n, l, "receive"),
-                |t| simple_ot_send(t, false),
-            )
-        },
-    );
-    c.bench_function_over_inputs(&format!("ot_native_tcp_n_{}", l), move |b, &&growing_n| {
-        b.iter_with_setup(move || tcp_setup_ot(growing_n, l, "receive"), |t| simple_ot_receive(t, true))
-    }, &[10, 100, 1000]);
-
-    // c.bench_function_over_inputs(
-    //     &format!("SimpleOT Sender TCP n={}, l", n),
-    //     move |b, &&growing_l| {
-    //         b.iter_with_setup(
-    //             move || tcp_setup_ot(n, growing_l, "receive"),
-    //             |t| simple_ot_send(t, true),
-    //         )
-    //     },
-    //     &[32, 64, 128, 256],
-    // );
 }
 
 fn ote_native_receive_benchmark(c: &mut Criterion) {
     let n = 1000;
     let l = 64;
 
+    let memory_fun = Fun::new("Memory", move |b: &mut Bencher, _: &()| {
+        b.iter_with_setup(
+            move || memory_setup_ote(n, l, "send"),
+            |t| extended_ot_receive(t, true),
+        )
+    });
+
     let tcp_fun = Fun::new("TCP", move |b: &mut Bencher, _: &()| {
         b.iter_with_setup(
             move || tcp_setup_ote(n, l, "send"),
@@ -412,46 +496,56 @@ fn ote_native_receive_benchmark(c: &mut Criterion) {
         )
     });
 
-    let funs = vec![tcp_fun, ws_fun];
+    let mut funs = vec![memory_fun];
+    if with_server() {
+        funs.push(tcp_fun);
+        funs.push(ws_fun);
+    }
 
     c.bench_functions(&format!("IKNP OTe Receiver n={},l={}", n, l), funs, ());
+}
+
+/// Compares the bit by bit transposition the OT extensions used to do with `BitMatrix::transpose`.
+fn transpose_benchmark(c: &mut Criterion) {
+    let l = 128;
+    let n = 1 << 16;
 
-    // c.bench_function(
-    //     &format!("SimpleOT Receiver TCP n={},l={}, Setup only", n, l),
-    //     move |b| {
-    //         b.iter_with_setup(
-    //             move || tcp_setup_ot(n, l, "send"),
-    //             |t| simple_ot_receive(t, false),
-    //         )
-    //     },
-    // );
-    // // c.bench_function_over_inputs(
-    // //     &format!("SimpleOT Receiver n, l={}", l),
-    // //     move |b, &&growing_n| {
-    // //         b.iter_with_setup(
-    // //             move || tcp_setup(growing_n, l, "receive"),
-    // //             |t| simple_ot_receive(t, true),
-    // //         )
-    // //     },
-    // //     &[10, 100, 1000, 10000],
-    // // );
-    // c.bench_function_over_inputs(
-    //     &format!("SimpleOT Receiver TCP n={}, l", n),
-    //     move |b, &&growing_l| {
-    //         b.iter_with_setup(
-    //             move || tcp_setup_ot(n, growing_l, "send"),
-    //             |t| simple_ot_receive(t, true),
-    //         )
-    //     },
-    //     &[32, 64, 128, 256],
-    // );
+    let mut rng = ChaChaRng::from_entropy();
+    let columns: Vec<Vec<u8>> = (0..l)
+        .map(|_| (0..n / 8).map(|_| rng.gen::<u8>()).collect())
+        .collect();
+    let bit_columns: Vec<BitVec> = columns.iter().map(|c| BitVec::from_bytes(c)).collect();
+    let matrix = BitMatrix::from_rows(&columns, n);
+
+    let naive_fun = Fun::new("Bitwise", move |b: &mut Bencher, _: &()| {
+        b.iter(|| {
+            (0..n)
+                .map(|i| bit_columns.iter().map(|c| c[i]).collect::<BitVec>().to_bytes())
+                .collect::<Vec<Vec<u8>>>()
+        })
+    });
+
+    let blocked_fun = Fun::new("Blocked", move |b: &mut Bencher, _: &()| {
+        b.iter(|| matrix.transpose())
+    });
+
+    let funs = vec![naive_fun, blocked_fun];
+
+    c.bench_functions(&format!("Transpose l={},n={}", l, n), funs, ());
 }
 
+// the bitwise transposition takes a while, so fewer samples are collected
+criterion_group!{
+    name = transpose;
+    config = Criterion::default().sample_size(10);
+    targets = transpose_benchmark
+}
 criterion_group!(
     benches,
+    communication_report,
     ot_native_send_benchmark,
     ot_native_receive_benchmark,
-    // ote_native_send_benchmark,
-    // ote_native_receive_benchmark
+    ote_native_send_benchmark,
+    ote_native_receive_benchmark
 );
-criterion_main!(benches);
+criterion_main!(transpose, benches);
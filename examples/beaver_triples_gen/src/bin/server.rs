@@ -1,44 +1,26 @@
-extern crate beaver_triples_gen;
-extern crate bit_vec;
-extern crate ot;
-extern crate rand;
-extern crate tungstenite;
-
-use bit_vec::BitVec;
 use ot::common::digest::sha3::SHA3_256;
 use ot::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
 use ot::sync::communication::{BinaryReceive, BinarySend, GetConn};
 use ot::sync::crypto::aes::AesCryptoProvider;
 use ot::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
-use ot::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
+use ot::sync::ot_extension::{CorrelatedOTReceiver, CorrelatedOTSender};
 use rand::{ChaChaRng, FromEntropy};
 use std::env;
 use std::net::TcpListener;
 use std::thread::spawn;
-use tungstenite::handshake::server::Request;
-use tungstenite::server::accept_hdr;
+use tungstenite::accept_hdr;
+use tungstenite::handshake::server::{Request, Response};
 
 use std::time::Instant;
 
 use beaver_triples_gen::*;
 
-fn calculate_beaver_triple<T>(conn: T, a: GFElement, b: GFElement) -> GFElement
+fn calculate_beaver_triple<T>(conn: T, a: RingElement, b: RingElement) -> RingElement
 where
     T: BinaryReceive + BinarySend,
 {
-    let mut rng = ChaChaRng::from_entropy();
-
-    let ts: Vec<GFElement> = (0..K).map(|_| GFElement::random(&mut rng)).collect();
-    let pairs: Vec<(Vec<u8>, Vec<u8>)> = ts
-        .iter()
-        .enumerate()
-        .map(|(i, t)| {
-            let x = a * GFElement::new(1 << i);
-            (t.to_bytes(), (*t + x).to_bytes())
-        })
-        .collect();
-    let bytes = b.to_bytes();
-    let choices: BitVec = BitVec::from_bytes(&bytes).into_iter().rev().collect();
+    let deltas = gilboa_deltas(a);
+    let choices = gilboa_choices(b);
 
     let mut rng = ChaChaRng::from_entropy();
     println!("Creating BaseOT receiver...");
@@ -56,15 +38,9 @@ where
     println!("Sending values...");
     now = Instant::now();
 
-    ot_ext_send
-        .send(pairs.iter().map(|(p1, p2)| (&p1[..], &p2[..])).collect())
-        .unwrap();
+    let xs = ot_ext_send.send_correlated_additive(&deltas).unwrap();
     println!("IKNP send took {:?}", now.elapsed());
-
-    let mut send_result = GFElement(0);
-    for t in ts.into_iter() {
-        send_result += t;
-    }
+    let send_result = gilboa_share(&xs, true);
 
     rng = ChaChaRng::from_entropy();
 
@@ -85,15 +61,11 @@ where
             .unwrap();
     println!("IKNP receiver creation took {:?}", now.elapsed());
     now = Instant::now();
-    let qs = ot_ext_recv.receive(&choices).unwrap();
-    println!("IKNP send took {:?}", now.elapsed());
-
-    let mut recv_result = GFElement(0);
-    for q in qs.into_iter().map(|e| GFElement::from_bytes(e)) {
-        recv_result += q;
-    }
+    let ys = ot_ext_recv.receive_correlated_additive(&choices).unwrap();
+    println!("IKNP receive took {:?}", now.elapsed());
+    let recv_result = gilboa_share(&ys, false);
 
-    let c = a * b + (-send_result) + recv_result;
+    let c = a * b + send_result + recv_result;
     println!("Sending to client for verification...");
     let mut v1 = a.to_bytes();
     let mut v2 = b.to_bytes();
@@ -110,25 +82,27 @@ fn main() {
 
     let server = TcpListener::bind(address).unwrap();
     for stream in server.incoming() {
-        let callback = |req: &Request| {
+        let callback = |req: &Request, mut response: Response| {
             println!("Received a new ws handshake");
-            println!("The request's path is: {}", req.path);
+            println!("The request's path is: {}", req.uri().path());
             println!("The request's headers are:");
-            for &(ref header, _ /* value */) in req.headers.iter() {
+            for header in req.headers().keys() {
                 println!("* {}", header);
             }
 
             // TODO: for better example decide based on the subprotocol if you send or receive
-            let extra_headers = vec![(String::from("Sec-WebSocket-Protocol"), String::from("ot"))];
-            Ok(Some(extra_headers))
+            response
+                .headers_mut()
+                .insert("Sec-WebSocket-Protocol", "ot".parse().unwrap());
+            Ok(response)
         };
         spawn(move || {
             let now = Instant::now();
             let stream = accept_hdr(stream.unwrap(), callback).unwrap();
             let mut rng = ChaChaRng::from_entropy();
 
-            let a = GFElement::random(&mut rng);
-            let b = GFElement::random(&mut rng);
+            let a = RingElement::random(&mut rng);
+            let b = RingElement::random(&mut rng);
             let c = calculate_beaver_triple(stream, a, b);
 
             println!("Triple generated: [{}] * [{}] = [{}]", a.0, b.0, c.0);
@@ -1,78 +1,87 @@
 extern crate rand;
 
-use rand::distributions::range::Range;
-use rand::distributions::Distribution;
-use rand::{ChaChaRng, CryptoRng, FromEntropy, RngCore};
-use std::mem::transmute;
+use bit_vec::BitVec;
+use rand::{CryptoRng, Rng, RngCore};
+use std::convert::TryInto;
 use std::ops::{Add, AddAssign, Mul, Neg, Sub};
 
-// modulus is < 2^63 so addition never overflows
-pub const MODULUS: u64 = 9223372036854775783;
+// the shares live in Z_{2^64}, which is what the additive correlated OT works in
 pub const K: usize = 64;
 pub const SECURITY_PARAM: usize = 16;
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct GFElement(pub u64);
+pub struct RingElement(pub u64);
 
-impl GFElement {
-    pub fn new(val: u64) -> GFElement {
-        GFElement(val % MODULUS)
-    }
-
-    pub fn random<R: CryptoRng + RngCore>(rng: &mut R) -> GFElement {
-        let range = Range::new(0, MODULUS);
-        GFElement(range.sample(rng))
+impl RingElement {
+    pub fn random<R: CryptoRng + RngCore>(rng: &mut R) -> RingElement {
+        RingElement(rng.gen())
     }
 
     pub fn from_bytes(v: Vec<u8>) -> Self {
-        assert_eq!(
-            v.len(),
-            8,
-            "Vector has to have 8 bytes to be converted to u64"
-        );
-        let mut bytes: [u8; 8] = Default::default();
-        bytes.copy_from_slice(&v);
-
-        let val = unsafe { u64::from_be(transmute(bytes)) };
-        GFElement(val)
+        let bytes: [u8; 8] = v
+            .as_slice()
+            .try_into()
+            .expect("Vector has to have 8 bytes to be converted to u64");
+        RingElement(u64::from_be_bytes(bytes))
     }
     pub fn to_bytes(self) -> Vec<u8> {
-        let bytes: [u8; 8] = unsafe { transmute(self.0.to_be()) };
-        bytes.to_vec()
+        self.0.to_be_bytes().to_vec()
     }
 }
 
-impl Add for GFElement {
+impl Add for RingElement {
     type Output = Self;
-    fn add(self, other: GFElement) -> Self {
-        GFElement((self.0 + other.0) % MODULUS)
+    fn add(self, other: RingElement) -> Self {
+        RingElement(self.0.wrapping_add(other.0))
     }
 }
 
-impl AddAssign for GFElement {
-    fn add_assign(&mut self, other: GFElement) {
-        (*self).0 = (self.0 + other.0) % MODULUS;
+impl AddAssign for RingElement {
+    fn add_assign(&mut self, other: RingElement) {
+        *self = *self + other;
     }
 }
 
-impl Mul for GFElement {
+impl Mul for RingElement {
     type Output = Self;
-    fn mul(self, rhs: GFElement) -> GFElement {
-        let r = ((self.0 as u128) * (rhs.0 as u128)) % (MODULUS as u128);
-        GFElement(r as u64)
+    fn mul(self, rhs: RingElement) -> RingElement {
+        RingElement(self.0.wrapping_mul(rhs.0))
     }
 }
 
-impl Neg for GFElement {
+impl Neg for RingElement {
     type Output = Self;
     fn neg(self) -> Self {
-        GFElement(MODULUS - (self.0))
+        RingElement(self.0.wrapping_neg())
     }
 }
 
-impl Sub for GFElement {
+impl Sub for RingElement {
     type Output = Self;
-    fn sub(self, other: GFElement) -> Self {
+    fn sub(self, other: RingElement) -> Self {
         self + (-other)
     }
 }
+
+/// Gilboa's multiplication: for the i-th bit of the receiver's b the sender
+/// transfers x_i and x_i + a * 2^i, so the sums of both outputs differ by a * b.
+pub fn gilboa_deltas(a: RingElement) -> Vec<u64> {
+    (0..K).map(|i| a.0 << i).collect()
+}
+
+/// The choice bits of the receiver, the bits of b starting with the least significant one.
+pub fn gilboa_choices(b: RingElement) -> BitVec {
+    (0..K).map(|i| (b.0 >> i) & 1 == 1).collect()
+}
+
+/// The share of a * b of one party, the sender negates its sum.
+pub fn gilboa_share(outputs: &[u64], sender: bool) -> RingElement {
+    let sum = outputs
+        .iter()
+        .fold(RingElement(0), |acc, x| acc + RingElement(*x));
+    if sender {
+        -sum
+    } else {
+        sum
+    }
+}
@@ -1,7 +1,3 @@
-extern crate ot;
-extern crate rand;
-extern crate tungstenite;
-
 use ot::common::digest::sha3::SHA3_256;
 use ot::common::util::{generate_random_choices, generate_random_string_pairs};
 use ot::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
@@ -12,8 +8,8 @@ use ot::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
 use rand::{ChaChaRng, FromEntropy};
 use std::net::TcpListener;
 use std::thread::spawn;
-use tungstenite::handshake::server::Request;
-use tungstenite::server::accept_hdr;
+use tungstenite::accept_hdr;
+use tungstenite::handshake::server::{Request, Response};
 
 use std::time::Instant;
 
@@ -24,17 +20,19 @@ fn main() {
 
     let server = TcpListener::bind("127.0.0.1:3012").unwrap();
     for stream in server.incoming() {
-        let callback = |req: &Request| {
+        let callback = |req: &Request, mut response: Response| {
             println!("Received a new ws handshake");
-            println!("The request's path is: {}", req.path);
+            println!("The request's path is: {}", req.uri().path());
             println!("The request's headers are:");
-            for &(ref header, _ /* value */) in req.headers.iter() {
+            for header in req.headers().keys() {
                 println!("* {}", header);
             }
 
             // TODO: for better example decide based on the subprotocol if you send or receive
-            let extra_headers = vec![(String::from("Sec-WebSocket-Protocol"), String::from("ot"))];
-            Ok(Some(extra_headers))
+            response
+                .headers_mut()
+                .insert("Sec-WebSocket-Protocol", "ot".parse().unwrap());
+            Ok(response)
         };
         spawn(move || {
             let values = generate_random_string_pairs(VALUE_LENGTH, VALUE_COUNT);
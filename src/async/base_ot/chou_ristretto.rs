@@ -0,0 +1,318 @@
+//! chou and orlandis 1-out-of-n OT [https://eprint.iacr.org/2015/267.pdf] in the Ristretto group,
+//! see `sync::base_ot::chou_ristretto` for details. Both versions are compatible on the wire.
+
+use super::{BaseOTReceiver, BaseOTSender};
+use crate::common::digest::Digest;
+use crate::common::util::{decompress_ristretto, pack, random_scalar, unpack};
+use crate::errors::*;
+use crate::r#async::communication::{BinaryReceive, BinarySend, GetConn};
+use crate::r#async::crypto::{SymmetricDecryptor, SymmetricEncryptor};
+use crate::r#async::{BoxFuture, MaybeSend};
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_TABLE;
+use curve25519_dalek::ristretto::*;
+use curve25519_dalek::scalar::*;
+use futures::future::try_join_all;
+use generic_array::{ArrayLength, GenericArray};
+use rand::{CryptoRng, RngCore};
+
+async fn send_point<C: BinarySend>(conn: &mut C, point: RistrettoPoint) -> Result<()> {
+    conn.send(point.compress().as_bytes())
+        .await
+        .chain_err(|| "Error while sending point")
+}
+
+async fn receive_point<C: BinaryReceive>(conn: &mut C) -> Result<RistrettoPoint> {
+    let buf = conn
+        .receive()
+        .await
+        .chain_err(|| "Error while receiving point")?;
+    decompress_ristretto(&buf)
+}
+
+#[derive(Clone)]
+pub struct ChouOrlandiRistrettoOTSender<C, D, L, S>
+where
+    C: BinarySend + BinaryReceive,
+    D: Digest<OutputSize = L> + Clone + MaybeSend,
+    L: ArrayLength<u8>,
+    S: SymmetricEncryptor<L>,
+{
+    conn: C,
+    hasher: D,
+    encryptor: S,
+    y: Scalar,
+    t: RistrettoPoint,
+}
+
+impl<
+        C: BinarySend + BinaryReceive,
+        D: Digest<OutputSize = L> + Clone + MaybeSend,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > GetConn<C> for ChouOrlandiRistrettoOTSender<C, D, L, S>
+{
+    fn get_conn(self) -> C {
+        self.conn
+    }
+}
+
+impl<
+        C: BinarySend + BinaryReceive,
+        D: Digest<OutputSize = L> + Clone + MaybeSend,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > ChouOrlandiRistrettoOTSender<C, D, L, S>
+{
+    pub async fn new<R>(mut conn: C, mut hasher: D, encryptor: S, mut rng: R) -> Result<Self>
+    where
+        R: RngCore + CryptoRng + MaybeSend,
+    {
+        let y = random_scalar(&mut rng);
+        let s = &y * RISTRETTO_BASEPOINT_TABLE;
+        send_point(&mut conn, s).await?;
+        hasher.input(s.compress().as_bytes());
+        Ok(ChouOrlandiRistrettoOTSender {
+            conn: conn,
+            hasher: hasher,
+            encryptor: encryptor,
+            y: y,
+            t: y * s,
+        })
+    }
+
+    pub async fn compute_keys(&mut self, n: u64) -> Result<Vec<GenericArray<u8, L>>> {
+        let r = receive_point(&mut self.conn).await?;
+        Ok(self.keys_for_point(r, n))
+    }
+
+    /// Computes the keys for many transfers at once, all points of the receiver
+    /// arrive in a single message.
+    pub async fn compute_keys_many(
+        &mut self,
+        ns: Vec<u64>,
+    ) -> Result<Vec<Vec<GenericArray<u8, L>>>> {
+        let buf = self
+            .conn
+            .receive()
+            .await
+            .chain_err(|| "Error while receiving points")?;
+        if buf.len() != 32 * ns.len() {
+            return Err(ErrorKind::PointError.into());
+        }
+        buf.chunks(32)
+            .zip(ns)
+            .map(|(p, n)| Ok(self.keys_for_point(decompress_ristretto(p)?, n)))
+            .collect()
+    }
+
+    fn keys_for_point(&self, r: RistrettoPoint, n: u64) -> Vec<GenericArray<u8, L>> {
+        let mut hasher = self.hasher.clone();
+        hasher.input(r.compress().as_bytes());
+        let yr = self.y * r;
+        (0..n)
+            .map(|j| {
+                // hash p = yR - jT, this will reduce to xS if c == j
+                let p = yr - Scalar::from(j) * self.t;
+                let mut hasher = hasher.clone();
+                hasher.input(p.compress().as_bytes());
+                hasher.result()
+            })
+            .collect()
+    }
+}
+
+impl<
+        C: BinarySend + BinaryReceive,
+        D: Digest<OutputSize = L> + Clone + MaybeSend,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > BaseOTSender for ChouOrlandiRistrettoOTSender<C, D, L, S>
+{
+    fn send<'a>(&'a mut self, values: Vec<Vec<u8>>) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let keys = self
+                .compute_keys(values.len() as u64)
+                .await
+                .chain_err(|| "Error computing keys")?;
+            let encryptions: Vec<_> = keys
+                .into_iter()
+                .zip(values)
+                .map(|(key, value)| self.encryptor.encrypt(&key, value))
+                .collect();
+            let ciphertexts = try_join_all(encryptions).await?;
+            // the ciphertexts are sent one by one, like the sync version does
+            for ciphertext in ciphertexts {
+                self.conn
+                    .send(&ciphertext)
+                    .await
+                    .chain_err(|| "Error sending encrypted data")?;
+            }
+            Ok(())
+        })
+    }
+
+    /// All transfers share our point S, the receiver sends all of its points in one message
+    /// and we answer with all ciphertexts in one message.
+    fn send_many<'a>(&'a mut self, values: Vec<Vec<Vec<u8>>>) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let ns = values.iter().map(|v| v.len() as u64).collect();
+            let keys = self
+                .compute_keys_many(ns)
+                .await
+                .chain_err(|| "Error computing keys")?;
+            let encryptions: Vec<_> = keys
+                .into_iter()
+                .zip(values)
+                .flat_map(|(keys, values)| keys.into_iter().zip(values))
+                .map(|(key, value)| self.encryptor.encrypt(&key, value))
+                .collect();
+            let ciphertexts = try_join_all(encryptions).await?;
+            self.conn
+                .send(&pack(&ciphertexts))
+                .await
+                .chain_err(|| "Error sending encrypted data")
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct ChouOrlandiRistrettoOTReceiver<C, R, D, L, S>
+where
+    C: BinarySend + BinaryReceive,
+    R: RngCore + CryptoRng + MaybeSend,
+    D: Digest<OutputSize = L> + Clone + MaybeSend,
+    L: ArrayLength<u8>,
+    S: SymmetricDecryptor<L>,
+{
+    conn: C,
+    hasher: D,
+    decryptor: S,
+    rng: R,
+    s: RistrettoPoint,
+}
+
+impl<
+        C: BinarySend + BinaryReceive,
+        R: RngCore + CryptoRng + MaybeSend,
+        D: Digest<OutputSize = L> + Clone + MaybeSend,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > GetConn<C> for ChouOrlandiRistrettoOTReceiver<C, R, D, L, S>
+{
+    fn get_conn(self) -> C {
+        self.conn
+    }
+}
+
+impl<
+        C: BinarySend + BinaryReceive,
+        R: RngCore + CryptoRng + MaybeSend,
+        D: Digest<OutputSize = L> + Clone + MaybeSend,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > ChouOrlandiRistrettoOTReceiver<C, R, D, L, S>
+{
+    pub async fn new(mut conn: C, mut hasher: D, decryptor: S, rng: R) -> Result<Self> {
+        let s = receive_point(&mut conn).await?;
+        hasher.input(s.compress().as_bytes());
+        Ok(ChouOrlandiRistrettoOTReceiver {
+            conn: conn,
+            hasher: hasher,
+            decryptor: decryptor,
+            rng: rng,
+            s: s,
+        })
+    }
+
+    pub async fn compute_key(&mut self, c: u64) -> Result<GenericArray<u8, L>> {
+        let (r, key) = self.point_and_key(c);
+        send_point(&mut self.conn, r).await?;
+        Ok(key)
+    }
+
+    /// Computes the keys for many transfers at once, all our points are sent in a single message.
+    pub async fn compute_keys_many(&mut self, cs: Vec<u64>) -> Result<Vec<GenericArray<u8, L>>> {
+        let mut points = Vec::with_capacity(32 * cs.len());
+        let mut keys = Vec::with_capacity(cs.len());
+        for c in cs {
+            let (r, key) = self.point_and_key(c);
+            points.extend_from_slice(r.compress().as_bytes());
+            keys.push(key);
+        }
+        self.conn
+            .send(&points)
+            .await
+            .chain_err(|| "Error while sending points")?;
+        Ok(keys)
+    }
+
+    /// Returns the point R = cS + xG to send for choice c and the resulting key H(S, R, xS).
+    fn point_and_key(&mut self, c: u64) -> (RistrettoPoint, GenericArray<u8, L>) {
+        let mut hasher = self.hasher.clone();
+        let x = random_scalar(&mut self.rng);
+        let r = Scalar::from(c) * self.s + &x * RISTRETTO_BASEPOINT_TABLE;
+        hasher.input(r.compress().as_bytes());
+        hasher.input((x * self.s).compress().as_bytes());
+        (r, hasher.result())
+    }
+}
+
+impl<
+        C: BinarySend + BinaryReceive,
+        R: RngCore + CryptoRng + MaybeSend,
+        D: Digest<OutputSize = L> + Clone + MaybeSend,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > BaseOTReceiver for ChouOrlandiRistrettoOTReceiver<C, R, D, L, S>
+{
+    fn receive<'a>(&'a mut self, c: usize, n: usize) -> BoxFuture<'a, Vec<u8>> {
+        Box::pin(async move {
+            if c >= n {
+                bail!("index out of bounds");
+            }
+            let key = self
+                .compute_key(c as u64)
+                .await
+                .chain_err(|| "Error computing keys")?;
+            let mut vals = Vec::with_capacity(n);
+            for _ in 0..n {
+                let buf = self
+                    .conn
+                    .receive()
+                    .await
+                    .chain_err(|| "Error receiving encrypted data")?;
+                vals.push(buf);
+            }
+            self.decryptor.decrypt(&key, vals.swap_remove(c)).await
+        })
+    }
+
+    /// See `ChouOrlandiRistrettoOTSender::send_many`.
+    fn receive_many<'a>(&'a mut self, cs: Vec<usize>, n: usize) -> BoxFuture<'a, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            if n == 0 || cs.iter().any(|&c| c >= n) {
+                bail!("index out of bounds");
+            }
+            let keys = self
+                .compute_keys_many(cs.iter().map(|&c| c as u64).collect())
+                .await
+                .chain_err(|| "Error computing keys")?;
+            let buf = self
+                .conn
+                .receive()
+                .await
+                .chain_err(|| "Error receiving encrypted data")?;
+            let ciphertexts = unpack(&buf)?;
+            if ciphertexts.len() != n * cs.len() {
+                bail!("Received wrong number of ciphertexts");
+            }
+            let decryptions: Vec<_> = keys
+                .iter()
+                .zip(&cs)
+                .zip(ciphertexts.chunks(n))
+                .map(|((key, &c), chunk)| self.decryptor.decrypt(key, chunk[c].clone()))
+                .collect();
+            try_join_all(decryptions).await
+        })
+    }
+}
@@ -4,17 +4,18 @@
 //! As these rely on expensive public-key-cryptography one is advised
 //! to compose these with extended OT.
 pub mod chou;
+pub mod chou_ristretto;
 
-use errors::*;
-use futures_core::Future;
+use crate::r#async::{BoxFuture, MaybeSend};
 
-pub trait BaseOTSender<'a> {
-    // sadly, impl Trait is not available in trait methods, so we have to use a Box here
-    fn send(self, values: Vec<Vec<u8>>) -> Box<Future<Item = Self, Error = Error> + 'a>;
+pub trait BaseOTSender: MaybeSend {
+    fn send<'a>(&'a mut self, values: Vec<Vec<u8>>) -> BoxFuture<'a, ()>;
+    /// Performs one transfer for every entry of `values` with as few messages as possible.
+    fn send_many<'a>(&'a mut self, values: Vec<Vec<Vec<u8>>>) -> BoxFuture<'a, ()>;
 }
 
-pub trait BaseOTReceiver<'a> {
-    // sadly, impl Trait is not available in trait methods, so we have to use a Box here
-    fn receive(self, c: usize, n: usize)
-        -> Box<Future<Item = (Vec<u8>, Self), Error = Error> + 'a>;
+pub trait BaseOTReceiver: MaybeSend {
+    fn receive<'a>(&'a mut self, c: usize, n: usize) -> BoxFuture<'a, Vec<u8>>;
+    /// Performs one 1-out-of-n transfer for every index, see `BaseOTSender::send_many`.
+    fn receive_many<'a>(&'a mut self, cs: Vec<usize>, n: usize) -> BoxFuture<'a, Vec<Vec<u8>>>;
 }
@@ -1,24 +1,28 @@
 //! This module provides utility traits and wrappers
 //! for basic communication needed by our protocols.
-//! As the async part of this library only supports web assembly
-//! at the moment, a wrapper around the browser implementation
-//! of websockets is given.
+//! In the browser a wrapper around its implementation of websockets is given,
+//! on native hosts tokio's `TcpStream` (with the framing of `sync::communication::tcp`)
+//! and `tokio-tungstenite` websockets can be used.
 
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub mod tcp;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub mod tokio_websockets;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
 pub mod websockets;
 // TODO consider rtc?!
 
-use errors::*;
-use futures_core::Future;
-use std::sync::{Arc, Mutex};
+use crate::r#async::{BoxFuture, MaybeSend};
+use std::vec::Vec;
 
-pub trait BinarySend {
-    fn send(&self, data: Vec<u8>) -> Box<Future<Item = (Arc<Mutex<Self>>), Error = Error>>;
+pub trait BinarySend: MaybeSend {
+    fn send<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, ()>;
 }
 
-pub trait BinaryReceive {
-    fn receive(&self) -> Box<Future<Item = (Arc<Mutex<Self>>, Vec<u8>), Error = Error>>;
+pub trait BinaryReceive: MaybeSend {
+    fn receive<'a>(&'a mut self) -> BoxFuture<'a, Vec<u8>>;
 }
 
 pub trait GetConn<C: BinarySend + BinaryReceive> {
-    fn get_conn(self) -> Arc<Mutex<C>>;
+    fn get_conn(self) -> C;
 }
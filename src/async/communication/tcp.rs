@@ -0,0 +1,86 @@
+//! The protocol of `sync::communication::tcp` for tokio's `TcpStream`, both are compatible on the wire.
+use super::{BinaryReceive, BinarySend};
+use crate::errors::*;
+use crate::r#async::BoxFuture;
+use crate::sync::communication::tcp::DEFAULT_MAX_FRAME_SIZE;
+use byte_tools::{read_u64_be, write_u64_be};
+use std::cmp;
+use std::vec::Vec;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+impl BinarySend for TcpStream {
+    fn send<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let mut bytes = [0; 8];
+            write_u64_be(&mut bytes, data.len() as u64);
+            self.write_all(&bytes).await?;
+            self.write_all(data).await?;
+            self.flush().await?;
+            Ok(())
+        })
+    }
+}
+
+impl BinaryReceive for TcpStream {
+    fn receive<'a>(&'a mut self) -> BoxFuture<'a, Vec<u8>> {
+        Box::pin(async move {
+            let mut bytes = [0; 8];
+            self.read_exact(&mut bytes).await?;
+            let len = read_u64_be(&bytes);
+            if len > DEFAULT_MAX_FRAME_SIZE as u64 {
+                return Err(ErrorKind::FrameTooLarge(len, DEFAULT_MAX_FRAME_SIZE).into());
+            }
+            // the buffer grows with the received data instead of trusting the announced length
+            let len = len as usize;
+            let mut v = Vec::with_capacity(cmp::min(len, 1 << 16));
+            (&mut *self).take(len as u64).read_to_end(&mut v).await?;
+            if v.len() != len {
+                bail!("Connection closed in the middle of a frame");
+            }
+            Ok(v)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::digest::sha3::SHA3_256;
+    use crate::r#async::base_ot::chou::ChouOrlandiOTSender;
+    use crate::r#async::base_ot::BaseOTSender;
+    use crate::r#async::crypto::aes_native::AesCryptoProvider;
+    use crate::sync::base_ot::chou::ChouOrlandiOTReceiver;
+    use crate::sync::base_ot::BaseOTReceiver;
+    use crate::sync::crypto::aes;
+    use rand::{ChaChaRng, FromEntropy};
+    use std::net;
+    use std::thread;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn compatible_with_sync_tcp() {
+        let values = vec![b"zero".to_vec(), b"one".to_vec(), b"two".to_vec()];
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut ot = ChouOrlandiOTReceiver::new(
+                net::TcpStream::connect(addr).unwrap(),
+                SHA3_256::default(),
+                aes::AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            )
+            .unwrap();
+            ot.receive(2, 3).unwrap()
+        });
+        let mut ot = ChouOrlandiOTSender::new(
+            listener.accept().await.unwrap().0,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            ChaChaRng::from_entropy(),
+        )
+        .await
+        .unwrap();
+        ot.send(values).await.unwrap();
+        assert_eq!(client.join().unwrap(), b"two".to_vec());
+    }
+}
@@ -0,0 +1,33 @@
+//! Websockets of `tokio-tungstenite`, compatible with `sync::communication::websockets`
+//! and the websockets of the browser.
+use super::{BinaryReceive, BinarySend};
+use crate::errors::*;
+use crate::r#async::BoxFuture;
+use futures::{SinkExt, StreamExt};
+use std::vec::Vec;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> BinarySend for WebSocketStream<S> {
+    fn send<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            SinkExt::send(self, Message::binary(data.to_owned()))
+                .await
+                .chain_err(|| "Could not send data over socket")
+        })
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> BinaryReceive for WebSocketStream<S> {
+    fn receive<'a>(&'a mut self) -> BoxFuture<'a, Vec<u8>> {
+        Box::pin(async move {
+            match StreamExt::next(self).await {
+                Some(Ok(Message::Binary(v))) => Ok(v),
+                Some(Ok(_)) => Err(ErrorKind::CommunicationError.into()),
+                Some(Err(e)) => Err(Error::with_chain(e, "Couldn't read from web socket")),
+                None => bail!("Web socket was closed"),
+            }
+        })
+    }
+}
@@ -1,191 +1,159 @@
 use super::{BinaryReceive, BinarySend};
-use errors::*;
-use futures_core::task::{Context, Waker};
-use futures_core::{Async, Future};
+use crate::errors::*;
+use crate::r#async::{js_error, BoxFuture};
+use futures::future::{poll_fn, ready};
+use js_sys::{ArrayBuffer, Uint8Array};
 use std::collections::VecDeque;
-use std::sync::{Arc, Mutex, Weak};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
 use std::vec::Vec;
-use stdweb::traits::*;
-use stdweb::web::event::{SocketCloseEvent, SocketMessageEvent, SocketOpenEvent};
-use stdweb::web::SocketBinaryType;
-use stdweb::web::TypedArray;
-use stdweb::web::WebSocket;
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::JsCast;
+use web_sys::{BinaryType, CloseEvent, Event, MessageEvent, WebSocket};
 
-/// This is a wrapper around stdweb's websockets so we can use them
-/// with futures (websockets in the browser are callback-based and do not implement
-/// promises from the get-go)
-pub struct WasmWebSocket {
-    me: Weak<Mutex<WasmWebSocket>>,
-    ws: WebSocket,
+/// State shared with the event listeners of the websocket.
+struct Shared {
     msg_queue: Result<VecDeque<Vec<u8>>>,
     // TODO: only one waker? Will this break anything?
     waker: Option<Waker>,
     open: bool,
 }
 
+impl Shared {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// This is a wrapper around the websockets of the browser so we can use them
+/// with futures (websockets in the browser are callback-based and do not implement
+/// promises from the get-go)
+pub struct WasmWebSocket {
+    ws: WebSocket,
+    shared: Arc<Mutex<Shared>>,
+    // the browser calls these as long as the socket lives
+    _on_open: Closure<dyn FnMut(Event)>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+}
+
 impl WasmWebSocket {
-    fn new(ws: WebSocket) -> Arc<Mutex<Self>> {
-        let me: Weak<Mutex<WasmWebSocket>> = Weak::new();
-        let s = Arc::new(Mutex::new(WasmWebSocket {
-            me: me,
-            ws: ws,
+    fn new(ws: WebSocket) -> Self {
+        let s = Arc::new(Mutex::new(Shared {
             msg_queue: Ok(VecDeque::new()),
             waker: None,
             open: false,
         }));
-        {
-            let mut me = s.lock().unwrap();
-            me.me = Arc::downgrade(&s);
-
-            me.ws
-                .add_event_listener(enclose! { (s) move |_: SocketOpenEvent| {
-                    if let Ok(ref mut me) = s.lock() {
-                        me.open = true;
-                        //me.wakers.iter().for_each(|waker| waker.wake());
-                        if let Some(ref waker) = me.waker {
-                            waker.wake();
-                        }
-                    }
-                }});
-            me.ws
-                .add_event_listener(enclose! { (s) move |event: SocketMessageEvent| {
-                    let data = event.data();
-                    if let Ok(ref mut me) = s.lock() {
-                        if let Some(arr) = data.into_array_buffer() {
-                            let buf: TypedArray<u8> = TypedArray::from(arr);
-                            if let Ok(ref mut msg_queue) = me.msg_queue {
-                                msg_queue.push_back(buf.to_vec());
-                            }
-                        //me.wakers.iter().for_each(|waker| waker.wake());
-                        } else {
-                            me.msg_queue = Err("Did not receive binary data!".into());
-                        }
-                        if let Some(ref waker) = me.waker {
-                            waker.wake();
-                        }
-                    }
-                }});
-            me.ws
-                .add_event_listener(enclose! { (s) move |event: SocketCloseEvent| {
-                    if let Ok(ref mut me) = s.lock() {
-                        me.msg_queue = Err(event.reason().into());
-                        //me.wakers.iter().for_each(|waker| waker.wake());
-                        if let Some(ref waker) = me.waker {
-                            waker.wake();
-                        }
+        let on_open = Closure::new(enclose! { (s) move |_: Event| {
+            if let Ok(ref mut me) = s.lock() {
+                me.open = true;
+                me.wake();
+            }
+        }});
+        let on_message = Closure::new(enclose! { (s) move |event: MessageEvent| {
+            if let Ok(ref mut me) = s.lock() {
+                if let Ok(buf) = event.data().dyn_into::<ArrayBuffer>() {
+                    if let Ok(ref mut msg_queue) = me.msg_queue {
+                        msg_queue.push_back(Uint8Array::new(&buf).to_vec());
                     }
-                }});
+                } else {
+                    me.msg_queue = Err("Did not receive binary data!".into());
+                }
+                me.wake();
+            }
+        }});
+        let on_close = Closure::new(enclose! { (s) move |event: CloseEvent| {
+            if let Ok(ref mut me) = s.lock() {
+                me.msg_queue = Err(event.reason().into());
+                me.wake();
+            }
+        }});
+        ws.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        ws.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        ws.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+        WasmWebSocket {
+            ws,
+            shared: s,
+            _on_open: on_open,
+            _on_message: on_message,
+            _on_close: on_close,
         }
-        s
     }
 
-    pub fn open(socket: WebSocket) -> WasmWebSocketOpen {
-        socket.set_binary_type(SocketBinaryType::ArrayBuffer);
+    /// Resolves as soon as the socket is open.
+    pub async fn open(socket: WebSocket) -> Result<Self> {
+        socket.set_binary_type(BinaryType::Arraybuffer);
         let ws = Self::new(socket);
-        WasmWebSocketOpen { ws: ws }
+        poll_fn(|cx| ws.poll_open(cx)).await?;
+        Ok(ws)
     }
-}
-
-impl BinaryReceive for WasmWebSocket {
-    fn receive(&self) -> Box<Future<Item = (Arc<Mutex<WasmWebSocket>>, Vec<u8>), Error = Error>> {
-        Box::new(WasmWebSocketRead {
-            ws: self.me.upgrade().unwrap().clone(),
-        })
-    }
-}
 
-impl BinarySend for WasmWebSocket {
-    // TODO: should the values be owned? Maybe use bytes crate
-    // just sending references is not possible because of the way futures work
-    fn send(&self, buffer: Vec<u8>) -> Box<Future<Item = (Arc<Mutex<Self>>), Error = Error>> {
-        Box::new(WasmWebSocketWrite {
-            ws: self.me.upgrade().unwrap().clone(),
-            buffer: buffer,
-        })
-    }
-}
-
-impl Drop for WasmWebSocket {
-    fn drop(&mut self) {
-        // TODO what if ws is already closed? Does this blow up?
-        // TODO doesnt get called in browser example
-        self.ws.close();
-    }
-}
-
-pub struct WasmWebSocketOpen {
-    ws: Arc<Mutex<WasmWebSocket>>,
-}
-
-impl Future for WasmWebSocketOpen {
-    type Item = Arc<Mutex<WasmWebSocket>>;
-    type Error = Error;
-    fn poll(&mut self, cx: &mut Context) -> Result<Async<Self::Item>> {
-        if let Ok(ref mut ws) = self.ws.lock() {
-            if ws.open {
-                ws.waker = None;
-                return Ok(Async::Ready(self.ws.clone()));
-            } else {
-                ws.waker = Some(cx.waker().clone());
-                return Ok(Async::Pending);
-            }
+    fn poll_open(&self, cx: &mut Context) -> Poll<Result<()>> {
+        let mut shared = match self.shared.lock() {
+            Ok(shared) => shared,
+            Err(_) => return Poll::Ready(Err("Internal error, couldn't access mutex!".into())),
+        };
+        if shared.open {
+            Poll::Ready(Ok(()))
+        } else if shared.msg_queue.is_err() {
+            Poll::Ready(Err("Couldn't open web socket".into()))
         } else {
-            bail!("Internal error, couldn't access mutex!")
+            shared.waker = Some(cx.waker().clone());
+            Poll::Pending
         }
     }
-}
 
-pub struct WasmWebSocketRead {
-    ws: Arc<Mutex<WasmWebSocket>>,
-}
-
-impl Future for WasmWebSocketRead {
-    type Item = (Arc<Mutex<WasmWebSocket>>, Vec<u8>);
-    type Error = Error;
-
-    fn poll(&mut self, cx: &mut Context) -> Result<Async<Self::Item>> {
-        if let Ok(ref mut ws) = self.ws.lock() {
-            // we can't return val directly because of the borrow of the msg_queue
-            // it has to go out of scope first
-
-            let value = match ws.msg_queue {
-                Ok(ref mut msg_queue) => msg_queue.pop_front(),
-                // return returned error
-                Err(_) => return Err("Couldn't read from web socket".into()),
-            };
-            if let Some(buf) = value {
-                ws.waker = None;
-                return Ok(Async::Ready((self.ws.clone(), buf)));
-            } else {
-                ws.waker = Some(cx.waker().clone());
-                return Ok(Async::Pending);
+    fn poll_receive(&self, cx: &mut Context) -> Poll<Result<Vec<u8>>> {
+        let mut shared = match self.shared.lock() {
+            Ok(shared) => shared,
+            Err(_) => {
+                return Poll::Ready(Err(
+                    "Internal error, couldn't access websocket value. This should not happen"
+                        .into(),
+                ))
             }
+        };
+        // we can't return val directly because of the borrow of the msg_queue
+        // it has to go out of scope first
+        let value = match shared.msg_queue {
+            Ok(ref mut msg_queue) => msg_queue.pop_front(),
+            Err(_) => return Poll::Ready(Err("Couldn't read from web socket".into())),
+        };
+        if let Some(buf) = value {
+            Poll::Ready(Ok(buf))
         } else {
-            bail!("Internal error, couldn't access websocket value. This should not happen")
+            shared.waker = Some(cx.waker().clone());
+            Poll::Pending
         }
     }
 }
 
-// As webstd websocket returns instantly after send,
-// this is only a wrapper so the interface is consistent for read and write
-pub struct WasmWebSocketWrite {
-    ws: Arc<Mutex<WasmWebSocket>>,
-    buffer: Vec<u8>,
+impl BinaryReceive for WasmWebSocket {
+    fn receive<'a>(&'a mut self) -> BoxFuture<'a, Vec<u8>> {
+        Box::pin(poll_fn(move |cx| self.poll_receive(cx)))
+    }
 }
 
-impl Future for WasmWebSocketWrite {
-    type Item = Arc<Mutex<WasmWebSocket>>;
-    type Error = Error;
+impl BinarySend for WasmWebSocket {
+    // As the browser's websocket returns instantly after send,
+    // the future is only here so the interface is consistent for read and write
+    fn send<'a>(&'a mut self, data: &'a [u8]) -> BoxFuture<'a, ()> {
+        let result = self
+            .ws
+            .send_with_u8_array(data)
+            .map_err(|e| js_error(e, "Could not send data over socket"));
+        Box::pin(ready(result))
+    }
+}
 
-    fn poll(&mut self, _cx: &mut Context) -> Result<Async<Self::Item>> {
-        if let Ok(ref mut ws) = self.ws.lock() {
-            let arr = TypedArray::from(self.buffer.as_slice());
-            ws.ws
-                .send_array_buffer(&arr.buffer())
-                .map(|_| (Async::Ready(self.ws.clone())))
-                .map_err(|e| Error::with_chain(e, "Could not send data over socket"))
-        } else {
-            bail!("Internal error, couldn't access websocket value. This should not happen")
-        }
+impl Drop for WasmWebSocket {
+    fn drop(&mut self) {
+        self.ws.set_onopen(None);
+        self.ws.set_onmessage(None);
+        self.ws.set_onclose(None);
+        // closing an already closed socket does nothing
+        let _ = self.ws.close();
     }
 }
@@ -1,15 +1,11 @@
 use super::{SymmetricDecryptor, SymmetricEncryptor};
-use errors::*;
-//use futures::prelude::*;
-use futures_core::IntoFuture;
-use futures_util::FutureExt;
+use crate::errors::*;
+use crate::r#async::{js_error, BoxFuture};
 use generic_array::{typenum::U32, GenericArray};
-use stdweb::unstable::TryInto;
-use stdweb::web::{ArrayBuffer, TypedArray};
-use stdweb::PromiseFuture;
-use stdweb::*;
-//use futures::prelude::Future;
-use futures_core::Future;
+use js_sys::{Array, ArrayBuffer, Promise, Uint8Array};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AesGcmParams, CryptoKey, SubtleCrypto};
 
 // TODO: add  this to an preloaded js file
 // window.crypto = window.crypto || window.msCrypto; //for IE11
@@ -19,90 +15,48 @@ use futures_core::Future;
 #[derive(Default)]
 pub struct AesCryptoProvider();
 
+fn subtle() -> Result<SubtleCrypto> {
+    let window = web_sys::window().ok_or("No window, WebCrypto is not available")?;
+    let crypto = window
+        .crypto()
+        .map_err(|e| js_error(e, "WebCrypto is not available"))?;
+    Ok(crypto.subtle())
+}
+
+/// Imports the key and runs AES-GCM with the static 0 nonce in the given direction.
+async fn aes_gcm(key: Vec<u8>, data: Vec<u8>, encrypt: bool) -> Result<Vec<u8>> {
+    let crypto = subtle()?;
+    let nonce: [u8; 12] = Default::default();
+    let algorithm = AesGcmParams::new("AES-GCM", &Uint8Array::from(&nonce[..]));
+    algorithm.set_tag_length(128);
+    let usage = if encrypt { "encrypt" } else { "decrypt" };
+    let usages = Array::of1(&usage.into());
+    let key = crypto
+        .import_key_with_str("raw", &Uint8Array::from(&key[..]), "AES-GCM", false, &usages)
+        .map_err(|e| js_error(e, "Couldn't import aes-gcm key"))?;
+    let key: CryptoKey = JsFuture::from(key)
+        .await
+        .map_err(|e| js_error(e, "Couldn't import aes-gcm key"))?
+        .unchecked_into();
+    let result: std::result::Result<Promise, _> = if encrypt {
+        crypto.encrypt_with_object_and_u8_array(&algorithm, &key, &data)
+    } else {
+        crypto.decrypt_with_object_and_u8_array(&algorithm, &key, &data)
+    };
+    let buf = async { JsFuture::from(result?).await }
+        .await
+        .map_err(|e| js_error(e, &format!("Couldn't {} with aes-gcm", usage)))?;
+    Ok(Uint8Array::new(&buf.unchecked_into::<ArrayBuffer>()).to_vec())
+}
+
 impl SymmetricEncryptor<U32> for AesCryptoProvider {
-    fn encrypt(
-        &mut self,
-        key: &GenericArray<u8, U32>,
-        data: Vec<u8>,
-    ) -> Box<Future<Item = Vec<u8>, Error = Error>> {
-        let arr_key = TypedArray::from(key.as_slice()).buffer();
-        let arr_data = TypedArray::from(data.as_slice()).buffer();
-        let nonce: [u8; 12] = Default::default();
-        let tarr: TypedArray<u8> = TypedArray::from(&nonce[..]);
-        let arr_nonce = tarr.buffer();
-        let future: Result<PromiseFuture<ArrayBuffer>> = js! {
-            var algorithm = {
-                "name": "AES-GCM",
-                "iv": @{arr_nonce},
-                "tagLength": 128,
-            };
-            var crypto = window.crypto.subtle;
-            var result = crypto.importKey(
-                "raw",
-                @{arr_key},
-                algorithm,
-                false,
-                ["encrypt"]
-            ).then(function(key) {
-                return crypto.encrypt(algorithm, key, @{arr_data});
-            }).catch(function(err) {
-                console.log(err);
-            });
-            return result;
-        }.try_into()
-            .chain_err(|| "encryption failed");
-        Box::new(
-            future
-                .into_future()
-                .and_then(|f| f.map_err(|e| Error::with_chain(e, "Couldn't encrypt with aes-gcm")))
-                .map(|arr| {
-                    let tarr: TypedArray<u8> = TypedArray::from(arr);
-                    tarr.to_vec()
-                }),
-        )
+    fn encrypt(&mut self, key: &GenericArray<u8, U32>, data: Vec<u8>) -> BoxFuture<'static, Vec<u8>> {
+        Box::pin(aes_gcm(key.to_vec(), data, true))
     }
 }
 
 impl SymmetricDecryptor<U32> for AesCryptoProvider {
-    fn decrypt(
-        &mut self,
-        key: &GenericArray<u8, U32>,
-        data: Vec<u8>,
-    ) -> Box<Future<Item = Vec<u8>, Error = Error>> {
-        let arr_key = TypedArray::from(key.as_slice()).buffer();
-        let arr_data = TypedArray::from(data.as_slice()).buffer();
-        let nonce: [u8; 12] = Default::default();
-        let tarr: TypedArray<u8> = TypedArray::from(&nonce[..]);
-        let arr_nonce = tarr.buffer();
-        let future: Result<PromiseFuture<ArrayBuffer>> = js! {
-            var algorithm = {
-                "name": "AES-GCM",
-                "iv": @{arr_nonce},
-                "tagLength": 128,
-            };
-            var crypto = window.crypto.subtle;
-            var result = crypto.importKey(
-                "raw",
-                @{arr_key},
-                algorithm,
-                false,
-                ["decrypt"]
-            ).then(function(key) {
-                return crypto.decrypt(algorithm, key, @{arr_data});
-            }).catch(function(err) {
-                console.error(err);
-            });
-            return result;
-        }.try_into()
-            .chain_err(|| "decryption failed");
-        Box::new(
-            future
-                .into_future()
-                .and_then(|f| f.map_err(|e| Error::with_chain(e, "Couldn't decrypt with aes-gcm")))
-                .map(|arr| {
-                    let tarr: TypedArray<u8> = TypedArray::from(arr);
-                    tarr.to_vec()
-                }),
-        )
+    fn decrypt(&mut self, key: &GenericArray<u8, U32>, data: Vec<u8>) -> BoxFuture<'static, Vec<u8>> {
+        Box::pin(aes_gcm(key.to_vec(), data, false))
     }
 }
@@ -0,0 +1,33 @@
+//! AES-GCM of `sync::crypto::aes` for the asynchronous protocols on native hosts,
+//! it is compatible with `aes_browser`.
+use super::{SymmetricDecryptor, SymmetricEncryptor};
+use crate::r#async::BoxFuture;
+use crate::sync::crypto::aes;
+use crate::sync::crypto::{
+    SymmetricDecryptor as SyncSymmetricDecryptor, SymmetricEncryptor as SyncSymmetricEncryptor,
+};
+use futures::future::ready;
+use generic_array::{typenum::U32, GenericArray};
+
+#[derive(Default)]
+pub struct AesCryptoProvider(aes::AesCryptoProvider);
+
+impl SymmetricEncryptor<U32> for AesCryptoProvider {
+    fn encrypt(
+        &mut self,
+        key: &GenericArray<u8, U32>,
+        data: Vec<u8>,
+    ) -> BoxFuture<'static, Vec<u8>> {
+        Box::pin(ready(self.0.encrypt(key, data)))
+    }
+}
+
+impl SymmetricDecryptor<U32> for AesCryptoProvider {
+    fn decrypt(
+        &mut self,
+        key: &GenericArray<u8, U32>,
+        data: Vec<u8>,
+    ) -> BoxFuture<'static, Vec<u8>> {
+        Box::pin(ready(self.0.decrypt(key, data)))
+    }
+}
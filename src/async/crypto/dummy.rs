@@ -1,9 +1,8 @@
 /// Dummy trait that just xors the repeated key onto the data (Vignere encryption).
 /// Only here to test, should not be used for anything.
 use super::{SymmetricDecryptor, SymmetricEncryptor};
-use errors::*;
-use futures_core::future::ok;
-use futures_core::Future;
+use crate::r#async::BoxFuture;
+use futures::future::ok;
 use generic_array::{ArrayLength, GenericArray};
 
 #[derive(Default)]
@@ -14,11 +13,11 @@ impl<E: ArrayLength<u8>> SymmetricEncryptor<E> for DummyCryptoProvider {
         &mut self,
         key: &GenericArray<u8, E>,
         mut data: Vec<u8>,
-    ) -> Box<Future<Item = Vec<u8>, Error = Error>> {
+    ) -> BoxFuture<'static, Vec<u8>> {
         for i in 0..data.len() {
             data[i] ^= key[i % key.len()];
         }
-        Box::new(ok(data))
+        Box::pin(ok(data))
     }
 }
 
@@ -27,10 +26,10 @@ impl<E: ArrayLength<u8>> SymmetricDecryptor<E> for DummyCryptoProvider {
         &mut self,
         key: &GenericArray<u8, E>,
         mut data: Vec<u8>,
-    ) -> Box<Future<Item = Vec<u8>, Error = Error>> {
+    ) -> BoxFuture<'static, Vec<u8>> {
         for i in 0..data.len() {
             data[i] ^= key[i % key.len()];
         }
-        Box::new(ok(data))
+        Box::pin(ok(data))
     }
 }
@@ -1,31 +1,26 @@
-use errors::*;
-use futures_core::Future;
+use crate::r#async::{BoxFuture, MaybeSend};
 use generic_array::{ArrayLength, GenericArray};
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
 pub mod aes_browser;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub mod aes_native;
 pub mod dummy;
 
 // TODO: is this a good interface? should there maybe be only one trait?
 // TODO: wait for Rust allowing impl Trait in traits, then remove all Boxes (should be worked on right now)
 
-/// Trait for blockciphers to be used in OT
-pub trait SymmetricEncryptor<E>
+/// Trait for blockciphers to be used in OT. The returned future does not borrow
+/// the encryptor, so several encryptions can run at the same time.
+pub trait SymmetricEncryptor<E>: MaybeSend
 where
     E: ArrayLength<u8>,
 {
-    fn encrypt(
-        &mut self,
-        key: &GenericArray<u8, E>,
-        data: Vec<u8>,
-    ) -> Box<Future<Item = Vec<u8>, Error = Error>>;
+    fn encrypt(&mut self, key: &GenericArray<u8, E>, data: Vec<u8>) -> BoxFuture<'static, Vec<u8>>;
 }
 
-pub trait SymmetricDecryptor<E>
+pub trait SymmetricDecryptor<E>: MaybeSend
 where
     E: ArrayLength<u8>,
 {
-    fn decrypt(
-        &mut self,
-        key: &GenericArray<u8, E>,
-        data: Vec<u8>,
-    ) -> Box<Future<Item = Vec<u8>, Error = Error>>;
+    fn decrypt(&mut self, key: &GenericArray<u8, E>, data: Vec<u8>) -> BoxFuture<'static, Vec<u8>>;
 }
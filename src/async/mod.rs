@@ -1,7 +1,42 @@
+//! The protocols of this module are built on `std::future` and can be driven by any executor,
+//! e.g. `wasm_bindgen_futures::spawn_local` in the browser or tokio on native hosts.
+//!
+//! On native hosts all futures are `Send`, so they can be spawned on tokio's multi-threaded runtime.
+//! The ones of the browser can't be, as javascript values never leave their thread,
+//! which is why the `Send` bounds of this module only apply outside of wasm (see `MaybeSend`).
+use crate::errors::*;
+use std::future::Future;
+use std::pin::Pin;
+
 pub mod base_ot;
 pub mod communication;
 pub mod crypto;
 pub mod ot_extension;
 
-// TODO: maybe replace Mutexs with simple get_mut().unwrap() calls as
-// in reality only one mutable reference should be open
+/// The future returned by the traits of this module, boxed as traits can't return `impl Future`.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+/// The future returned by the traits of this module, boxed as traits can't return `impl Future`.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + 'a>>;
+
+/// `Send` on native hosts and implemented by every type in the browser.
+/// Bounds on connections, rngs, hashers and ciphers use this, so the same protocol code
+/// produces `Send` futures where they are possible.
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub trait MaybeSend: Send {}
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+impl<T: Send> MaybeSend for T {}
+/// `Send` on native hosts and implemented by every type in the browser.
+/// Bounds on connections, rngs, hashers and ciphers use this, so the same protocol code
+/// produces `Send` futures where they are possible.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub trait MaybeSend {}
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+impl<T> MaybeSend for T {}
+
+/// Errors of browser APIs are plain javascript values, their debug output becomes part of the message.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub fn js_error(e: wasm_bindgen::JsValue, msg: &str) -> Error {
+    format!("{}: {:?}", msg, e).into()
+}
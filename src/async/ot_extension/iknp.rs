@@ -1,321 +1,588 @@
-use super::{ExtendedOTReceiver, ExtendedOTSender};
-use async::base_ot::{BaseOTReceiver, BaseOTSender};
-use async::communication::{BinaryReceive, BinarySend, GetConn};
+use super::{CorrelatedOTReceiver, CorrelatedOTSender, ExtendedOTReceiver, ExtendedOTSender};
+use crate::common::bitmatrix::BitMatrix;
+use crate::common::chunks::{column_frames, unpack_pairs, ColumnCollector};
+use crate::common::digest::{CrHash, Prg};
+use crate::common::util::{bv_truncate, pack};
+use crate::errors::*;
+use crate::r#async::base_ot::{BaseOTReceiver, BaseOTSender};
+use crate::r#async::communication::{BinaryReceive, BinarySend, GetConn};
+use crate::r#async::{BoxFuture, MaybeSend};
 use bit_vec::BitVec;
-use common::digest::ArbitraryDigest;
-use common::util::{bv_truncate, trunc_hash};
-use errors::*;
-use futures_core::stream;
-use futures_core::Future;
-use futures_util::future::*;
-use futures_util::stream::*;
-use futures_util::FutureExt;
+use byte_tools::{read_u64_be, write_u64_be};
 use rand::{CryptoRng, Rng, RngCore};
-use std::sync::{Arc, Mutex};
+use std::cmp;
+
+/// Number of OTs whose data is sent in a single frame unless configured otherwise,
+/// the wire format is the same as the one of `sync::ot_extension::iknp`.
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 16;
+
+/// Receives the `count` columns of `rows` bits sent in frames of arbitrary size.
+async fn receive_columns<T: BinaryReceive>(
+    conn: &mut T,
+    count: usize,
+    rows: usize,
+) -> Result<Vec<BitVec>> {
+    let mut collector = ColumnCollector::new(count, rows);
+    // no frames are expected for zero rows
+    while !collector.is_complete() {
+        let frame = conn.receive().await?;
+        collector.push(&frame)?;
+    }
+    Ok(collector.into_columns())
+}
+
+/// Receives the ciphertext pairs of `n` OTs, packed into frames of arbitrary many pairs.
+async fn receive_pairs<T: BinaryReceive>(conn: &mut T, n: usize) -> Result<Vec<[Vec<u8>; 2]>> {
+    let mut pairs = Vec::with_capacity(n);
+    while pairs.len() < n {
+        let frame = conn.receive().await?;
+        let received = pairs.len();
+        pairs.extend(unpack_pairs(&frame, n - received)?);
+    }
+    Ok(pairs)
+}
+
+/// Sends the concatenated corrections of `len` bytes each, `chunk_size` of them per frame.
+async fn send_fixed<T: BinarySend>(
+    conn: &mut T,
+    ys: &[u8],
+    len: usize,
+    chunk_size: usize,
+) -> Result<()> {
+    for frame in ys.chunks(cmp::max(len * chunk_size, 1)) {
+        conn.send(frame).await?;
+    }
+    Ok(())
+}
+
+/// Receives `n` corrections of `len` bytes each sent with `send_fixed`, concatenated.
+async fn receive_fixed<T: BinaryReceive>(conn: &mut T, n: usize, len: usize) -> Result<Vec<u8>> {
+    let total = n * len;
+    let mut ys = Vec::with_capacity(total);
+    while ys.len() < total {
+        let frame = conn.receive().await?;
+        if frame.is_empty() || frame.len() % len != 0 || ys.len() + frame.len() > total {
+            bail!("Received corrections have the wrong length");
+        }
+        ys.extend_from_slice(&frame);
+    }
+    Ok(ys)
+}
+
+/// Expands a seed to `bits` pseudorandom bits.
+fn expand<A: Prg>(prg: &A, batch: u64, seed: &[u8], bits: usize) -> BitVec {
+    bv_truncate(&prg.expand(batch, seed, bits.div_ceil(8)), bits)
+}
 
 pub struct IKNPExtendedOTReceiver<T, A>
 where
     T: BinaryReceive + BinarySend,
-    A: ArbitraryDigest + Clone,
+    A: Prg + CrHash + MaybeSend,
 {
-    conn: Arc<Mutex<T>>,
-    arbitrary_hasher: A,
+    conn: T,
+    hasher: A,
     initial_pairs: Vec<(Vec<u8>, Vec<u8>)>,
+    batch: usize,
+    chunk_size: usize,
 }
 
-impl<'a, T: 'a + BinaryReceive + BinarySend, A: 'a + ArbitraryDigest + Clone> GetConn<T>
-    for IKNPExtendedOTReceiver<T, A>
-{
-    fn get_conn(self) -> Arc<Mutex<T>> {
-        return self.conn;
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash + MaybeSend> GetConn<T> for IKNPExtendedOTReceiver<T, A> {
+    fn get_conn(self) -> T {
+        self.conn
     }
 }
 
 /// security parameter: number of bytes to use
-impl<'a, T: 'a + BinaryReceive + BinarySend, A: 'a + ArbitraryDigest + Clone>
-    IKNPExtendedOTReceiver<T, A>
-{
-    pub fn new<S, R>(
-        arbitrary_hasher: A,
-        base_ot_sender: S,
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash + MaybeSend> IKNPExtendedOTReceiver<T, A> {
+    pub async fn new<S, R>(
+        hasher: A,
+        mut base_ot_sender: S,
         mut rng: R,
         security_param: usize,
-    ) -> Box<Future<Item = Self, Error = Error> + 'a>
+    ) -> Result<Self>
     where
-        S: 'a + BaseOTSender<'a> + GetConn<T>,
-        R: 'a + RngCore + CryptoRng,
+        S: BaseOTSender + GetConn<T>,
+        R: RngCore + CryptoRng + MaybeSend,
     {
         let l = security_param * 8;
-        let state = (Some(base_ot_sender), 0);
-        let stream = unfold(state, move |(sender, i): (Option<S>, usize)| {
-            if i < l {
+        let initial_pairs: Vec<(Vec<u8>, Vec<u8>)> = (0..l)
+            .map(|_| {
                 let mut k0: Vec<u8> = Vec::with_capacity(security_param);
                 let mut k1: Vec<u8> = Vec::with_capacity(security_param);
                 for _ in 0..security_param {
                     k0.push(rng.gen());
                     k1.push(rng.gen());
                 }
-                let future = sender
-                    .unwrap()
-                    .send(vec![k0.clone(), k1.clone()])
-                    .map(move |s| {
-                        if i == l - 1 {
-                            (((k0, k1), Some(s)), (None, i + 1))
-                        } else {
-                            (((k0, k1), None), (Some(s), i + 1))
-                        }
-                    });
-                Some(future)
-            } else {
-                None
-            }
-        }).collect()
-            .map(|vals: Vec<((Vec<u8>, Vec<u8>), Option<S>)>| {
-                let (initial_pairs, mut selfs): (
-                    Vec<(Vec<u8>, Vec<u8>)>,
-                    Vec<Option<S>>,
-                ) = vals.into_iter().unzip();
-                IKNPExtendedOTReceiver {
-                    arbitrary_hasher: arbitrary_hasher,
-                    conn: selfs.pop().unwrap().unwrap().get_conn(),
-                    initial_pairs: initial_pairs,
-                }
-            });
-        Box::new(stream)
+                (k0, k1)
+            })
+            .collect();
+        // all seeds are transferred in one batch, see ChouOrlandiOTSender::send_many
+        let values = initial_pairs
+            .iter()
+            .map(|(k0, k1)| vec![k0.clone(), k1.clone()])
+            .collect();
+        base_ot_sender.send_many(values).await?;
+        Ok(IKNPExtendedOTReceiver {
+            hasher: hasher,
+            conn: base_ot_sender.get_conn(),
+            initial_pairs: initial_pairs,
+            batch: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
     }
-}
 
-impl<'a, T: 'a + BinaryReceive + BinarySend, A: 'a + ArbitraryDigest + Clone> ExtendedOTReceiver<'a>
-    for IKNPExtendedOTReceiver<T, A>
-{
-    fn receive(
-        self,
-        choice_bits: BitVec,
-    ) -> Box<Future<Item = (Vec<Vec<u8>>, Self), Error = Error> + 'a> {
+    /// Sets the number of OTs whose columns are sent in one frame, it is rounded up
+    /// to a multiple of 8. The sender may use a different chunk size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = cmp::max(chunk_size, 1);
+    }
+
+    /// Sends the masked columns for the given choices and returns the matrix t
+    /// (with one row per OT) together with the number of this batch.
+    async fn send_matrix(&mut self, choice_bits: &BitVec) -> Result<(BitMatrix, u64)> {
         let output_size = choice_bits.len();
-        let l = self.initial_pairs.len();
-        let t_mat: Vec<(BitVec, BitVec)> = self
+        let batch = self.batch as u64;
+        self.batch += 1;
+        let t_mat: Vec<BitVec> = self
+            .initial_pairs
+            .iter()
+            .map(|(k0, _)| expand(&self.hasher, batch, k0, output_size))
+            .collect();
+        let u_mat: Vec<Vec<u8>> = self
             .initial_pairs
             .iter()
-            .map(|(k0, k1)| {
-                (
-                    trunc_hash(self.arbitrary_hasher.clone(), output_size, k0),
-                    trunc_hash(self.arbitrary_hasher.clone(), output_size, k1),
-                )
+            .zip(&t_mat)
+            .map(|((_, k1), t)| {
+                let gk = expand(&self.hasher, batch, k1, output_size);
+                let u: BitVec = izip!(t, gk, choice_bits)
+                    .map(|(t, k, r)| t ^ k ^ r)
+                    .collect();
+                u.to_bytes()
             })
             .collect();
+        let t_mat =
+            BitMatrix::from_rows(t_mat.iter().map(|t| t.to_bytes()), output_size).transpose();
 
-        let choice_bits = Arc::new(choice_bits);
+        for frame in column_frames(&u_mat, self.chunk_size) {
+            self.conn.send(&frame).await?;
+        }
+        Ok((t_mat, batch))
+    }
+}
 
-        // because the enclose macro does not expect self.*, maybe fix this in the macro
-        let conn = self.conn.clone();
-        let hasher = self.arbitrary_hasher.clone();
-        let fut = iter_ok(t_mat)
-            .and_then(enclose!{ (conn, choice_bits) move |(t, k1)| {
-                assert_eq!(t.len(), output_size, "internal error, lengths don't match.");
-                assert_eq!(t.len(), k1.len(), "internal error, lengths don't match.");
-                assert_eq!(
-                    t.len(),
-                    choice_bits.len(),
-                    "internal error, lengths don't match."
-                );
-                let u: BitVec = izip!(t.iter(), k1, choice_bits.iter())
-                    .map(|(t, k, r)| t ^ k ^ r)
-                    .collect();
-                let conn = conn.clone();
-                let lock = conn.lock().unwrap();
-                lock.send(u.to_bytes()).map(|_| {
-                    t
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash + MaybeSend> ExtendedOTReceiver
+    for IKNPExtendedOTReceiver<T, A>
+{
+    fn receive<'a>(&'a mut self, choice_bits: BitVec) -> BoxFuture<'a, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            let output_size = choice_bits.len();
+            let (t_mat, batch) = self.send_matrix(&choice_bits).await?;
+            let pairs = receive_pairs(&mut self.conn, output_size).await?;
+            let result: Vec<Vec<u8>> = pairs
+                .iter()
+                .enumerate()
+                .map(|(i, ys)| {
+                    let ht = self
+                        .hasher
+                        .hash(batch, i as u64, t_mat.row(i), ys[0].len());
+                    ys[choice_bits[i] as usize]
+                        .iter()
+                        .zip(ht)
+                        .map(|(y, ht)| y ^ ht)
+                        .collect()
+                })
+                .collect();
+            Ok(result)
+        })
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash + MaybeSend> CorrelatedOTReceiver
+    for IKNPExtendedOTReceiver<T, A>
+{
+    fn receive_correlated<'a>(
+        &'a mut self,
+        choice_bits: BitVec,
+        len: usize,
+    ) -> BoxFuture<'a, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            let output_size = choice_bits.len();
+            let (t_mat, batch) = self.send_matrix(&choice_bits).await?;
+            let ys = receive_fixed(&mut self.conn, output_size, len).await?;
+            Ok((0..output_size)
+                .map(|i| {
+                    let ht = self.hasher.hash(batch, i as u64, t_mat.row(i), len);
+                    if choice_bits[i] {
+                        let y = &ys[i * len..(i + 1) * len];
+                        y.iter().zip(ht).map(|(y, h)| y ^ h).collect()
+                    } else {
+                        ht
+                    }
                 })
-            }})
-            .collect()
-            .and_then(enclose! { (conn) move |t_mat: Vec<BitVec>| {
-                iter_ok(0..output_size).and_then(move |i| {
-                    let t_mat = t_mat.clone();
-                    let lock = conn.lock().unwrap();
-                    let mut hasher = hasher.clone();
+                .collect())
+        })
+    }
 
-                    lock.receive().and_then(enclose! { (choice_bits) move |(conn, y0)| {
-                        conn.lock().unwrap().receive().map(move |(_, y1)| {
-                            let ys = [y0, y1];
-                            assert_eq!(
-                                ys[0].len(),
-                                ys[1].len(),
-                                "String pairs do not have same size"
-                            );
-                            let mut bt = BitVec::with_capacity(l);
-                            for j in 0..l {
-                                bt.push(t_mat[j][i.clone()]);
-                            }
-                            hasher.input(&(i as u64).to_bytes());
-                            hasher.input(&bt.to_bytes());
-                            let ht = hasher.result(ys[0].len());
-                            let r: Vec<u8> = ys[choice_bits[i.clone()] as usize]
-                                .iter()
-                                .zip(ht)
-                                .map(|(ht, y)| y ^ ht)
-                                .collect();
-                            r
-                        })
-                    }})
-                }).collect()
-            }})
-            .map(|r: Vec<Vec<u8>>| (r, self));
-        Box::new(fut)
+    fn receive_correlated_additive<'a>(&'a mut self, choice_bits: BitVec) -> BoxFuture<'a, Vec<u64>> {
+        Box::pin(async move {
+            let output_size = choice_bits.len();
+            let (t_mat, batch) = self.send_matrix(&choice_bits).await?;
+            let ys = receive_fixed(&mut self.conn, output_size, 8).await?;
+            Ok((0..output_size)
+                .map(|i| {
+                    let ht = read_u64_be(&self.hasher.hash(batch, i as u64, t_mat.row(i), 8));
+                    if choice_bits[i] {
+                        read_u64_be(&ys[i * 8..(i + 1) * 8]).wrapping_add(ht)
+                    } else {
+                        ht
+                    }
+                })
+                .collect())
+        })
     }
 }
 
 pub struct IKNPExtendedOTSender<T, A>
 where
     T: BinaryReceive + BinarySend,
-    A: ArbitraryDigest + Clone,
+    A: Prg + CrHash + MaybeSend,
 {
-    conn: Arc<Mutex<T>>,
-    arbitrary_hasher: A,
+    conn: T,
+    hasher: A,
     initial: Vec<Vec<u8>>,
     random_choices: BitVec,
+    batch: usize,
+    chunk_size: usize,
 }
 
-impl<'a, T: 'a + BinaryReceive + BinarySend, A: 'a + ArbitraryDigest + Clone> GetConn<T>
-    for IKNPExtendedOTSender<T, A>
-{
-    fn get_conn(self) -> Arc<Mutex<T>> {
-        return self.conn;
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash + MaybeSend> GetConn<T> for IKNPExtendedOTSender<T, A> {
+    fn get_conn(self) -> T {
+        self.conn
     }
 }
 
 /// security parameter: number of bytes to use
-impl<'a, T: 'a + BinaryReceive + BinarySend, A: 'a + ArbitraryDigest + Clone>
-    IKNPExtendedOTSender<T, A>
-{
-    pub fn new<S, R>(
-        arbitrary_hasher: A,
-        base_ot_receiver: S,
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash + MaybeSend> IKNPExtendedOTSender<T, A> {
+    pub async fn new<S, R>(
+        hasher: A,
+        mut base_ot_receiver: S,
         mut rng: R,
         security_param: usize,
-    ) -> Box<Future<Item = Self, Error = Error> + 'a>
+    ) -> Result<Self>
     where
-        S: 'a + BaseOTReceiver<'a> + GetConn<T>,
-        R: 'a + RngCore + CryptoRng,
+        S: BaseOTReceiver + GetConn<T>,
+        R: RngCore + CryptoRng + MaybeSend,
     {
         // To simplify this protocol both security parameters are specified
         // in bytes and as such have to be multiplied by 8 for certain parts of the protocol.
         let l = security_param * 8;
         // we generate random choices (0 or 1) and use them to receive
         // `l` seeds (of size `security_param`) from the receiver with the base-OT primitive.
-        let state = (Some(base_ot_receiver), 0);
-        // let mut random_choices = BitVec::with_capacity(security_param);
-        // let mut initial = Vec::with_capacity(security_param);
-        let stream = unfold(state, move |(sender, i): (Option<S>, usize)| {
-            if i < l {
-                let choice: bool = rng.gen();
-                let future = sender
-                    .unwrap()
-                    .receive(choice as usize, 2)
-                    .map(move |(val, s)| {
-                        if i == l - 1 {
-                            (((choice, val), Some(s)), (None, i + 1))
-                        } else {
-                            (((choice, val), None), (Some(s), i + 1))
-                        }
-                    });
-                Some(future)
-            } else {
-                None
+        let random_choices: BitVec = (0..l).map(|_| rng.gen::<bool>()).collect();
+        let indices = random_choices.iter().map(|c| c as usize).collect();
+        let initial = base_ot_receiver.receive_many(indices, 2).await?;
+        Ok(IKNPExtendedOTSender {
+            hasher: hasher,
+            conn: base_ot_receiver.get_conn(),
+            random_choices: random_choices,
+            initial: initial,
+            batch: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Sets the number of OTs whose ciphertexts are sent in one frame.
+    /// The receiver may use a different chunk size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = cmp::max(chunk_size, 1);
+    }
+
+    /// Receives the columns of the receiver and returns the matrix q
+    /// (with one row per OT) together with the number of this batch.
+    async fn receive_matrix(&mut self, output_size: usize) -> Result<(BitMatrix, u64)> {
+        let batch = self.batch as u64;
+        self.batch += 1;
+        let us = receive_columns(&mut self.conn, self.initial.len(), output_size).await?;
+        let q_mat = izip!(&self.initial, &us, &self.random_choices).map(|(k, u, s)| {
+            let gk = expand(&self.hasher, batch, k, output_size);
+            u.iter()
+                .zip(gk)
+                .map(|(u, k)| (((s as u8) * (u as u8)) ^ (k as u8)) == 1)
+                .collect::<BitVec>()
+                .to_bytes()
+        });
+        Ok((BitMatrix::from_rows(q_mat, output_size).transpose(), batch))
+    }
+
+    /// The hashes of the i-th row of q and of its xor with the packed random choices s.
+    fn hash_rows(
+        &self,
+        q_mat: &BitMatrix,
+        s: &[u8],
+        batch: u64,
+        i: usize,
+        len: usize,
+    ) -> (Vec<u8>, Vec<u8>) {
+        let qt = q_mat.row(i);
+        let sqt: Vec<u8> = qt.iter().zip(s).map(|(q, s)| q ^ s).collect();
+        (
+            self.hasher.hash(batch, i as u64, qt, len),
+            self.hasher.hash(batch, i as u64, &sqt, len),
+        )
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash + MaybeSend> ExtendedOTSender
+    for IKNPExtendedOTSender<T, A>
+{
+    fn send<'a>(&'a mut self, values: Vec<(Vec<u8>, Vec<u8>)>) -> BoxFuture<'a, ()> {
+        Box::pin(async move {
+            let output_size = values.len();
+            if values.iter().any(|(x0, x1)| x0.len() != x1.len()) {
+                bail!("String pairs do not have same size");
             }
-        }).collect()
-            .map(|vals: Vec<((bool, Vec<u8>), Option<S>)>| {
-                let (pairs, mut selfs): (Vec<(bool, Vec<u8>)>, Vec<Option<S>>) =
-                    vals.into_iter().unzip();
-                let (choices, initial) = pairs.into_iter().unzip();
 
-                IKNPExtendedOTSender {
-                    arbitrary_hasher: arbitrary_hasher,
-                    conn: selfs.pop().unwrap().unwrap().get_conn(),
-                    random_choices: choices,
-                    initial: initial,
-                }
-            });
-        Box::new(stream)
+            let (q_mat, batch) = self.receive_matrix(output_size).await?;
+            let random_choices = self.random_choices.to_bytes();
+            let chunk_size = self.chunk_size;
+            let frames: Vec<Vec<u8>> = values
+                .chunks(chunk_size)
+                .enumerate()
+                .map(|(c, chunk)| {
+                    let mut ys = Vec::with_capacity(2 * chunk.len());
+                    for (j, (x0, x1)) in chunk.iter().enumerate() {
+                        let i = c * chunk_size + j;
+                        let (hq, shq) = self.hash_rows(&q_mat, &random_choices, batch, i, x0.len());
+                        ys.push(x0.iter().zip(hq).map(|(x, q)| x ^ q).collect());
+                        ys.push(x1.iter().zip(shq).map(|(x, q)| x ^ q).collect());
+                    }
+                    pack(&ys)
+                })
+                .collect();
+            for frame in frames {
+                self.conn.send(&frame).await?;
+            }
+            Ok(())
+        })
     }
 }
 
-impl<'a, T: 'a + BinaryReceive + BinarySend, A: 'a + ArbitraryDigest + Clone> ExtendedOTSender<'a>
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash + MaybeSend> CorrelatedOTSender
     for IKNPExtendedOTSender<T, A>
 {
-    fn send(self, values: Vec<(Vec<u8>, Vec<u8>)>) -> Box<Future<Item = Self, Error = Error> + 'a> {
-        let output_size = values.len();
-        let l = self.initial.len();
-        let security_parameter = self.initial.len();
+    fn send_correlated<'a>(&'a mut self, n: usize, delta: Vec<u8>) -> BoxFuture<'a, Vec<Vec<u8>>> {
+        Box::pin(async move {
+            let (q_mat, batch) = self.receive_matrix(n).await?;
+            let s = self.random_choices.to_bytes();
+            let mut result = Vec::with_capacity(n);
+            let mut ys = Vec::with_capacity(n * delta.len());
+            for i in 0..n {
+                // x0 = H(q), the receiver learns x0 ⊕ delta from y = x0 ⊕ delta ⊕ H(q ⊕ s)
+                let (x0, shq) = self.hash_rows(&q_mat, &s, batch, i, delta.len());
+                ys.extend(izip!(&x0, &delta, shq).map(|(x, d, h)| x ^ d ^ h));
+                result.push(x0);
+            }
+            send_fixed(&mut self.conn, &ys, delta.len(), self.chunk_size).await?;
+            Ok(result)
+        })
+    }
+
+    fn send_correlated_additive<'a>(&'a mut self, deltas: Vec<u64>) -> BoxFuture<'a, Vec<u64>> {
+        Box::pin(async move {
+            let (q_mat, batch) = self.receive_matrix(deltas.len()).await?;
+            let s = self.random_choices.to_bytes();
+            let mut result = Vec::with_capacity(deltas.len());
+            let mut ys = vec![0; 8 * deltas.len()];
+            for (i, delta) in deltas.iter().enumerate() {
+                // x0 = H(q), the receiver learns x0 + delta from y = x0 + delta - H(q ⊕ s)
+                let (x0, shq) = self.hash_rows(&q_mat, &s, batch, i, 8);
+                let (x0, shq) = (read_u64_be(&x0), read_u64_be(&shq));
+                write_u64_be(
+                    &mut ys[i * 8..(i + 1) * 8],
+                    x0.wrapping_add(*delta).wrapping_sub(shq),
+                );
+                result.push(x0);
+            }
+            send_fixed(&mut self.conn, &ys, 8, self.chunk_size).await?;
+            Ok(result)
+        })
+    }
+}
 
-        // TODO: find better way. This clone is bad as it copies the vectors
-        let random_choices = Arc::new(self.random_choices.clone());
-        let initial = Arc::new(self.initial.clone());
+#[cfg(all(test, not(all(target_arch = "wasm32", target_os = "unknown"))))]
+mod tests {
+    use super::*;
+    use crate::common::digest::sha3::SHA3_256;
+    use crate::common::util::{generate_random_choices, generate_random_string_pairs};
+    use crate::r#async::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+    use crate::r#async::crypto::aes_native::AesCryptoProvider;
+    use rand::{ChaChaRng, FromEntropy};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_tungstenite::{accept_async, client_async};
 
-        let conn = self.conn.clone();
-        let arbitrary_hasher = self.arbitrary_hasher.clone();
-        let stream = iter_ok(0..security_parameter)
-            .and_then(enclose! { (conn) move |_| {
-                let lock = conn.lock().unwrap();
-                lock.receive().map(move |(_, s)| bv_truncate(&s, output_size))
-            }})
-            .collect()
-            .and_then(
-                enclose!{ (conn, arbitrary_hasher, random_choices) move |us: Vec<BitVec>| {
-                    assert_eq!(
-                        us.len(),
-                        initial.len(),
-                        "internal error, lengths don't match."
-                    );
-                    assert_eq!(
-                        us.len(),
-                        random_choices.len(),
-                        "internal error, lengths don't match."
-                    );
-                    let q_mat: Vec<BitVec> = izip!(initial.iter(), &us, random_choices.iter())
-                        .map(|(k, u, s)| {
-                            let gk = trunc_hash(arbitrary_hasher.clone(), output_size, k);
-                            u.iter()
-                                .zip(gk)
-                                .map(|(u, k)| (((s as u8) * (u as u8)) ^ (k as u8)) == 1)
-                                .collect()
-                        })
-                        .collect();
-                    let fut = iter_ok(0..output_size)
-                        .and_then(enclose!{ (conn, arbitrary_hasher, random_choices) move |i| {
-                            let lock = conn.lock().unwrap();
-                            let n = values[i].0.len();
-                            assert_eq!(n, values[i].1.len(), "String pairs do not have same size");
-                            let mut qt = BitVec::with_capacity(l);
-                            for j in 0..l {
-                                qt.push(q_mat[j][i]);
-                            }
-                            let mut hasher = arbitrary_hasher.clone();
-                            hasher.input(&(i as u64).to_bytes());
-                            let mut hasher2 = hasher.clone();
-                            // TODO make this nicer
-                            hasher.input(&qt.to_bytes());
-                            let hq = hasher.result(n);
-                            let y0: Vec<u8> = values[i].0.iter().zip(hq).map(|(x, q)| x ^ q).collect();
-                            let q2: Vec<u8> = qt
-                                .to_bytes()
-                                .iter()
-                                .zip(random_choices.to_bytes())
-                                .map(|(q, s)| q ^ s)
-                                .collect();
-                            hasher2.input(&q2);
-                            let shq = hasher2.result(n);
-                            let y1: Vec<u8> = values[i].1.iter().zip(shq).map(|(x, q)| x ^ q).collect();
-                            lock.send(y0).and_then(|s| {
-                                let lock = s.lock().unwrap();
-                                lock.send(y1)
-                            })
-                        }})
-                        .collect()
-                        .map(move |_: Vec<Arc<Mutex<T>>>| self);
-                    fut
-                }},
-            );
-        Box::new(stream)
+    #[tokio::test]
+    async fn iknp_over_websockets() {
+        let n = 100;
+        let choices = generate_random_choices(n);
+        let values: Vec<(Vec<u8>, Vec<u8>)> = generate_random_string_pairs(16, n)
+            .into_iter()
+            .map(|(s0, s1)| (s0.into_bytes(), s1.into_bytes()))
+            .collect();
+        let expected: Vec<Vec<u8>> = choices
+            .iter()
+            .zip(&values)
+            .map(|(c, (s0, s1))| if c { s1.clone() } else { s0.clone() })
+            .collect();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = async {
+            let ws = accept_async(listener.accept().await?.0)
+                .await
+                .chain_err(|| "Handshake failed")?;
+            let rng = ChaChaRng::from_entropy();
+            let base_ot = ChouOrlandiOTSender::new(
+                ws,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )
+            .await?;
+            let mut ot_ext =
+                IKNPExtendedOTReceiver::new(SHA3_256::default(), base_ot, rng, 16).await?;
+            ot_ext.receive(choices).await
+        };
+        let client = async {
+            let stream = TcpStream::connect(addr).await?;
+            let ws = client_async("ws://localhost/", stream)
+                .await
+                .chain_err(|| "Handshake failed")?
+                .0;
+            let rng = ChaChaRng::from_entropy();
+            let base_ot = ChouOrlandiOTReceiver::new(
+                ws,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )
+            .await?;
+            let mut ot_ext =
+                IKNPExtendedOTSender::new(SHA3_256::default(), base_ot, rng, 16).await?;
+            ot_ext.send(values).await
+        };
+        let (received, sent): (Result<_>, Result<_>) = tokio::join!(server, client);
+        sent.unwrap();
+        assert_eq!(received.unwrap(), expected);
+    }
+    /// The correlated OTs have the wire format of the sync implementation.
+    #[tokio::test]
+    async fn correlated_additive_with_sync_sender() {
+        use crate::sync::base_ot::chou as sync_chou;
+        use crate::sync::crypto::aes;
+        use crate::sync::ot_extension::iknp as sync_iknp;
+        use crate::sync::ot_extension::CorrelatedOTSender as _;
+        use std::{net, thread};
+
+        let n = 100;
+        let choices = generate_random_choices(n);
+        let deltas: Vec<u64> = (0..n as u64).map(|i| (i << 40) | i).collect();
+        let deltas2 = deltas.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let rng = ChaChaRng::from_entropy();
+            let base_ot = sync_chou::ChouOrlandiOTReceiver::new(
+                net::TcpStream::connect(addr).unwrap(),
+                SHA3_256::default(),
+                aes::AesCryptoProvider::default(),
+                rng.clone(),
+            )
+            .unwrap();
+            let mut ot_ext =
+                sync_iknp::IKNPExtendedOTSender::new(SHA3_256::default(), base_ot, rng, 16)
+                    .unwrap();
+            ot_ext.set_chunk_size(7);
+            ot_ext.send_correlated_additive(&deltas2).unwrap()
+        });
+        let rng = ChaChaRng::from_entropy();
+        let base_ot = ChouOrlandiOTSender::new(
+            listener.accept().await.unwrap().0,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            rng.clone(),
+        )
+        .await
+        .unwrap();
+        let mut ot_ext = IKNPExtendedOTReceiver::new(SHA3_256::default(), base_ot, rng, 16)
+            .await
+            .unwrap();
+        let received = ot_ext
+            .receive_correlated_additive(choices.clone())
+            .await
+            .unwrap();
+        let sent = client.join().unwrap();
+        for (i, choice) in choices.iter().enumerate() {
+            let expected = if choice {
+                sent[i].wrapping_add(deltas[i])
+            } else {
+                sent[i]
+            };
+            assert_eq!(expected, received[i], "Values differ at index {}", i);
+        }
+    }
+
+    /// Both parties are spawned on tokio's multi-threaded runtime, which only accepts `Send` futures.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn iknp_on_multi_threaded_runtime() {
+        let n = 100;
+        let choices = generate_random_choices(n);
+        let values: Vec<(Vec<u8>, Vec<u8>)> = generate_random_string_pairs(16, n)
+            .into_iter()
+            .map(|(s0, s1)| (s0.into_bytes(), s1.into_bytes()))
+            .collect();
+        let expected: Vec<Vec<u8>> = choices
+            .iter()
+            .zip(&values)
+            .map(|(c, (s0, s1))| if c { s1.clone() } else { s0.clone() })
+            .collect();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(async move {
+            let ws = accept_async(listener.accept().await?.0)
+                .await
+                .chain_err(|| "Handshake failed")?;
+            let rng = ChaChaRng::from_entropy();
+            let base_ot = ChouOrlandiOTSender::new(
+                ws,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )
+            .await?;
+            let mut ot_ext =
+                IKNPExtendedOTReceiver::new(SHA3_256::default(), base_ot, rng, 16).await?;
+            ot_ext.receive(choices).await
+        });
+        let client = tokio::spawn(async move {
+            let stream = TcpStream::connect(addr).await?;
+            let ws = client_async("ws://localhost/", stream)
+                .await
+                .chain_err(|| "Handshake failed")?
+                .0;
+            let rng = ChaChaRng::from_entropy();
+            let base_ot = ChouOrlandiOTReceiver::new(
+                ws,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )
+            .await?;
+            let mut ot_ext =
+                IKNPExtendedOTSender::new(SHA3_256::default(), base_ot, rng, 16).await?;
+            ot_ext.send(values).await
+        });
+        client.await.unwrap().unwrap();
+        assert_eq!(server.await.unwrap().unwrap(), expected);
     }
 }
@@ -4,22 +4,37 @@
 //! without using expensive public-key-cryptography.
 //! While BaseOT implements 1-out-of-n OT, this implements n 1-out-of-2 OTs
 //! because that's how most OT extension protocols work.
-//! It is trivial to implement 1-out-of-n OT with n 1-out-of-2 OTs.
+use crate::r#async::{BoxFuture, MaybeSend};
 use bit_vec::BitVec;
-use errors::*;
-use futures_core::Future;
 
 pub mod iknp;
 
 /// This is the base trait for sending all ot-extension protocols in this library implement.
-pub trait ExtendedOTSender<'a> {
-    fn send(self, values: Vec<(Vec<u8>, Vec<u8>)>) -> Box<Future<Item = Self, Error = Error> + 'a>;
+pub trait ExtendedOTSender: MaybeSend {
+    fn send<'a>(&'a mut self, values: Vec<(Vec<u8>, Vec<u8>)>) -> BoxFuture<'a, ()>;
 }
 
 /// This is the base trait for receiving all ot-extension protocols in this library implement.
-pub trait ExtendedOTReceiver<'a> {
-    fn receive(
-        self,
+pub trait ExtendedOTReceiver: MaybeSend {
+    fn receive<'a>(&'a mut self, choice_bits: BitVec) -> BoxFuture<'a, Vec<Vec<u8>>>;
+}
+
+/// Correlated OT, see `sync::ot_extension::CorrelatedOTSender`.
+pub trait CorrelatedOTSender: MaybeSend {
+    /// Transfers `x0_i` and `x1_i = x0_i ⊕ delta` for `n` OTs with a global `delta`, returns the `x0_i`.
+    fn send_correlated<'a>(&'a mut self, n: usize, delta: Vec<u8>) -> BoxFuture<'a, Vec<Vec<u8>>>;
+    /// Transfers `x0_i` and `x1_i = x0_i + deltas[i]` in the additive group Z_{2^64}, returns the `x0_i`.
+    fn send_correlated_additive<'a>(&'a mut self, deltas: Vec<u64>) -> BoxFuture<'a, Vec<u64>>;
+}
+
+/// Receiving side of `CorrelatedOTSender`.
+pub trait CorrelatedOTReceiver: MaybeSend {
+    /// Receives `x0_i` or `x0_i ⊕ delta` of length `len` depending on the i-th choice bit.
+    fn receive_correlated<'a>(
+        &'a mut self,
         choice_bits: BitVec,
-    ) -> Box<Future<Item = (Vec<Vec<u8>>, Self), Error = Error> + 'a>;
+        len: usize,
+    ) -> BoxFuture<'a, Vec<Vec<u8>>>;
+    /// Receives `x0_i` or `x0_i + delta_i` (mod 2^64) depending on the i-th choice bit.
+    fn receive_correlated_additive<'a>(&'a mut self, choice_bits: BitVec) -> BoxFuture<'a, Vec<u64>>;
 }
@@ -6,118 +6,255 @@ extern crate rand;
 extern crate tungstenite;
 
 use ot::common::digest::sha3::SHA3_256;
+use ot::errors::*;
 use ot::common::util::{
     create_random_strings, generate_random_choices, generate_random_string_pairs,
 };
 use ot::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+use ot::sync::base_ot::naor_pinkas::{NaorPinkasOTReceiver, NaorPinkasOTSender};
 use ot::sync::base_ot::{BaseOTReceiver, BaseOTSender};
-use ot::sync::communication::{BinaryReceive, BinarySend, GetConn};
+use ot::sync::communication::deadline::DeadlineStream;
+use ot::sync::communication::metered::{MeteredChannel, Stopwatch};
+use ot::sync::communication::tcp::FramedTcp;
+use ot::sync::communication::websockets::TimedWebSocket;
+use ot::sync::communication::{BinaryReceive, BinarySend};
 use ot::sync::crypto::aes::AesCryptoProvider;
 use ot::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
 use ot::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
 use rand::distributions::range::Range;
 use rand::Rng;
 use rand::{ChaChaRng, FromEntropy};
-use std::net::TcpListener;
+use std::net::{TcpListener, TcpStream};
 use std::thread::spawn;
-use std::time::Instant;
+use std::time::Duration;
 // use tungstenite::handshake::server::Request;
 // use tungstenite::server::accept_hdr;
 
 use byte_tools::read_u64_be;
-use tungstenite::server::accept;
+use tungstenite::{accept, HandshakeError};
 
-use std::env::args;
 
-fn serve<T>(mut stream: T, n: usize, l: usize, comm_switch: bool)
+/// Clients that stop responding for this long are dropped, instead of blocking their thread forever.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Largest n of a 1-out-of-n base OT a client may request.
+const MAX_BASE_OT_VALUES: usize = 1 << 12;
+/// Largest number of extended OTs a client may request.
+const MAX_EXTENDED_OTS: usize = 1 << 20;
+/// Largest length of the transferred strings a client may request.
+const MAX_STRING_LENGTH: usize = 1 << 12;
+/// Bounds the memory of a single client, n strings of length l are created.
+const MAX_TRANSFER_BYTES: usize = 1 << 28;
+
+/// Upgrades the connection to a websocket, with the same timeout as the TCP connection.
+fn websocket(stream: FramedTcp) -> Result<TimedWebSocket> {
+    let mut stream = DeadlineStream::new(stream.into_inner());
+    stream.set_timeout(Some(TIMEOUT));
+    stream.start();
+    let ws = accept(stream).map_err(|e| match e {
+        HandshakeError::Failure(e) => e.into(),
+        // the stream blocks, so the handshake is never interrupted
+        HandshakeError::Interrupted(_) => Error::from(ErrorKind::CommunicationError),
+    })?;
+    let mut ws = TimedWebSocket::new(ws);
+    ws.set_timeout(Some(TIMEOUT));
+    Ok(ws)
+}
+
+fn serve<T>(stream: T, n: usize, l: usize, comm_switch: bool, naor_pinkas: bool) -> Result<()>
 where
     T: BinarySend + BinaryReceive,
 {
     let mut rng = ChaChaRng::from_entropy();
-
-
+    let stream = MeteredChannel::new(stream);
+    let mut stopwatch = Stopwatch::start(stream.meter());
 
     if comm_switch {
-        //println!("Bytes: {:?}", bytes);
-        //println!("Length: {}", n);
         let dist = Range::new(0, n);
         let choice = rng.sample(dist);
-        //println!("Generated random index: {:?}", choice);
-        //println!("Creating BaseOT receiver...");
-        //let mut now = Instant::now();
-        //stream.send("sync".as_bytes()).unwrap();
-        let mut ot_recv = ChouOrlandiOTReceiver::new(
-            stream,
-            SHA3_256::default(),
-            AesCryptoProvider::default(),
-            rng.clone(),
-        ).unwrap();
-        //println!("chou ot receiver creation took {:?}", now.elapsed());
-        //now = Instant::now();
-        let values = ot_recv.receive(choice, n).unwrap();
-    //println!("Received values: {:?}", values);
-    //println!("OT receive took {:?}", now.elapsed());
+        if naor_pinkas {
+            let mut ot_recv = NaorPinkasOTReceiver::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )?;
+            println!("base OT setup: {}", stopwatch.lap());
+            ot_recv.receive(choice, n)?;
+            println!("transfer: {}", stopwatch.lap());
+        } else {
+            let mut ot_recv = ChouOrlandiOTReceiver::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )?;
+            println!("base OT setup: {}", stopwatch.lap());
+            ot_recv.receive(choice, n)?;
+            println!("transfer: {}", stopwatch.lap());
+        }
     } else {
         let strings: Vec<Vec<u8>> = create_random_strings(n, l)
             .into_iter()
             .map(|s| s.into_bytes())
             .collect();
-        //stream.send("sync".as_bytes()).unwrap();
-        let mut ot = ChouOrlandiOTSender::new(
-            stream,
-            SHA3_256::default(),
-            AesCryptoProvider::default(),
-            rng,
-        ).unwrap();
-        ot.send(strings.iter().map(|s| s.as_slice()).collect())
-            .unwrap();
+        let values = strings.iter().map(|s| s.as_slice()).collect();
+        if naor_pinkas {
+            let mut ot = NaorPinkasOTSender::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng,
+            )?;
+            println!("base OT setup: {}", stopwatch.lap());
+            ot.send(values)?;
+            println!("transfer: {}", stopwatch.lap());
+        } else {
+            let mut ot = ChouOrlandiOTSender::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng,
+            )?;
+            println!("base OT setup: {}", stopwatch.lap());
+            ot.send(values)?;
+            println!("transfer: {}", stopwatch.lap());
+        }
     }
+    Ok(())
 }
 
-fn serve_ote<T>(mut stream: T, n: usize, l: usize, comm_switch: bool)
+fn serve_ote<T>(stream: T, n: usize, l: usize, comm_switch: bool, naor_pinkas: bool) -> Result<()>
 where
     T: BinarySend + BinaryReceive,
 {
-    let mut rng = ChaChaRng::from_entropy();
+    let rng = ChaChaRng::from_entropy();
+    let stream = MeteredChannel::new(stream);
+    let mut stopwatch = Stopwatch::start(stream.meter());
 
     if comm_switch {
         let choices = generate_random_choices(n);
-        let mut ot = ChouOrlandiOTSender::new(
-            stream,
-            SHA3_256::default(),
-            AesCryptoProvider::default(),
-            rng.clone(),
-        ).unwrap();
-
-        let mut ote = IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, rng.clone(), 16).unwrap();
-        ote.receive(&choices).unwrap();
+        if naor_pinkas {
+            let ot = NaorPinkasOTSender::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )?;
+            println!("base OT setup: {}", stopwatch.lap());
+            let mut ote = IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, rng.clone(), 16)?;
+            println!("extension setup: {}", stopwatch.lap());
+            ote.receive(&choices)?;
+            println!("transfer: {}", stopwatch.lap());
+        } else {
+            let ot = ChouOrlandiOTSender::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )?;
+            println!("base OT setup: {}", stopwatch.lap());
+            let mut ote = IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, rng.clone(), 16)?;
+            println!("extension setup: {}", stopwatch.lap());
+            ote.receive(&choices)?;
+            println!("transfer: {}", stopwatch.lap());
+        }
     } else {
         let strings: Vec<(Vec<u8>, Vec<u8>)> = generate_random_string_pairs(l, n)
             .into_iter()
             .map(|(s1, s2)| (s1.into_bytes(), s2.into_bytes()))
             .collect();
+        let values = strings
+            .iter()
+            .map(|(s1, s2)| (s1.as_slice(), s2.as_slice()))
+            .collect();
 
-        let mut ot = ChouOrlandiOTReceiver::new(
-            stream,
-            SHA3_256::default(),
-            AesCryptoProvider::default(),
-            rng.clone(),
-        ).unwrap();
-
-        let mut ote = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng.clone(), 16).unwrap();
-        ote.send(
-            strings
-                .iter()
-                .map(|(s1, s2)| (s1.as_slice(), s1.as_slice()))
-                .collect(),
-        ).unwrap();
+        if naor_pinkas {
+            let ot = NaorPinkasOTReceiver::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )?;
+            println!("base OT setup: {}", stopwatch.lap());
+            let mut ote = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng.clone(), 16)?;
+            println!("extension setup: {}", stopwatch.lap());
+            ote.send(values)?;
+            println!("transfer: {}", stopwatch.lap());
+        } else {
+            let ot = ChouOrlandiOTReceiver::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )?;
+            println!("base OT setup: {}", stopwatch.lap());
+            let mut ote = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng.clone(), 16)?;
+            println!("extension setup: {}", stopwatch.lap());
+            ote.send(values)?;
+            println!("transfer: {}", stopwatch.lap());
+        }
+    }
+    Ok(())
+}
+
+/// Reads the parameters the client sent and runs the requested protocol.
+fn handle(stream: TcpStream) -> Result<()> {
+    // limits the size of the frames a client can make us allocate
+    let mut stream = FramedTcp::new(stream);
+    stream.set_timeout(Some(TIMEOUT));
+    let mut receive_string =
+        || String::from_utf8(stream.receive()?).chain_err(|| "Received invalid parameter");
+    let stream_switch = receive_string()? == "websocket";
+    let comm_switch = receive_string()? == "receive";
+    // one of "simpleOT", "naorPinkas", "iknp" or "iknpNaorPinkas",
+    // the latter uses Naor-Pinkas instead of SimpleOT as base OT
+    let protocol = receive_string()?;
+    let iknp_switch = protocol.starts_with("iknp");
+    let naor_pinkas = protocol == "naorPinkas" || protocol == "iknpNaorPinkas";
+
+    let mut receive_u64 = || -> Result<usize> {
+        let bytes = stream.receive()?;
+        if bytes.len() != 8 {
+            return Err("Received invalid parameter".into());
+        }
+        Ok(read_u64_be(&bytes) as usize)
+    };
+    let n = receive_u64()?;
+    let l = receive_u64()?;
+    let max_n = if iknp_switch {
+        MAX_EXTENDED_OTS
+    } else {
+        MAX_BASE_OT_VALUES
+    };
+    if n == 0 || n > max_n {
+        return Err(format!("Number of transfers has to be between 1 and {}", max_n).into());
+    }
+    if l > MAX_STRING_LENGTH || n * l > MAX_TRANSFER_BYTES {
+        return Err(format!("String length {} is too large for {} transfers", l, n).into());
+    }
+
+    if iknp_switch {
+        if stream_switch {
+            // Websocket
+            serve_ote(websocket(stream)?, n, l, comm_switch, naor_pinkas)
+        } else {
+            // Tcp
+            serve_ote(stream, n, l, comm_switch, naor_pinkas)
+        }
+    } else if stream_switch {
+        // Websocket
+        serve(websocket(stream)?, n, l, comm_switch, naor_pinkas)
+    } else {
+        // Tcp
+        serve(stream, n, l, comm_switch, naor_pinkas)
     }
 }
 
-fn main() {
+fn main() -> Result<()> {
     //let args = args();
     //assert_eq!(args.len(), 1);
-    let server = TcpListener::bind("127.0.0.1:8123").unwrap();
+    let server = TcpListener::bind("127.0.0.1:8123")?;
     for stream in server.incoming() {
         // let callback = |req: &Request| {
         //     println!("Received a new ws handshake");
@@ -132,34 +269,11 @@ fn main() {
         //     Ok(Some(extra_headers))
         // };
         spawn(move || {
-            let mut stream = stream.unwrap();
-            let stream_switch =
-                &String::from_utf8(stream.receive().unwrap()).unwrap() == "websocket";
-            let comm_switch = &String::from_utf8(stream.receive().unwrap()).unwrap() == "receive";
-            let iknp_switch = &String::from_utf8(stream.receive().unwrap()).unwrap() == "iknp";
-
-            let bytes = stream.receive().unwrap();
-            let n = read_u64_be(&bytes) as usize;
-            let bytes = stream.receive().unwrap();
-            let l = read_u64_be(&bytes) as usize;
-
-            if iknp_switch {
-                if stream_switch {
-                    // Websocket
-                    serve_ote(accept(stream).unwrap(), n, l, comm_switch);
-                } else {
-                    // Tcp
-                    serve_ote(stream, n, l, comm_switch);
-                }
-            } else {
-                if stream_switch {
-                    // Websocket
-                    serve(accept(stream).unwrap(), n, l, comm_switch);
-                } else {
-                    // Tcp
-                    serve(stream, n, l, comm_switch);
-                }
+            // a failing client only ends its own thread
+            if let Err(e) = stream.map_err(Error::from).and_then(handle) {
+                eprintln!("Client failed: {}", e);
             }
         });
     }
+    Ok(())
 }
@@ -0,0 +1,293 @@
+//! A packed bit matrix with a fast transpose.
+//!
+//! OT extensions build a matrix of `l` columns (one per base OT) with one row per OT,
+//! but generate and send it column by column. Transposing it bit by bit dominates
+//! the running time of the protocols, so the matrix is transposed in blocks instead:
+//! with AVX2 or SSE2 (detected at runtime) 32 or 16 rows are transposed at once,
+//! the portable fallback transposes blocks of 8x8 bits inside an `u64`.
+
+use std::cmp;
+
+/// A matrix of bits, stored row by row. Every row is packed into bytes with the
+/// most significant bit first, just like `BitVec::to_bytes`, unused bits are zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BitMatrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<u8>,
+}
+
+impl BitMatrix {
+    /// A matrix of the given size with all bits set to zero.
+    pub fn new(rows: usize, cols: usize) -> Self {
+        BitMatrix {
+            rows,
+            cols,
+            data: vec![0; rows * cols.div_ceil(8)],
+        }
+    }
+
+    /// Creates a matrix from packed rows, e.g. from `BitVec::to_bytes`.
+    /// Rows that are too long are truncated to `cols` bits, shorter ones are padded with zeros.
+    pub fn from_rows<I, B>(rows: I, cols: usize) -> Self
+    where
+        I: IntoIterator<Item = B>,
+        B: AsRef<[u8]>,
+    {
+        let stride = cols.div_ceil(8);
+        let mut data = Vec::new();
+        let mut count = 0;
+        for row in rows {
+            let row = row.as_ref();
+            let len = cmp::min(row.len(), stride);
+            data.extend_from_slice(&row[..len]);
+            data.resize((count + 1) * stride, 0);
+            if !cols.is_multiple_of(8) {
+                data[(count + 1) * stride - 1] &= 0xff << (8 - cols % 8);
+            }
+            count += 1;
+        }
+        BitMatrix {
+            rows: count,
+            cols,
+            data,
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// The packed bytes of row `i`.
+    pub fn row(&self, i: usize) -> &[u8] {
+        let stride = self.stride();
+        &self.data[i * stride..(i + 1) * stride]
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> bool {
+        assert!(i < self.rows && j < self.cols, "index out of bounds");
+        self.data[i * self.stride() + j / 8] & (0x80 >> (j % 8)) != 0
+    }
+
+    pub fn set(&mut self, i: usize, j: usize, bit: bool) {
+        assert!(i < self.rows && j < self.cols, "index out of bounds");
+        let stride = self.stride();
+        let byte = &mut self.data[i * stride + j / 8];
+        if bit {
+            *byte |= 0x80 >> (j % 8);
+        } else {
+            *byte &= !(0x80 >> (j % 8));
+        }
+    }
+
+    /// Returns the transposed matrix.
+    pub fn transpose(&self) -> BitMatrix {
+        let width = self.stride();
+        // the kernels work on blocks of 32 rows, so we pad with zero rows if necessary
+        let padded_rows = self.rows.div_ceil(32) * 32;
+        let mut padded;
+        let input = if padded_rows == self.rows {
+            &self.data
+        } else {
+            padded = self.data.clone();
+            padded.resize(padded_rows * width, 0);
+            &padded
+        };
+        let mut output = vec![0; width * 8 * padded_rows / 8];
+        transpose_blocks(input, padded_rows, width, &mut output);
+
+        // remove the rows and columns that only exist because of padding
+        let stride = self.rows.div_ceil(8);
+        let padded_stride = padded_rows / 8;
+        let data = if stride == padded_stride {
+            output.truncate(self.cols * stride);
+            output
+        } else {
+            let mut data = Vec::with_capacity(self.cols * stride);
+            for row in output.chunks(padded_stride).take(self.cols) {
+                data.extend_from_slice(&row[..stride]);
+            }
+            data
+        };
+        BitMatrix {
+            rows: self.cols,
+            cols: self.rows,
+            data,
+        }
+    }
+
+    fn stride(&self) -> usize {
+        self.cols.div_ceil(8)
+    }
+}
+
+/// Transposes `rows` (a multiple of 32) rows of `width` bytes into `output`,
+/// which has `8 * width` rows of `rows / 8` bytes.
+fn transpose_blocks(input: &[u8], rows: usize, width: usize, output: &mut [u8]) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            unsafe { transpose_avx2(input, rows, width, output) };
+            return;
+        }
+        if is_x86_feature_detected!("sse2") {
+            unsafe { transpose_sse2(input, rows, width, output) };
+            return;
+        }
+    }
+    transpose_portable(input, rows, width, output);
+}
+
+/// Transposes blocks of 8x8 bits, the rows of a block are the bytes of an `u64`
+/// with the first row in the most significant byte.
+fn transpose_portable(input: &[u8], rows: usize, width: usize, output: &mut [u8]) {
+    let out_stride = rows / 8;
+    for rb in 0..rows / 8 {
+        for cb in 0..width {
+            let mut x: u64 = 0;
+            for k in 0..8 {
+                x = (x << 8) | input[(rb * 8 + k) * width + cb] as u64;
+            }
+            // [Hacker's Delight, 7-3]
+            let mut t = (x ^ (x >> 7)) & 0x00AA_00AA_00AA_00AA;
+            x ^= t ^ (t << 7);
+            t = (x ^ (x >> 14)) & 0x0000_CCCC_0000_CCCC;
+            x ^= t ^ (t << 14);
+            t = (x ^ (x >> 28)) & 0x0000_0000_F0F0_F0F0;
+            x ^= t ^ (t << 28);
+            for k in 0..8 {
+                output[(cb * 8 + k) * out_stride + rb] = (x >> (56 - 8 * k)) as u8;
+            }
+        }
+    }
+}
+
+/// Collects the byte `cb` of `N` consecutive rows starting at `start`. Within every group of
+/// eight the order is reversed as `movemask` puts the first byte into the least significant bit,
+/// while our rows are packed most significant bit first.
+#[cfg(target_arch = "x86_64")]
+macro_rules! gather {
+    ($n:expr, $input:expr, $start:expr, $width:expr, $cb:expr) => {{
+        let mut block = [0u8; $n];
+        for (p, b) in block.iter_mut().enumerate() {
+            *b = $input[($start + 8 * (p / 8) + 7 - p % 8) * $width + $cb];
+        }
+        block
+    }};
+}
+
+/// Every `movemask` collects the most significant bit of 16 rows, i.e. 16 bits of a column.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn transpose_sse2(input: &[u8], rows: usize, width: usize, output: &mut [u8]) {
+    use std::arch::x86_64::*;
+    let out_stride = rows / 8;
+    for rb in 0..rows / 16 {
+        for cb in 0..width {
+            let block = gather!(16, input, rb * 16, width, cb);
+            let mut v = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+            for k in 0..8 {
+                let mask = _mm_movemask_epi8(v) as u16;
+                let out = (cb * 8 + k) * out_stride + rb * 2;
+                output[out] = mask as u8;
+                output[out + 1] = (mask >> 8) as u8;
+                v = _mm_slli_epi64(v, 1);
+            }
+        }
+    }
+}
+
+/// Same as `transpose_sse2` but with 32 rows at once.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn transpose_avx2(input: &[u8], rows: usize, width: usize, output: &mut [u8]) {
+    use std::arch::x86_64::*;
+    let out_stride = rows / 8;
+    for rb in 0..rows / 32 {
+        for cb in 0..width {
+            let block = gather!(32, input, rb * 32, width, cb);
+            let mut v = _mm256_loadu_si256(block.as_ptr() as *const __m256i);
+            for k in 0..8 {
+                let mask = _mm256_movemask_epi8(v) as u32;
+                let out = (cb * 8 + k) * out_stride + rb * 4;
+                for q in 0..4 {
+                    output[out + q] = (mask >> (8 * q)) as u8;
+                }
+                v = _mm256_slli_epi64(v, 1);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{thread_rng, Rng};
+
+    fn random_matrix(rows: usize, cols: usize) -> BitMatrix {
+        let mut rng = thread_rng();
+        let mut m = BitMatrix::new(rows, cols);
+        for i in 0..rows {
+            for j in 0..cols {
+                m.set(i, j, rng.gen());
+            }
+        }
+        m
+    }
+
+    fn naive_transpose(m: &BitMatrix) -> BitMatrix {
+        let mut t = BitMatrix::new(m.cols(), m.rows());
+        for i in 0..m.rows() {
+            for j in 0..m.cols() {
+                t.set(j, i, m.get(i, j));
+            }
+        }
+        t
+    }
+
+    #[test]
+    fn transpose_matches_naive() {
+        for &(rows, cols) in &[(1, 1), (8, 8), (13, 7), (32, 128), (128, 100), (128, 1000), (200, 33)] {
+            let m = random_matrix(rows, cols);
+            let t = m.transpose();
+            assert_eq!(t, naive_transpose(&m), "{}x{}", rows, cols);
+            assert_eq!(t.transpose(), m);
+        }
+    }
+
+    #[test]
+    fn kernels_agree() {
+        let (rows, width) = (64, 5);
+        let mut rng = thread_rng();
+        let input: Vec<u8> = (0..rows * width).map(|_| rng.gen()).collect();
+        let mut expected = vec![0; rows * width];
+        transpose_portable(&input, rows, width, &mut expected);
+        let mut output = vec![0; rows * width];
+        transpose_blocks(&input, rows, width, &mut output);
+        assert_eq!(output, expected);
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("sse2") {
+                let mut output = vec![0; rows * width];
+                unsafe { transpose_sse2(&input, rows, width, &mut output) };
+                assert_eq!(output, expected);
+            }
+        }
+        let m = BitMatrix::from_rows(input.chunks(width), width * 8);
+        assert_eq!(
+            BitMatrix::from_rows(expected.chunks(rows / 8), rows),
+            naive_transpose(&m)
+        );
+    }
+
+    #[test]
+    fn from_rows_masks_padding() {
+        let m = BitMatrix::from_rows(vec![vec![0xff, 0xff], vec![0xff]], 12);
+        assert_eq!(m.row(0), &[0xff, 0xf0]);
+        assert_eq!(m.row(1), &[0xff, 0x00]);
+    }
+}
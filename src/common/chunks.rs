@@ -0,0 +1,129 @@
+//! Splitting the large messages of OT extensions into frames of bounded size.
+//!
+//! Sending one frame per column or per OT is slow as every frame has its own length prefix
+//! and is flushed separately, especially over websockets. Instead the data of many OTs
+//! is combined into one frame. The receiving side validates every frame it gets, the
+//! chunk size of the other party is derived from the frame lengths and does not need to be known.
+
+use bit_vec::BitVec;
+use crate::common::util::{bv_truncate, unpack};
+use crate::errors::*;
+use std::cmp;
+
+/// Splits the columns of a matrix into frames, each frame contains the bytes of every
+/// column for `chunk_size` rows (rounded up to a multiple of 8).
+pub fn column_frames(columns: &[Vec<u8>], chunk_size: usize) -> Vec<Vec<u8>> {
+    let width = cmp::max(chunk_size.div_ceil(8), 1);
+    let height = columns.first().map_or(0, |c| c.len());
+    let mut frames = Vec::with_capacity(height.div_ceil(width));
+    let mut start = 0;
+    while start < height {
+        let end = cmp::min(start + width, height);
+        let mut frame = Vec::with_capacity(columns.len() * (end - start));
+        for column in columns {
+            frame.extend_from_slice(&column[start..end]);
+        }
+        frames.push(frame);
+        start = end;
+    }
+    frames
+}
+
+/// Reassembles the columns split by `column_frames`.
+pub struct ColumnCollector {
+    columns: Vec<Vec<u8>>,
+    rows: usize,
+    received: usize,
+}
+
+impl ColumnCollector {
+    /// Collects `count` columns of `rows` bits each.
+    pub fn new(count: usize, rows: usize) -> Self {
+        ColumnCollector {
+            columns: vec![Vec::with_capacity(rows.div_ceil(8)); count],
+            rows,
+            received: 0,
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received == self.rows.div_ceil(8)
+    }
+
+    /// Adds the next frame, it has to contain the same number of bytes for every column
+    /// and may not exceed the expected size.
+    pub fn push(&mut self, frame: &[u8]) -> Result<()> {
+        let count = self.columns.len();
+        if count == 0
+            || frame.is_empty()
+            || !frame.len().is_multiple_of(count)
+            || self.received + frame.len() / count > self.rows.div_ceil(8)
+        {
+            bail!("Received columns have the wrong length");
+        }
+        let width = frame.len() / count;
+        for (column, part) in self.columns.iter_mut().zip(frame.chunks(width)) {
+            column.extend_from_slice(part);
+        }
+        self.received += width;
+        Ok(())
+    }
+
+    pub fn into_columns(self) -> Vec<BitVec> {
+        let rows = self.rows;
+        self.columns.iter().map(|c| bv_truncate(c, rows)).collect()
+    }
+}
+
+/// Unpacks a frame of ciphertext pairs created with `pack(&[y0, y1, y0', y1', ...])`,
+/// at most `remaining` pairs are accepted and both ciphertexts of a pair have to be of the same size.
+pub fn unpack_pairs(frame: &[u8], remaining: usize) -> Result<Vec<[Vec<u8>; 2]>> {
+    let ys = unpack(frame)?;
+    if ys.is_empty() || ys.len() % 2 != 0 || ys.len() / 2 > remaining {
+        bail!("Received wrong number of ciphertexts");
+    }
+    let mut pairs = Vec::with_capacity(ys.len() / 2);
+    let mut ys = ys.into_iter();
+    while let (Some(y0), Some(y1)) = (ys.next(), ys.next()) {
+        if y0.len() != y1.len() {
+            bail!("Received pairs differ in size");
+        }
+        pairs.push([y0, y1]);
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::util::pack;
+
+    #[test]
+    fn columns_roundtrip() {
+        let columns: Vec<Vec<u8>> = (0..5u8).map(|i| (0..13).map(|j| i * 13 + j).collect()).collect();
+        for &chunk_size in &[1, 8, 13, 24, 100, 1 << 16] {
+            let mut collector = ColumnCollector::new(5, 100);
+            for frame in column_frames(&columns, chunk_size) {
+                assert!(!collector.is_complete());
+                collector.push(&frame).unwrap();
+            }
+            assert!(collector.is_complete());
+            let received = collector.into_columns();
+            for (c, r) in columns.iter().zip(received) {
+                assert_eq!(bv_truncate(c, 100), r);
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_frames() {
+        let mut collector = ColumnCollector::new(5, 100);
+        assert!(collector.push(&[]).is_err());
+        assert!(collector.push(&[0; 7]).is_err());
+        assert!(collector.push(&[0; 5 * 14]).is_err());
+        assert!(unpack_pairs(&pack(&[vec![0; 4]]), 1).is_err());
+        assert!(unpack_pairs(&pack(&[vec![0; 4], vec![0; 5]]), 1).is_err());
+        assert!(unpack_pairs(&pack(&[vec![0; 4], vec![0; 4]]), 0).is_err());
+        assert_eq!(unpack_pairs(&pack(&[vec![0; 4], vec![1; 4]]), 1).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,311 @@
+//! Fixed-key AES constructions for OT extensions.
+//!
+//! Hashing every row of the extension matrix with SHA3 dominates the running time of IKNP.
+//! `AesHash` replaces it with the tweakable correlation robust hash of Guo et al.
+//! (TCCR, [https://eprint.iacr.org/2019/074.pdf]) `H(i, x) = π(π(x) ⊕ i) ⊕ π(x)` where π is AES
+//! with a fixed, public key, and expands the seeds of the base OTs with AES in counter mode.
+//! AES-NI is used if the processor supports it (detected at runtime), otherwise a portable
+//! software implementation, which also runs in the browser.
+//!
+//! Both are designed for the 128 bit seeds and rows of IKNP with `security_param = 16`.
+//! Inputs of other lengths are folded block by block together with their length and hashes
+//! longer than 16 bytes are expanded with AES-CTR, keyed with the hash.
+
+use super::{CrHash, Prg, Separable};
+use byte_tools::write_u64_be;
+
+/// The fixed key of π, the first digits of pi.
+const FIXED_KEY: [u8; 16] = [
+    0x24, 0x3f, 0x6a, 0x88, 0x85, 0xa3, 0x08, 0xd3, 0x13, 0x19, 0x8a, 0x2e, 0x03, 0x70, 0x73, 0x44,
+];
+
+/// Fixed-key AES correlation robust hash and AES-CTR PRG.
+#[derive(Clone)]
+pub struct AesHash {
+    key: [u8; 16],
+    cipher: Aes128,
+}
+
+impl Default for AesHash {
+    fn default() -> Self {
+        AesHash::with_key(FIXED_KEY)
+    }
+}
+
+impl AesHash {
+    fn with_key(key: [u8; 16]) -> Self {
+        AesHash {
+            key,
+            cipher: Aes128::new(&key),
+        }
+    }
+
+    /// Uses a key derived from `domain` (e.g. a session id) instead of the fixed one,
+    /// so the hashes of different domains are independent. The key is still public.
+    pub fn with_domain(domain: &[u8]) -> Self {
+        AesHash::default().separate(domain)
+    }
+
+    /// Inputs of exactly one block are used as they are. All others are folded into a block
+    /// encoding their length with `x' = π(x) ⊕ x ⊕ m` for every (zero padded) block `m`,
+    /// so inputs which only differ in trailing zeros are mapped to different blocks.
+    fn compress(&self, input: &[u8]) -> [u8; 16] {
+        if input.len() == 16 {
+            return to_block(input);
+        }
+        let mut x = counter_block(input.len() as u64, 0);
+        for m in input.chunks(16) {
+            x = xor(&xor(&self.cipher.encrypt(x), &x), &to_block(m));
+        }
+        x
+    }
+}
+
+/// Derives the new key from the current one, so `with_domain` equals separating the default hash.
+impl Separable for AesHash {
+    const NAME: &'static str = "aes-tccr";
+    fn separate(self, domain: &[u8]) -> Self {
+        AesHash::with_key(xor(&self.compress(domain), &self.key))
+    }
+}
+
+impl Prg for AesHash {
+    fn expand(&self, nonce: u64, seed: &[u8], len: usize) -> Vec<u8> {
+        ctr(&Aes128::new(&self.compress(seed)), nonce, len)
+    }
+}
+
+impl CrHash for AesHash {
+    fn hash(&self, nonce: u64, tweak: u64, input: &[u8], len: usize) -> Vec<u8> {
+        let px = self.cipher.encrypt(self.compress(input));
+        let h = xor(
+            &self.cipher.encrypt(xor(&px, &counter_block(nonce, tweak))),
+            &px,
+        );
+        if len <= 16 {
+            h[..len].to_vec()
+        } else {
+            ctr(&Aes128::new(&h), 0, len)
+        }
+    }
+}
+
+/// The first `len` bytes of the key stream of AES in counter mode.
+fn ctr(cipher: &Aes128, nonce: u64, len: usize) -> Vec<u8> {
+    let mut blocks: Vec<[u8; 16]> = (0..len.div_ceil(16))
+        .map(|j| counter_block(nonce, j as u64))
+        .collect();
+    cipher.encrypt_blocks(&mut blocks);
+    let mut stream: Vec<u8> = blocks.iter().flat_map(|b| b.iter().cloned()).collect();
+    stream.truncate(len);
+    stream
+}
+
+fn counter_block(high: u64, low: u64) -> [u8; 16] {
+    let mut block = [0; 16];
+    write_u64_be(&mut block[..8], high);
+    write_u64_be(&mut block[8..], low);
+    block
+}
+
+fn to_block(data: &[u8]) -> [u8; 16] {
+    let mut block = [0; 16];
+    block[..data.len()].copy_from_slice(data);
+    block
+}
+
+fn xor(a: &[u8; 16], b: &[u8; 16]) -> [u8; 16] {
+    let mut c = [0; 16];
+    for (c, (a, b)) in c.iter_mut().zip(a.iter().zip(b)) {
+        *c = a ^ b;
+    }
+    c
+}
+
+/// AES-128 encryption, only what the constructions above need.
+#[derive(Clone)]
+struct Aes128 {
+    round_keys: [[u8; 16]; 11],
+    #[cfg_attr(not(target_arch = "x86_64"), allow(dead_code))]
+    aesni: bool,
+}
+
+impl Aes128 {
+    fn new(key: &[u8; 16]) -> Self {
+        Aes128 {
+            round_keys: expand_key(key),
+            aesni: has_aesni(),
+        }
+    }
+
+    fn encrypt(&self, block: [u8; 16]) -> [u8; 16] {
+        let mut blocks = [block];
+        self.encrypt_blocks(&mut blocks);
+        blocks[0]
+    }
+
+    fn encrypt_blocks(&self, blocks: &mut [[u8; 16]]) {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if self.aesni {
+                unsafe { encrypt_aesni(&self.round_keys, blocks) };
+                return;
+            }
+        }
+        for block in blocks.iter_mut() {
+            encrypt_soft(&self.round_keys, block);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn has_aesni() -> bool {
+    is_x86_feature_detected!("aes")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn has_aesni() -> bool {
+    false
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "aes")]
+unsafe fn encrypt_aesni(round_keys: &[[u8; 16]; 11], blocks: &mut [[u8; 16]]) {
+    use std::arch::x86_64::*;
+    let mut keys = [_mm_setzero_si128(); 11];
+    for (k, rk) in keys.iter_mut().zip(round_keys.iter()) {
+        *k = _mm_loadu_si128(rk.as_ptr() as *const __m128i);
+    }
+    for block in blocks.iter_mut() {
+        let mut b = _mm_loadu_si128(block.as_ptr() as *const __m128i);
+        b = _mm_xor_si128(b, keys[0]);
+        for k in &keys[1..10] {
+            b = _mm_aesenc_si128(b, *k);
+        }
+        b = _mm_aesenclast_si128(b, keys[10]);
+        _mm_storeu_si128(block.as_mut_ptr() as *mut __m128i, b);
+    }
+}
+
+const SBOX: [u8; 256] = [
+    0x63, 0x7c, 0x77, 0x7b, 0xf2, 0x6b, 0x6f, 0xc5, 0x30, 0x01, 0x67, 0x2b, 0xfe, 0xd7, 0xab, 0x76,
+    0xca, 0x82, 0xc9, 0x7d, 0xfa, 0x59, 0x47, 0xf0, 0xad, 0xd4, 0xa2, 0xaf, 0x9c, 0xa4, 0x72, 0xc0,
+    0xb7, 0xfd, 0x93, 0x26, 0x36, 0x3f, 0xf7, 0xcc, 0x34, 0xa5, 0xe5, 0xf1, 0x71, 0xd8, 0x31, 0x15,
+    0x04, 0xc7, 0x23, 0xc3, 0x18, 0x96, 0x05, 0x9a, 0x07, 0x12, 0x80, 0xe2, 0xeb, 0x27, 0xb2, 0x75,
+    0x09, 0x83, 0x2c, 0x1a, 0x1b, 0x6e, 0x5a, 0xa0, 0x52, 0x3b, 0xd6, 0xb3, 0x29, 0xe3, 0x2f, 0x84,
+    0x53, 0xd1, 0x00, 0xed, 0x20, 0xfc, 0xb1, 0x5b, 0x6a, 0xcb, 0xbe, 0x39, 0x4a, 0x4c, 0x58, 0xcf,
+    0xd0, 0xef, 0xaa, 0xfb, 0x43, 0x4d, 0x33, 0x85, 0x45, 0xf9, 0x02, 0x7f, 0x50, 0x3c, 0x9f, 0xa8,
+    0x51, 0xa3, 0x40, 0x8f, 0x92, 0x9d, 0x38, 0xf5, 0xbc, 0xb6, 0xda, 0x21, 0x10, 0xff, 0xf3, 0xd2,
+    0xcd, 0x0c, 0x13, 0xec, 0x5f, 0x97, 0x44, 0x17, 0xc4, 0xa7, 0x7e, 0x3d, 0x64, 0x5d, 0x19, 0x73,
+    0x60, 0x81, 0x4f, 0xdc, 0x22, 0x2a, 0x90, 0x88, 0x46, 0xee, 0xb8, 0x14, 0xde, 0x5e, 0x0b, 0xdb,
+    0xe0, 0x32, 0x3a, 0x0a, 0x49, 0x06, 0x24, 0x5c, 0xc2, 0xd3, 0xac, 0x62, 0x91, 0x95, 0xe4, 0x79,
+    0xe7, 0xc8, 0x37, 0x6d, 0x8d, 0xd5, 0x4e, 0xa9, 0x6c, 0x56, 0xf4, 0xea, 0x65, 0x7a, 0xae, 0x08,
+    0xba, 0x78, 0x25, 0x2e, 0x1c, 0xa6, 0xb4, 0xc6, 0xe8, 0xdd, 0x74, 0x1f, 0x4b, 0xbd, 0x8b, 0x8a,
+    0x70, 0x3e, 0xb5, 0x66, 0x48, 0x03, 0xf6, 0x0e, 0x61, 0x35, 0x57, 0xb9, 0x86, 0xc1, 0x1d, 0x9e,
+    0xe1, 0xf8, 0x98, 0x11, 0x69, 0xd9, 0x8e, 0x94, 0x9b, 0x1e, 0x87, 0xe9, 0xce, 0x55, 0x28, 0xdf,
+    0x8c, 0xa1, 0x89, 0x0d, 0xbf, 0xe6, 0x42, 0x68, 0x41, 0x99, 0x2d, 0x0f, 0xb0, 0x54, 0xbb, 0x16,
+];
+
+const RCON: [u8; 10] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x1b, 0x36];
+
+fn expand_key(key: &[u8; 16]) -> [[u8; 16]; 11] {
+    let mut round_keys = [[0; 16]; 11];
+    round_keys[0] = *key;
+    for r in 1..11 {
+        let prev = round_keys[r - 1];
+        let mut word = [
+            SBOX[prev[13] as usize] ^ RCON[r - 1],
+            SBOX[prev[14] as usize],
+            SBOX[prev[15] as usize],
+            SBOX[prev[12] as usize],
+        ];
+        for i in 0..16 {
+            word[i % 4] ^= prev[i];
+            round_keys[r][i] = word[i % 4];
+        }
+    }
+    round_keys
+}
+
+fn xtime(x: u8) -> u8 {
+    (x << 1) ^ if x & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+/// The state is stored column by column, i.e. in the order of the input bytes.
+fn encrypt_soft(round_keys: &[[u8; 16]; 11], block: &mut [u8; 16]) {
+    for (b, k) in block.iter_mut().zip(&round_keys[0]) {
+        *b ^= k;
+    }
+    for (r, round_key) in round_keys.iter().enumerate().skip(1) {
+        // SubBytes and ShiftRows
+        let mut state = [0; 16];
+        for c in 0..4 {
+            for row in 0..4 {
+                state[c * 4 + row] = SBOX[block[((c + row) % 4) * 4 + row] as usize];
+            }
+        }
+        // MixColumns, except for the last round
+        if r < 10 {
+            for column in state.chunks_mut(4) {
+                let a = [column[0], column[1], column[2], column[3]];
+                let all = a[0] ^ a[1] ^ a[2] ^ a[3];
+                for i in 0..4 {
+                    column[i] = a[i] ^ all ^ xtime(a[i] ^ a[(i + 1) % 4]);
+                }
+            }
+        }
+        for (b, (s, k)) in block.iter_mut().zip(state.iter().zip(round_key)) {
+            *b = s ^ k;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // FIPS-197, appendix C.1
+    const KEY: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    const PLAINTEXT: [u8; 16] = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ];
+    const CIPHERTEXT: [u8; 16] = [
+        0x69, 0xc4, 0xe0, 0xd8, 0x6a, 0x7b, 0x04, 0x30, 0xd8, 0xcd, 0xb7, 0x80, 0x70, 0xb4, 0xc5,
+        0x5a,
+    ];
+
+    #[test]
+    fn aes_test_vector() {
+        let mut cipher = Aes128::new(&KEY);
+        assert_eq!(cipher.encrypt(PLAINTEXT), CIPHERTEXT);
+        cipher.aesni = false;
+        assert_eq!(cipher.encrypt(PLAINTEXT), CIPHERTEXT);
+    }
+
+    #[test]
+    fn prg_and_hash() {
+        let aes = AesHash::default();
+        let seed = [7; 16];
+        let long = aes.expand(0, &seed, 100);
+        assert_eq!(long.len(), 100);
+        assert_eq!(&long[..37], aes.expand(0, &seed, 37).as_slice());
+        assert_ne!(long, aes.expand(1, &seed, 100));
+
+        let h = aes.hash(0, 5, &seed, 16);
+        assert_eq!(h, aes.hash(0, 5, &seed, 16));
+        assert_ne!(h, aes.hash(0, 6, &seed, 16));
+        assert_ne!(h, aes.hash(1, 5, &seed, 16));
+        assert_eq!(&h[..10], aes.hash(0, 5, &seed, 10).as_slice());
+        assert_eq!(aes.hash(0, 5, &seed, 50).len(), 50);
+        assert_ne!(aes.hash(0, 5, &[7; 20], 16), aes.hash(0, 5, &[7; 21], 16));
+        // zero padding must not make inputs of different lengths collide
+        assert_ne!(aes.hash(0, 5, &[7], 16), aes.hash(0, 5, &[7, 0], 16));
+        assert_ne!(aes.hash(0, 5, &[7; 16], 16), aes.hash(0, 5, &[7; 15], 16));
+        assert_ne!(aes.expand(0, &[7, 0], 16), aes.expand(0, &[7, 0, 0], 16));
+        assert_ne!(h, AesHash::with_domain(&[1; 32]).hash(0, 5, &seed, 16));
+    }
+}
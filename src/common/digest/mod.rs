@@ -1,5 +1,6 @@
 use generic_array::{ArrayLength, GenericArray};
 
+pub mod aes;
 pub mod sha3;
 
 /// A simple trait to generalize hashing functions used by this library.
@@ -9,6 +10,8 @@ pub mod sha3;
 ///
 /// As a general, fits-most implementation, a wrapper around tiny-keccaks SHA3 implementation is provided.
 pub trait Digest {
+    /// Identifies the hash function in session headers, e.g. `"sha3-256"`.
+    const NAME: &'static str;
     type OutputSize: ArrayLength<u8>;
     fn input(&mut self, data: &[u8]);
     fn result(self) -> GenericArray<u8, Self::OutputSize>;
@@ -18,6 +21,60 @@ pub trait Digest {
 ///
 /// As a general, fits-most implementation, a wrapper asround tiny-keccaks Keccak implementation is provided.
 pub trait ArbitraryDigest {
+    /// Identifies the hash function in session headers, e.g. `"sha3-256"`.
+    const NAME: &'static str;
     fn input(&mut self, data: &[u8]);
     fn result(self, output_size: usize) -> Vec<u8>;
 }
+
+/// A pseudorandom generator, used by OT extensions to expand the seeds of the base OTs.
+///
+/// Every variable-length hash function is one (see the implementation for `ArbitraryDigest`),
+/// a much faster alternative based on AES is `aes::AesHash`.
+pub trait Prg {
+    /// Expands `seed` to `len` pseudorandom bytes. OT extensions expand the same seeds
+    /// once per batch, so the output is bound to the `nonce` as well.
+    fn expand(&self, nonce: u64, seed: &[u8], len: usize) -> Vec<u8>;
+}
+
+/// A tweakable correlation robust hash function, used by OT extensions to break
+/// the correlation between the rows of their matrices.
+pub trait CrHash {
+    /// Hashes `input` to `len` bytes, using the batch `nonce` and the index of the OT as tweak.
+    fn hash(&self, nonce: u64, tweak: u64, input: &[u8], len: usize) -> Vec<u8>;
+}
+
+/// Hash functions of the OT extensions which can be bound to a domain, e.g. a session id,
+/// so that their outputs in different domains are independent.
+pub trait Separable {
+    /// Identifies the hash function in session headers.
+    const NAME: &'static str;
+    fn separate(self, domain: &[u8]) -> Self;
+}
+
+impl<A: ArbitraryDigest> Separable for A {
+    const NAME: &'static str = <A as ArbitraryDigest>::NAME;
+    fn separate(mut self, domain: &[u8]) -> Self {
+        self.input(domain);
+        self
+    }
+}
+
+impl<A: ArbitraryDigest + Clone> Prg for A {
+    fn expand(&self, nonce: u64, seed: &[u8], len: usize) -> Vec<u8> {
+        let mut hasher = self.clone();
+        hasher.input(&nonce.to_be_bytes());
+        hasher.input(seed);
+        hasher.result(len)
+    }
+}
+
+impl<A: ArbitraryDigest + Clone> CrHash for A {
+    fn hash(&self, nonce: u64, tweak: u64, input: &[u8], len: usize) -> Vec<u8> {
+        let mut hasher = self.clone();
+        hasher.input(&nonce.to_be_bytes());
+        hasher.input(&tweak.to_be_bytes());
+        hasher.input(input);
+        hasher.result(len)
+    }
+}
@@ -13,6 +13,7 @@ impl Default for SHA3_256 {
 }
 
 impl Digest for SHA3_256 {
+    const NAME: &'static str = "sha3-256";
     type OutputSize = U32;
     fn input(&mut self, data: &[u8]) {
         self.0.update(data);
@@ -25,12 +26,12 @@ impl Digest for SHA3_256 {
 }
 
 impl ArbitraryDigest for SHA3_256 {
+    const NAME: &'static str = "sha3-256";
     fn input(&mut self, data: &[u8]) {
         self.0.update(data);
     }
     fn result(self, output_size: usize) -> Vec<u8> {
-        let mut vec = Vec::with_capacity(output_size);
-        vec.resize(output_size, 0);
+        let mut vec = vec![0; output_size];
         self.0.finalize(&mut vec);
         vec
     }
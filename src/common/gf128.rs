@@ -0,0 +1,90 @@
+//! Arithmetic in the binary field GF(2^128) as needed for the correlation check of KOS15.
+//!
+//! Elements are represented as `u128` where bit `i` is the coefficient of `x^i`,
+//! the field is defined by the irreducible polynomial x^128 + x^7 + x^2 + x + 1.
+
+/// the lower part of the reduction polynomial, x^7 + x^2 + x + 1
+const MODULUS: u128 = 0x87;
+
+/// Multiplies two field elements (shift-and-add, with reduction in every step).
+pub fn gf128_mul(mut a: u128, mut b: u128) -> u128 {
+    let mut result = 0;
+    while b != 0 {
+        if b & 1 == 1 {
+            result ^= a;
+        }
+        let carry = a >> 127;
+        a <<= 1;
+        if carry == 1 {
+            a ^= MODULUS;
+        }
+        b >>= 1;
+    }
+    result
+}
+
+/// Packs up to 128 bits into a field element, the first bit becomes the coefficient of x^0.
+pub fn gf128_from_bits<I>(bits: I) -> u128
+where
+    I: IntoIterator<Item = bool>,
+{
+    bits.into_iter()
+        .take(128)
+        .enumerate()
+        .fold(0, |acc, (i, b)| acc | ((b as u128) << i))
+}
+
+/// Little endian encoding of a field element.
+pub fn gf128_to_bytes(a: u128) -> [u8; 16] {
+    let mut bytes: [u8; 16] = Default::default();
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (a >> (8 * i)) as u8;
+    }
+    bytes
+}
+
+/// Inverse of `gf128_to_bytes`.
+pub fn gf128_from_bytes(bytes: &[u8; 16]) -> u128 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |acc, &byte| (acc << 8) | byte as u128)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{thread_rng, Rng};
+
+    fn random_element() -> u128 {
+        let mut bytes: [u8; 16] = Default::default();
+        thread_rng().fill(&mut bytes);
+        gf128_from_bytes(&bytes)
+    }
+
+    #[test]
+    fn gf128_reduction() {
+        // x^127 * x = x^128 = x^7 + x^2 + x + 1
+        assert_eq!(gf128_mul(1 << 127, 2), 0x87);
+        assert_eq!(gf128_mul(1 << 127, 1 << 127), gf128_mul(0x87, 1 << 126));
+    }
+
+    #[test]
+    fn gf128_field_laws() {
+        for _ in 0..100 {
+            let (a, b, c) = (random_element(), random_element(), random_element());
+            assert_eq!(gf128_mul(a, 1), a);
+            assert_eq!(gf128_mul(a, b), gf128_mul(b, a));
+            assert_eq!(gf128_mul(gf128_mul(a, b), c), gf128_mul(a, gf128_mul(b, c)));
+            assert_eq!(gf128_mul(a, b ^ c), gf128_mul(a, b) ^ gf128_mul(a, c));
+        }
+    }
+
+    #[test]
+    fn gf128_encoding() {
+        let a = random_element();
+        assert_eq!(gf128_from_bytes(&gf128_to_bytes(a)), a);
+        let bits: Vec<bool> = (0..128).map(|i| (a >> i) & 1 == 1).collect();
+        assert_eq!(gf128_from_bits(bits), a);
+    }
+}
@@ -1,3 +1,6 @@
+pub mod bitmatrix;
+pub mod chunks;
 pub mod digest;
+pub mod gf128;
 #[macro_use]
 pub mod util;
@@ -1,7 +1,12 @@
 use super::digest::{ArbitraryDigest, Digest};
 use bit_vec::BitVec;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use curve25519_dalek::traits::Identity;
+use byte_tools::{read_u64_be, write_u64_be};
+use crate::errors::*;
 use generic_array::GenericArray;
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use rand::{distributions::Alphanumeric, thread_rng, CryptoRng, Rng, RngCore};
 
 // simple macro to make closures and cloning less tedious
 // as seen on https://github.com/rust-webplatform/rust-todomvc/blob/master/src/main.rs#L142
@@ -45,7 +50,7 @@ pub fn generate_random_string_pairs(n: usize, pair_num: usize) -> Vec<(String, S
 pub fn generate_random_choices(num: usize) -> BitVec {
     let mut rng = thread_rng();
     let mut len = num / 8;
-    if len % 8 != 0 {
+    if !len.is_multiple_of(8) {
         len += 1;
     }
     let mut v = Vec::with_capacity(len);
@@ -61,13 +66,26 @@ where
 {
     hasher.input(data);
     let mut byte_len = length / 8;
-    if length % 8 != 0 {
+    if !length.is_multiple_of(8) {
         byte_len += 1;
     }
     let v = hasher.result(byte_len);
     bv_truncate(&v, length)
 }
 
+/// OT extensions expand the same seeds on every call, so both the expansion and the row hashes
+/// have to be bound to a batch counter. Otherwise a second batch would reuse the PRG output of the first.
+/// Returns a copy of `hasher` seeded with the current batch and advances the counter.
+pub fn batch_hasher<A>(hasher: &A, batch: &mut usize) -> A
+where
+    A: ArbitraryDigest + Clone,
+{
+    let mut hasher = hasher.clone();
+    hasher.input(&(*batch as u64).to_be_bytes());
+    *batch += 1;
+    hasher
+}
+
 pub fn hash<D>(mut hasher: D, val: BitVec) -> GenericArray<u8, D::OutputSize>
 where
     D: Digest,
@@ -75,3 +93,65 @@ where
     hasher.input(&val.to_bytes());
     hasher.result()
 }
+
+/// A uniformly random scalar. `Scalar::random` of curve25519-dalek expects an rng of a newer
+/// rand version than ours, so we reduce 64 random bytes like it does.
+pub fn random_scalar<R: RngCore + CryptoRng>(rng: &mut R) -> Scalar {
+    let mut bytes = [0; 64];
+    rng.fill_bytes(&mut bytes);
+    Scalar::from_bytes_mod_order_wide(&bytes)
+}
+
+/// A uniformly random Ristretto point, see `random_scalar`.
+pub fn random_ristretto<R: RngCore + CryptoRng>(rng: &mut R) -> RistrettoPoint {
+    let mut bytes = [0; 64];
+    rng.fill_bytes(&mut bytes);
+    RistrettoPoint::from_uniform_bytes(&bytes)
+}
+
+/// Decodes a received Ristretto point. Fails with `ErrorKind::PointError` for wrong lengths,
+/// non-canonical encodings (decompression rejects them) and the identity.
+pub fn decompress_ristretto(buf: &[u8]) -> Result<RistrettoPoint> {
+    if buf.len() != 32 {
+        return Err(ErrorKind::PointError.into());
+    }
+    let p = CompressedRistretto(*array_ref![buf, 0, 32])
+        .decompress()
+        .ok_or_else(|| Error::from(ErrorKind::PointError))?;
+    if p == RistrettoPoint::identity() {
+        return Err(ErrorKind::PointError.into());
+    }
+    Ok(p)
+}
+
+/// Packs several buffers into a single one, each prefixed by its length as big endian u64.
+/// This is used by batched protocols to send many messages in one frame.
+pub fn pack(buffers: &[Vec<u8>]) -> Vec<u8> {
+    let mut packed = Vec::with_capacity(buffers.iter().map(|b| b.len() + 8).sum());
+    let mut len = [0; 8];
+    for buf in buffers {
+        write_u64_be(&mut len, buf.len() as u64);
+        packed.extend_from_slice(&len);
+        packed.extend_from_slice(buf);
+    }
+    packed
+}
+
+/// Inverse of `pack`, fails if the lengths do not match the packed buffer.
+pub fn unpack(mut packed: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut buffers = Vec::new();
+    while !packed.is_empty() {
+        if packed.len() < 8 {
+            bail!("Packed buffer is truncated");
+        }
+        let len = read_u64_be(&packed[..8]);
+        packed = &packed[8..];
+        if len > packed.len() as u64 {
+            bail!("Packed buffer is truncated");
+        }
+        let (buf, rest) = packed.split_at(len as usize);
+        buffers.push(buf.to_vec());
+        packed = rest;
+    }
+    Ok(buffers)
+}
@@ -7,9 +7,9 @@
 //! ## Structure
 //!
 //! At the moment this library is split in two: one synchronous implementation and one asynchronous.
-//! The asynchronous part is suited to run on the browser, where only one thread is avalable. The synchronous part can be
-//! used on native hosts. As of this writing, incompatibilities between tokio and the futures library forbid
-//! asynchronous OT on a native host. We hope to change this as soon as tokio gets updated.  
+//! The asynchronous part is built on `std::future` and runs in the browser, where only one thread is avalable,
+//! as well as on native hosts with tokio, over `tokio::net::TcpStream` or `tokio-tungstenite` websockets.
+//! The synchronous part can only be used on native hosts.
 //!
 //! As OT requires some sort of public-key-cryptography (CITE, is this correct like this?) it's speed always is a hindering factor.
 //! It has been shown though (CITE) that one can extend a set of basic OT transfers to transfer a much larger amount of data
@@ -17,40 +17,48 @@
 //!
 //! Henceforth we have implemented the OT-variant SimpleOT by Chou and Orlandi (CITE), the
 //! semi-honest OT-extension protocol of Ishai et al. (CITE) and a malicious-secure augmentation
-//! of the latter by Asharaov et al. (CITE).
+//! of the latter by Asharaov et al. (CITE). SimpleOT is also available over the prime order
+//! Ristretto group, which makes the cofactor handling of the Edwards version unnecessary.
 //!
 //! Recent revelations (CITE) have shown that SimpleOT is not malicious secure and as such
 //! composing it with the OT extension of Asharaov will *not* provide security against active adversaries.
+//! For this purpose the endemic OT of Masny and Rindal (CITE) is provided as an alternative base OT.
+//! It is a drop-in replacement for SimpleOT wherever 1-out-of-2 transfers are needed.
+//! As a faster alternative to the extension of Asharov et al. the actively secure extension of
+//! Keller, Orsini and Scholl (CITE) is provided, which only adds a single correlation check to IKNP.
+//! The 1-out-of-n OT of Naor and Pinkas (CITE), which many other MPC frameworks use as base OT,
+//! can be plugged into the OT extensions just like SimpleOT.
+//! The extension of Ishai et al. can use fixed-key AES instead of SHA3 to expand its seeds and
+//! hash the rows of its matrix, which is considerably faster, especially with AES-NI.
+//! For 1-out-of-n transfers the extension of Kolesnikov and Kumaresan (CITE) is provided.
+//!
+//! All protocols assume an authenticated channel between the parties. On native hosts
+//! `sync::communication::secure::SecureChannel` provides one with a Noise handshake over any connection.
+//! They also assume that both parties were set up with the same parameters, `sync::session::Session`
+//! checks this before a protocol runs and derives a session id to bind the hashes of the protocol to.
+//! A peer that stops responding would block a protocol forever, `sync::communication::deadline`
+//! provides timeouts and cancellation for TCP and websocket connections.
 
 #![recursion_limit = "1024"]
-#![feature(generators)]
-#![feature(iterator_flatten)]
-#![feature(int_to_from_bytes)]
-#![feature(iterator_repeat_with)]
 // TODO: remove this when error_chain crate is fixed
 #![allow(renamed_and_removed_lints)]
 
-// #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
-// extern crate tokio;
-
-// #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
-// extern crate futures;
-#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
-extern crate futures_channel;
-#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
-extern crate futures_core;
-#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
-extern crate futures_util;
+extern crate futures;
 
 #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 extern crate ring;
 #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+extern crate tokio;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+extern crate tokio_tungstenite;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 extern crate tungstenite;
 
 #[macro_use]
 extern crate arrayref;
 
 extern crate bit_vec;
+extern crate byte_tools;
 
 extern crate curve25519_dalek;
 extern crate generic_array;
@@ -64,7 +72,13 @@ extern crate itertools;
 // extern crate rust_sodium;
 
 #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
-extern crate stdweb;
+extern crate js_sys;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+extern crate wasm_bindgen;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+extern crate wasm_bindgen_futures;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+extern crate web_sys;
 
 #[macro_use]
 extern crate error_chain;
@@ -89,19 +103,37 @@ pub mod errors {
                 description("Error while communicating")
                 display("Error while communicating")
             }
+            ConsistencyCheckFailed {
+                description("Consistency check failed, the other party cheated or the connection is corrupted")
+                display("Consistency check failed, the other party cheated or the connection is corrupted")
+            }
+            FrameTooLarge(len: u64, max: usize) {
+                description("Frame exceeds the maximum frame size")
+                display("Frame of {} bytes exceeds the maximum frame size of {} bytes", len, max)
+            }
+            SessionMismatch(parameter: String, local: String, remote: String) {
+                description("Session parameters of both parties do not match")
+                display("Session parameter {} does not match: {} here, {} at the other party", parameter, local, remote)
+            }
+            Timeout {
+                description("Operation timed out")
+                display("Operation timed out")
+            }
+            Cancelled {
+                description("Operation was cancelled")
+                display("Operation was cancelled")
+            }
         }
     }
 }
 
 //extern crate block_cipher_trait;
 // TODO use fast CSPRG, the os_rng used takes very long to generate a value as it creates one from the os entropy pool
-// TODO channel should be authenticated, but not necessarily confidential, use TLS?
 // TODO transmit length, probably with aes-gcm ad (without any crypto value, would be convinient)
 // TODO for now, async only works on the client
 #[macro_use]
 pub mod common;
 
-#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
-pub mod async;
+pub mod r#async;
 #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 pub mod sync;
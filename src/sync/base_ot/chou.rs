@@ -4,14 +4,16 @@ use curve25519_dalek::constants::{ED25519_BASEPOINT_TABLE, EIGHT_TORSION};
 use curve25519_dalek::edwards::*;
 use curve25519_dalek::scalar::*;
 
-use common::digest::Digest;
-use errors::*;
+use crate::common::digest::{Digest, Separable};
+use crate::common::util::{pack, random_scalar, unpack};
+use crate::errors::*;
 use generic_array::{ArrayLength, GenericArray};
 use rand::{CryptoRng, RngCore};
 use std::iter::Iterator;
 use std::vec::Vec;
-use sync::communication::{BinaryReceive, BinarySend, GetConn};
-use sync::crypto::{SymmetricDecryptor, SymmetricEncryptor};
+use crate::sync::communication::{BinaryReceive, BinarySend, GetConn};
+use crate::sync::crypto::{SymmetricDecryptor, SymmetricEncryptor};
+use crate::sync::session::{self, Session};
 
 fn receive_point<T>(conn: &mut T) -> Result<EdwardsPoint>
 where
@@ -21,10 +23,15 @@ where
     if v.len() != 32 {
         return Err(ErrorKind::PointError.into());
     }
-    let mut buf: [u8; 32] = Default::default();
-    buf.copy_from_slice(&v);
-    CompressedEdwardsY(buf)
+    decompress_point(&v)
+}
+
+/// Points of small order are rejected, they vanish when multiplied by the cofactor,
+/// which would leave the derived keys without the randomness of the other party.
+fn decompress_point(buf: &[u8]) -> Result<EdwardsPoint> {
+    CompressedEdwardsY(*array_ref![buf, 0, 32])
         .decompress()
+        .filter(|p| !p.is_small_order())
         .ok_or_else(|| ErrorKind::PointError.into())
 }
 
@@ -72,12 +79,28 @@ impl<
         S: SymmetricEncryptor<L>,
     > ChouOrlandiOTSender<T, D, L, S>
 {
+    /// Like `new`, with the hash function bound to `session`, see `Session::check_base_ot`.
+    pub fn in_session<R>(
+        conn: T,
+        session: &Session,
+        hasher: D,
+        encryptor: S,
+        rng: R,
+    ) -> Result<Self>
+    where
+        R: RngCore + CryptoRng,
+        D: Separable,
+    {
+        session.check_base_ot::<D, S>(session::CHOU_ORLANDI)?;
+        Self::new(conn, session.bind(hasher), encryptor, rng)
+    }
+
     pub fn new<R>(mut conn: T, mut hasher: D, encryptor: S, mut rng: R) -> Result<Self>
     where
         R: RngCore + CryptoRng,
     {
-        let y = Scalar::random(&mut rng);
-        let mut s = &y * &ED25519_BASEPOINT_TABLE;
+        let y = random_scalar(&mut rng);
+        let mut s = &y * ED25519_BASEPOINT_TABLE;
 
         // we dont send s directly, instead we add a point from the eight torsion subgroup.
         // This enables the receiver to verify that s is in the subgroup of the twisted edwards curve
@@ -96,21 +119,39 @@ impl<
     }
 
     pub fn compute_keys(&mut self, n: u64) -> Result<Vec<GenericArray<u8, L>>> {
+        let r = receive_point(&mut self.conn)?;
+        Ok(self.keys_for_point(r, n))
+    }
+
+    /// Computes the keys for many transfers at once, all points of the receiver
+    /// arrive in a single message.
+    pub fn compute_keys_many(&mut self, ns: &[u64]) -> Result<Vec<Vec<GenericArray<u8, L>>>> {
+        let buf = self.conn.receive()?;
+        if buf.len() != 32 * ns.len() {
+            return Err(ErrorKind::PointError.into());
+        }
+        buf.chunks(32)
+            .zip(ns)
+            .map(|(p, &n)| Ok(self.keys_for_point(decompress_point(p)?, n)))
+            .collect()
+    }
+
+    fn keys_for_point(&self, r: EdwardsPoint, n: u64) -> Vec<GenericArray<u8, L>> {
         let mut hasher = self.hasher.clone();
-        let r = receive_point(&mut self.conn)?.mul_by_cofactor();
+        let r = r.mul_by_cofactor();
         // seed the hash function with s and r in its compressed form
         hasher.input(r.compress().as_bytes());
-        Ok((0..n)
+        (0..n)
             .map(|j| {
                 // hash p=64yR - 64jT, this will reduce to 64xS if c == j, but as x is only known
                 // to the receiver (provided the discrete logartihm problem is hard in our curve)
                 // the sender does not know c.
-                let p = self.y * r - Scalar::from_u64(j) * self.t64;
+                let p = self.y * r - Scalar::from(j) * self.t64;
                 let mut hasher = hasher.clone();
                 hasher.input(p.compress().as_bytes());
                 hasher.result()
             })
-            .collect())
+            .collect()
     }
 }
 
@@ -131,6 +172,20 @@ impl<
         }
         Ok(())
     }
+
+    /// All transfers share our point S, the receiver sends all of its points in one message
+    /// and we answer with all ciphertexts in one message.
+    fn send_many(&mut self, values: Vec<Vec<&[u8]>>) -> Result<()> {
+        let ns: Vec<u64> = values.iter().map(|v| v.len() as u64).collect();
+        let keys = self.compute_keys_many(&ns)?;
+        let mut ciphertexts = Vec::with_capacity(ns.iter().sum::<u64>() as usize);
+        for (keys, values) in keys.into_iter().zip(values) {
+            for (key, value) in keys.into_iter().zip(values) {
+                ciphertexts.push(self.encryptor.encrypt(&key, value.to_owned())?);
+            }
+        }
+        self.conn.send(&pack(&ciphertexts))
+    }
 }
 
 #[derive(Clone)]
@@ -157,6 +212,15 @@ impl<
         S: SymmetricDecryptor<L>,
     > ChouOrlandiOTReceiver<T, R, D, L, S>
 {
+    /// Like `new`, with the hash function bound to `session`, see `Session::check_base_ot`.
+    pub fn in_session(conn: T, session: &Session, hasher: D, decryptor: S, rng: R) -> Result<Self>
+    where
+        D: Separable,
+    {
+        session.check_base_ot::<D, S>(session::CHOU_ORLANDI)?;
+        Self::new(conn, session.bind(hasher), decryptor, rng)
+    }
+
     pub fn new(mut conn: T, mut hasher: D, decryptor: S, rng: R) -> Result<Self> {
         let mut s = receive_point(&mut conn)?;
         // as we've added a point from the eight torsion subgroup to s before sending,
@@ -176,20 +240,39 @@ impl<
     }
 
     pub fn compute_key(&mut self, c: u64) -> Result<GenericArray<u8, L>> {
-        let mut hasher = self.hasher.clone();
-        let x = Scalar::random(&mut self.rng);
-        let r = Scalar::from_u64(c) * self.s8 + (&x * &ED25519_BASEPOINT_TABLE).mul_by_cofactor();
+        let (r, key) = self.point_and_key(c);
+        send_point(&mut self.conn, r)?;
+        Ok(key)
+    }
 
-        send_point(&mut self.conn, r + EIGHT_TORSION[1])?;
+    /// Computes the keys for many transfers at once, all our points are sent in a single message.
+    pub fn compute_keys_many(&mut self, cs: &[u64]) -> Result<Vec<GenericArray<u8, L>>> {
+        let mut points = Vec::with_capacity(32 * cs.len());
+        let mut keys = Vec::with_capacity(cs.len());
+        for &c in cs {
+            let (r, key) = self.point_and_key(c);
+            points.extend_from_slice(r.compress().as_bytes());
+            keys.push(key);
+        }
+        self.conn.send(&points)?;
+        Ok(keys)
+    }
+
+    /// Returns the point R to send for choice c (with a point of the eight torsion subgroup added)
+    /// and the resulting key.
+    fn point_and_key(&mut self, c: u64) -> (EdwardsPoint, GenericArray<u8, L>) {
+        let mut hasher = self.hasher.clone();
+        let x = random_scalar(&mut self.rng);
+        let r = Scalar::from(c) * self.s8 + (&x * ED25519_BASEPOINT_TABLE).mul_by_cofactor();
 
         // seed the hash function with s and r in it's compressed form
         hasher.input(r.mul_by_cofactor().compress().as_bytes());
 
         // hash p = 64xS
         // TODO: is it better to use mul_by_cofactor?
-        let p = (x * Scalar::from_u64(8)) * self.s8;
+        let p = (x * Scalar::from(8u64)) * self.s8;
         hasher.input(p.compress().as_bytes());
-        Ok(hasher.result())
+        (r + EIGHT_TORSION[1], hasher.result())
     }
 }
 
@@ -210,6 +293,24 @@ impl<
         let buf = self.decryptor.decrypt(&key, buffers.remove(index))?;
         Ok(buf)
     }
+
+    /// See `ChouOrlandiOTSender::send_many`.
+    fn receive_many(&mut self, indices: &[usize], n: usize) -> Result<Vec<Vec<u8>>> {
+        if n == 0 || indices.iter().any(|&index| index >= n) {
+            bail!("index out of bounds");
+        }
+        let cs: Vec<u64> = indices.iter().map(|&index| index as u64).collect();
+        let keys = self.compute_keys_many(&cs)?;
+        let ciphertexts = unpack(&self.conn.receive()?)?;
+        if ciphertexts.len() != n * indices.len() {
+            bail!("Received wrong number of ciphertexts");
+        }
+        let mut result = Vec::with_capacity(indices.len());
+        for ((key, &index), chunk) in keys.iter().zip(indices).zip(ciphertexts.chunks(n)) {
+            result.push(self.decryptor.decrypt(key, chunk[index].clone())?);
+        }
+        Ok(result)
+    }
 }
 
 impl<
@@ -227,8 +328,8 @@ impl<
 #[cfg(test)]
 mod tests {
     use super::*;
-    use common::digest::sha3::SHA3_256;
-    use common::util::create_random_strings;
+    use crate::common::digest::sha3::SHA3_256;
+    use crate::common::util::create_random_strings;
     use rand::{thread_rng, ChaChaRng, FromEntropy, Rng};
     use std::net::TcpListener;
     use std::net::TcpStream;
@@ -236,14 +337,15 @@ mod tests {
     use std::thread;
     use std::time::Duration;
     use std::time::Instant;
-    use sync::base_ot::{BaseOTReceiver, BaseOTSender};
-    use sync::communication::corrupted::CorruptedChannel;
-    use sync::crypto::{
+    use crate::sync::base_ot::{BaseOTReceiver, BaseOTSender};
+    use crate::sync::communication::adversary::AdversaryChannel;
+    use crate::sync::communication::memory::channel_pair;
+    use crate::sync::crypto::{
         aes::AesCryptoProvider,
         dummy::DummyCryptoProvider, //sodium::SodiumCryptoProvider,
     };
-    use tungstenite::client::connect;
-    use tungstenite::server::accept;
+    use tungstenite::connect;
+    use tungstenite::accept;
     use url::Url;
 
     macro_rules! generate_communication_test {
@@ -284,12 +386,9 @@ mod tests {
     #[test]
     pub fn chou_ot_key_exchange() {
         let index = 3;
+        let (server_conn, client_conn) = channel_pair();
         let server = thread::spawn(move || {
-            let stream = TcpListener::bind("127.0.0.1:1236")
-                .unwrap()
-                .accept()
-                .unwrap()
-                .0;
+            let stream = server_conn;
             let rng = ChaChaRng::from_entropy();
             let mut now = Instant::now();
             let mut ot = ChouOrlandiOTSender::new(
@@ -305,7 +404,7 @@ mod tests {
             keys
         });
         let client = thread::spawn(move || {
-            let stream = TcpStream::connect("127.0.0.1:1236").unwrap();
+            let stream = client_conn;
             let rng = ChaChaRng::from_entropy();
             let mut now = Instant::now();
             let mut ot = ChouOrlandiOTReceiver::new(
@@ -330,13 +429,10 @@ mod tests {
     fn chou_ot_key_exchange_multiple() {
         static INDICES: [u64; 5] = [5, 0, 9, 3, 7];
 
+        let (server_conn, client_conn) = channel_pair();
         let server = thread::spawn(move || {
             let mut ot = ChouOrlandiOTSender::new(
-                TcpListener::bind("127.0.0.1:1237")
-                    .unwrap()
-                    .accept()
-                    .unwrap()
-                    .0,
+                server_conn,
                 SHA3_256::default(),
                 DummyCryptoProvider::default(),
                 ChaChaRng::from_entropy(),
@@ -349,7 +445,7 @@ mod tests {
 
         let client = thread::spawn(move || {
             let mut ot = ChouOrlandiOTReceiver::new(
-                TcpStream::connect("127.0.0.1:1237").unwrap(),
+                client_conn,
                 SHA3_256::default(),
                 DummyCryptoProvider::default(),
                 ChaChaRng::from_entropy(),
@@ -371,13 +467,10 @@ mod tests {
     #[test]
     fn chou_ot_key_exchange_c0() {
         // given c == 0 and a poor implementation an attacker can infer that c is 0 given the transmitted R
+        let (server_conn, client_conn) = channel_pair();
         let server = thread::spawn(move || {
             let mut ot = ChouOrlandiOTSender::new(
-                TcpListener::bind("127.0.0.1:1238")
-                    .unwrap()
-                    .accept()
-                    .unwrap()
-                    .0,
+                server_conn,
                 SHA3_256::default(),
                 DummyCryptoProvider::default(),
                 ChaChaRng::from_entropy(),
@@ -389,7 +482,7 @@ mod tests {
         // see ChouOrlandiOTReceiver::new for why we add this eight torsion point), then the point is torsion free
         // if c = 0 and an attacker can infer that c is indeed 0
 
-        fn eavesdrop(_: &mut (), buf: &[u8]) {
+        fn eavesdrop(buf: &[u8]) {
             let mut new_buf: [u8; 32] = Default::default();
             new_buf.copy_from_slice(buf);
             assert!(
@@ -401,13 +494,10 @@ mod tests {
         }
 
         let client = thread::spawn(move || {
-            let corrupted_channel = CorruptedChannel::new_eavesdrop(
-                TcpStream::connect("127.0.0.1:1238").unwrap(),
-                (),
-                eavesdrop,
-            );
+            let mut adversary_channel = AdversaryChannel::new(client_conn, Vec::new());
+            adversary_channel.set_eavesdropper(eavesdrop);
             let mut ot = ChouOrlandiOTReceiver::new(
-                corrupted_channel,
+                adversary_channel,
                 SHA3_256::default(),
                 DummyCryptoProvider::default(),
                 ChaChaRng::from_entropy(),
@@ -420,15 +510,88 @@ mod tests {
         assert_eq!(hashes_sender[0], hash_receiver)
     }
 
+    #[test]
+    fn batched_transfer() {
+        let n = 4;
+        let indices: Vec<usize> = (0..20).map(|_| thread_rng().gen_range(0, n)).collect();
+        let values: Vec<Vec<String>> = (0..20).map(|_| create_random_strings(n, 64)).collect();
+        let indices2 = indices.clone();
+        let values2 = values.clone();
+
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let mut ot = ChouOrlandiOTSender::new(
+                server_conn,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).unwrap();
+            ot.send_many(
+                values2
+                    .iter()
+                    .map(|v| v.iter().map(|s| s.as_bytes()).collect())
+                    .collect(),
+            ).unwrap();
+        });
+
+        let client = thread::spawn(move || {
+            let mut ot = ChouOrlandiOTReceiver::new(
+                AdversaryChannel::new(client_conn, Vec::new()),
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).unwrap();
+            let result = ot.receive_many(&indices2, n).unwrap();
+            (result, ot.get_conn().frames_sent())
+        });
+        server.join().unwrap();
+        let (result, frames) = client.join().unwrap();
+
+        // all points of the receiver are sent in a single frame
+        assert_eq!(frames, 1);
+        for ((r, v), &i) in result.iter().zip(&values).zip(&indices) {
+            assert_eq!(r.as_slice(), v[i].as_bytes());
+        }
+    }
+
+    #[test]
+    fn receive_many_rejects_zero_values() {
+        let (server_conn, client_conn) = channel_pair();
+        let _ot = ChouOrlandiOTSender::new(
+            server_conn,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            ChaChaRng::from_entropy(),
+        ).unwrap();
+        let mut ot = ChouOrlandiOTReceiver::new(
+            client_conn,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            ChaChaRng::from_entropy(),
+        ).unwrap();
+        assert!(ot.receive_many(&[], 0).is_err());
+    }
+
+    #[test]
+    fn memory_with_aes_encryption() {
+        let (server_conn, client_conn) = channel_pair();
+        generate_communication_test!(
+            server_conn,
+            client_conn,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            AesCryptoProvider::default()
+        );
+    }
+
     #[test]
     fn tcp_with_dummy_encryption() {
+        // the system picks a free port, so the tests can run in parallel
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
         generate_communication_test!(
-            TcpListener::bind("127.0.0.1:1239")
-                .unwrap()
-                .accept()
-                .unwrap()
-                .0,
-            TcpStream::connect("127.0.0.1:1239").unwrap(),
+            listener.accept().unwrap().0,
+            TcpStream::connect(addr).unwrap(),
             SHA3_256::default(),
             DummyCryptoProvider::default(),
             DummyCryptoProvider::default()
@@ -452,15 +615,11 @@ mod tests {
 
     #[test]
     fn websocket_with_dummy_encryption() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
         generate_communication_test!(
-            accept(
-                TcpListener::bind("127.0.0.1:1241")
-                    .unwrap()
-                    .accept()
-                    .unwrap()
-                    .0
-            ).unwrap(),
-            connect(Url::parse("ws://localhost:1241/socket").unwrap())
+            accept(listener.accept().unwrap().0).unwrap(),
+            connect(Url::parse(&format!("ws://{}/socket", addr)).unwrap())
                 .unwrap()
                 .0,
             SHA3_256::default(),
@@ -490,15 +649,11 @@ mod tests {
 
     #[test]
     fn websocket_with_aes_gcm_encryption() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
         generate_communication_test!(
-            accept(
-                TcpListener::bind("127.0.0.1:1243")
-                    .unwrap()
-                    .accept()
-                    .unwrap()
-                    .0
-            ).unwrap(),
-            connect(Url::parse("ws://localhost:1243/socket").unwrap())
+            accept(listener.accept().unwrap().0).unwrap(),
+            connect(Url::parse(&format!("ws://{}/socket", addr)).unwrap())
                 .unwrap()
                 .0,
             SHA3_256::default(),
@@ -0,0 +1,426 @@
+//! chou and orlandis 1-out-of-n OT [https://eprint.iacr.org/2015/267.pdf] in the Ristretto group
+//!
+//! Ristretto is a prime order group built on top of curve25519, every valid encoding
+//! is canonical and corresponds to exactly one group element. Contrary to the version in `chou`
+//! we do not need any cofactor handling: received points only have to be checked for the identity.
+//! The protocol is not compatible with the Edwards version.
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_TABLE;
+use curve25519_dalek::ristretto::*;
+use curve25519_dalek::scalar::*;
+
+use crate::common::digest::{Digest, Separable};
+use crate::common::util::{decompress_ristretto, pack, random_scalar, unpack};
+use crate::errors::*;
+use generic_array::{ArrayLength, GenericArray};
+use rand::{CryptoRng, RngCore};
+use std::vec::Vec;
+use crate::sync::communication::{BinaryReceive, BinarySend, GetConn};
+use crate::sync::crypto::{SymmetricDecryptor, SymmetricEncryptor};
+use crate::sync::session::{self, Session};
+
+fn receive_point<T>(conn: &mut T) -> Result<RistrettoPoint>
+where
+    T: BinaryReceive,
+{
+    let v = conn.receive()?;
+    decompress_ristretto(&v)
+}
+
+fn send_point<T>(conn: &mut T, p: &RistrettoPoint) -> Result<()>
+where
+    T: BinarySend,
+{
+    conn.send(p.compress().as_bytes())?;
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct ChouOrlandiRistrettoOTSender<T, D, L, S>
+where
+    T: BinarySend + BinaryReceive,
+    D: Digest<OutputSize = L> + Clone,
+    L: ArrayLength<u8>,
+    S: SymmetricEncryptor<L>,
+{
+    pub conn: T,
+    hasher: D,
+    encryptor: S,
+    y: Scalar,
+    t: RistrettoPoint,
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > GetConn<T> for ChouOrlandiRistrettoOTSender<T, D, L, S>
+{
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+impl<
+        T: BinarySend + BinaryReceive,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > ChouOrlandiRistrettoOTSender<T, D, L, S>
+{
+    /// Like `new`, with the hash function bound to `session`, see `Session::check_base_ot`.
+    pub fn in_session<R>(
+        conn: T,
+        session: &Session,
+        hasher: D,
+        encryptor: S,
+        rng: R,
+    ) -> Result<Self>
+    where
+        R: RngCore + CryptoRng,
+        D: Separable,
+    {
+        session.check_base_ot::<D, S>(session::CHOU_ORLANDI_RISTRETTO)?;
+        Self::new(conn, session.bind(hasher), encryptor, rng)
+    }
+
+    pub fn new<R>(mut conn: T, mut hasher: D, encryptor: S, mut rng: R) -> Result<Self>
+    where
+        R: RngCore + CryptoRng,
+    {
+        let y = random_scalar(&mut rng);
+        let s = &y * RISTRETTO_BASEPOINT_TABLE;
+        send_point(&mut conn, &s)?;
+        hasher.input(s.compress().as_bytes());
+        Ok(ChouOrlandiRistrettoOTSender {
+            conn,
+            hasher,
+            encryptor,
+            y,
+            t: y * s,
+        })
+    }
+
+    pub fn compute_keys(&mut self, n: u64) -> Result<Vec<GenericArray<u8, L>>> {
+        let r = receive_point(&mut self.conn)?;
+        Ok(self.keys_for_point(&r, n))
+    }
+
+    /// Computes the keys for many transfers at once, all points of the receiver
+    /// arrive in a single message.
+    pub fn compute_keys_many(&mut self, ns: &[u64]) -> Result<Vec<Vec<GenericArray<u8, L>>>> {
+        let buf = self.conn.receive()?;
+        if buf.len() != 32 * ns.len() {
+            return Err(ErrorKind::PointError.into());
+        }
+        buf.chunks(32)
+            .zip(ns)
+            .map(|(p, &n)| Ok(self.keys_for_point(&decompress_ristretto(p)?, n)))
+            .collect()
+    }
+
+    fn keys_for_point(&self, r: &RistrettoPoint, n: u64) -> Vec<GenericArray<u8, L>> {
+        let mut hasher = self.hasher.clone();
+        // seed the hash function with s and r in its compressed form
+        hasher.input(r.compress().as_bytes());
+        let yr = self.y * r;
+        (0..n)
+            .map(|j| {
+                // hash p = yR - jT, this will reduce to xS if c == j
+                let p = yr - Scalar::from(j) * self.t;
+                let mut hasher = hasher.clone();
+                hasher.input(p.compress().as_bytes());
+                hasher.result()
+            })
+            .collect()
+    }
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > super::BaseOTSender for ChouOrlandiRistrettoOTSender<T, D, L, S>
+{
+    fn send(&mut self, values: Vec<&[u8]>) -> Result<()> {
+        let keys = self.compute_keys(values.len() as u64)?;
+        for (key, value) in keys.into_iter().zip(values) {
+            let buf = self.encryptor.encrypt(&key, value.to_owned())?;
+            self.conn.send(&buf)?;
+        }
+        Ok(())
+    }
+
+    fn send_many(&mut self, values: Vec<Vec<&[u8]>>) -> Result<()> {
+        let ns: Vec<u64> = values.iter().map(|v| v.len() as u64).collect();
+        let keys = self.compute_keys_many(&ns)?;
+        let mut ciphertexts = Vec::with_capacity(ns.iter().sum::<u64>() as usize);
+        for (keys, values) in keys.into_iter().zip(values) {
+            for (key, value) in keys.into_iter().zip(values) {
+                ciphertexts.push(self.encryptor.encrypt(&key, value.to_owned())?);
+            }
+        }
+        self.conn.send(&pack(&ciphertexts))
+    }
+}
+
+#[derive(Clone)]
+pub struct ChouOrlandiRistrettoOTReceiver<T, R, D, L, S>
+where
+    T: BinaryReceive + BinarySend,
+    R: RngCore + CryptoRng,
+    D: Digest<OutputSize = L> + Clone,
+    L: ArrayLength<u8>,
+    S: SymmetricDecryptor<L>,
+{
+    pub conn: T,
+    hasher: D,
+    decryptor: S,
+    rng: R,
+    s: RistrettoPoint,
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > ChouOrlandiRistrettoOTReceiver<T, R, D, L, S>
+{
+    /// Like `new`, with the hash function bound to `session`, see `Session::check_base_ot`.
+    pub fn in_session(conn: T, session: &Session, hasher: D, decryptor: S, rng: R) -> Result<Self>
+    where
+        D: Separable,
+    {
+        session.check_base_ot::<D, S>(session::CHOU_ORLANDI_RISTRETTO)?;
+        Self::new(conn, session.bind(hasher), decryptor, rng)
+    }
+
+    pub fn new(mut conn: T, mut hasher: D, decryptor: S, rng: R) -> Result<Self> {
+        // an identity S would make all keys of the receiver known to everyone
+        let s = receive_point(&mut conn)?;
+        hasher.input(s.compress().as_bytes());
+        Ok(ChouOrlandiRistrettoOTReceiver {
+            conn,
+            hasher,
+            decryptor,
+            rng,
+            s,
+        })
+    }
+
+    pub fn compute_key(&mut self, c: u64) -> Result<GenericArray<u8, L>> {
+        let (r, key) = self.point_and_key(c);
+        send_point(&mut self.conn, &r)?;
+        Ok(key)
+    }
+
+    /// Computes the keys for many transfers at once, all our points are sent in a single message.
+    pub fn compute_keys_many(&mut self, cs: &[u64]) -> Result<Vec<GenericArray<u8, L>>> {
+        let mut points = Vec::with_capacity(32 * cs.len());
+        let mut keys = Vec::with_capacity(cs.len());
+        for &c in cs {
+            let (r, key) = self.point_and_key(c);
+            points.extend_from_slice(r.compress().as_bytes());
+            keys.push(key);
+        }
+        self.conn.send(&points)?;
+        Ok(keys)
+    }
+
+    /// Returns the point R = cS + xG to send for choice c and the resulting key H(S, R, xS).
+    fn point_and_key(&mut self, c: u64) -> (RistrettoPoint, GenericArray<u8, L>) {
+        let mut hasher = self.hasher.clone();
+        let x = random_scalar(&mut self.rng);
+        let r = Scalar::from(c) * self.s + &x * RISTRETTO_BASEPOINT_TABLE;
+        hasher.input(r.compress().as_bytes());
+        hasher.input((x * self.s).compress().as_bytes());
+        (r, hasher.result())
+    }
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > super::BaseOTReceiver for ChouOrlandiRistrettoOTReceiver<T, R, D, L, S>
+{
+    fn receive(&mut self, index: usize, n: usize) -> Result<Vec<u8>> {
+        if index >= n {
+            bail!("index out of bounds");
+        }
+        let key = self.compute_key(index as u64)?;
+        let mut buffers: Vec<Vec<u8>> = Vec::with_capacity(n);
+        for _ in 0..n {
+            buffers.push(self.conn.receive()?);
+        }
+        self.decryptor.decrypt(&key, buffers.remove(index))
+    }
+
+    fn receive_many(&mut self, indices: &[usize], n: usize) -> Result<Vec<Vec<u8>>> {
+        if n == 0 || indices.iter().any(|&index| index >= n) {
+            bail!("index out of bounds");
+        }
+        let cs: Vec<u64> = indices.iter().map(|&index| index as u64).collect();
+        let keys = self.compute_keys_many(&cs)?;
+        let ciphertexts = unpack(&self.conn.receive()?)?;
+        if ciphertexts.len() != n * indices.len() {
+            bail!("Received wrong number of ciphertexts");
+        }
+        let mut result = Vec::with_capacity(indices.len());
+        for ((key, &index), chunk) in keys.iter().zip(indices).zip(ciphertexts.chunks(n)) {
+            result.push(self.decryptor.decrypt(key, chunk[index].clone())?);
+        }
+        Ok(result)
+    }
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > GetConn<T> for ChouOrlandiRistrettoOTReceiver<T, R, D, L, S>
+{
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::digest::sha3::SHA3_256;
+    use crate::common::util::create_random_strings;
+    use rand::{thread_rng, ChaChaRng, FromEntropy, Rng};
+    use std::thread;
+    use crate::sync::base_ot::{BaseOTReceiver, BaseOTSender};
+    use crate::sync::communication::adversary::{AdversaryChannel, Attack};
+    use crate::sync::communication::memory::channel_pair;
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::crypto::dummy::DummyCryptoProvider;
+
+    #[test]
+    fn ristretto_key_exchange() {
+        let index = 7;
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let mut ot = ChouOrlandiRistrettoOTSender::new(
+                server_conn,
+                SHA3_256::default(),
+                DummyCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).unwrap();
+            ot.compute_keys(10).unwrap()
+        });
+        let client = thread::spawn(move || {
+            let mut ot = ChouOrlandiRistrettoOTReceiver::new(
+                client_conn,
+                SHA3_256::default(),
+                DummyCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).unwrap();
+            ot.compute_key(index).unwrap()
+        });
+        let keys = server.join().unwrap();
+        let key = client.join().unwrap();
+        assert_eq!(keys[index as usize], key);
+        for (i, k) in keys.iter().enumerate() {
+            if i != index as usize {
+                assert_ne!(*k, key);
+            }
+        }
+    }
+
+    #[test]
+    fn ristretto_tcp_with_aes_encryption() {
+        let n = 10;
+        let c = thread_rng().gen_range(0, n);
+        let values = create_random_strings(n, 512);
+        let values2 = values.clone();
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let mut ot = ChouOrlandiRistrettoOTSender::new(
+                server_conn,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).unwrap();
+            ot.send(values2.iter().map(|s| s.as_bytes()).collect())
+                .unwrap();
+            let batch: Vec<Vec<&[u8]>> = (0..5)
+                .map(|_| values2.iter().map(|s| s.as_bytes()).collect())
+                .collect();
+            ot.send_many(batch).unwrap();
+        });
+        let client = thread::spawn(move || {
+            let mut ot = ChouOrlandiRistrettoOTReceiver::new(
+                client_conn,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).unwrap();
+            let single = ot.receive(c, n).unwrap();
+            let many = ot.receive_many(&[c, 0, n - 1, c, 1], n).unwrap();
+            (single, many)
+        });
+        server.join().unwrap();
+        let (single, many) = client.join().unwrap();
+        assert_eq!(single, values[c].as_bytes());
+        for (r, &i) in many.iter().zip(&[c, 0, n - 1, c, 1]) {
+            assert_eq!(r.as_slice(), values[i].as_bytes());
+        }
+    }
+
+    fn receiver_with_point(encoding: [u8; 32]) -> Result<()> {
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let stream = server_conn;
+            ChouOrlandiRistrettoOTSender::new(
+                stream,
+                SHA3_256::default(),
+                DummyCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).map(|_| ())
+        });
+        // replaces the first received frame (the point S of the sender) with the given encoding
+        let replace_point = Attack::modify(move |index, frame| {
+            if index == 0 {
+                frame.copy_from_slice(&encoding);
+            }
+        });
+        let conn = AdversaryChannel::new(client_conn, vec![replace_point]);
+        let result = ChouOrlandiRistrettoOTReceiver::new(
+            conn,
+            SHA3_256::default(),
+            DummyCryptoProvider::default(),
+            ChaChaRng::from_entropy(),
+        ).map(|_| ());
+        server.join().unwrap().unwrap();
+        result
+    }
+
+    #[test]
+    fn ristretto_rejects_identity() {
+        // the canonical encoding of the identity consists of zeros only
+        match receiver_with_point([0; 32]) {
+            Err(Error(ErrorKind::PointError, _)) => (),
+            r => panic!("identity was accepted: {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn ristretto_rejects_non_canonical_encoding() {
+        // field elements >= p are never canonical
+        match receiver_with_point([0xff; 32]) {
+            Err(Error(ErrorKind::PointError, _)) => (),
+            r => panic!("non-canonical encoding was accepted: {:?}", r.map(|_| ())),
+        }
+    }
+}
@@ -0,0 +1,538 @@
+//! Masny and Rindal's endemic 1-out-of-2 OT [https://eprint.iacr.org/2019/706.pdf]
+//!
+//! Contrary to SimpleOT this protocol is secure against active adversaries (in the random oracle model).
+//! The receiver masks the public key of a key agreement with the hash of a random group element,
+//! the sender can not tell which of the two received elements hides the real key and the receiver
+//! can not know the secret keys of both unmasked elements at once.
+//! We operate in the prime order Ristretto group so no cofactor handling is needed.
+//!
+//! `send_many` and `receive_many` combine the points of all transfers into a single frame
+//! and the ciphertexts into another one.
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_TABLE;
+use curve25519_dalek::ristretto::*;
+use curve25519_dalek::scalar::*;
+use curve25519_dalek::traits::Identity;
+
+use crate::common::digest::{ArbitraryDigest, Digest, Separable};
+use crate::common::util::{decompress_ristretto, pack, random_ristretto, random_scalar, unpack};
+use crate::errors::*;
+use generic_array::{ArrayLength, GenericArray};
+use rand::{CryptoRng, RngCore};
+use std::vec::Vec;
+use crate::sync::communication::{BinaryReceive, BinarySend, GetConn};
+use crate::sync::crypto::{SymmetricDecryptor, SymmetricEncryptor};
+use crate::sync::session::{self, Session};
+
+fn receive_point<T>(conn: &mut T) -> Result<RistrettoPoint>
+where
+    T: BinaryReceive,
+{
+    let v = conn.receive()?;
+    decompress_ristretto(&v)
+}
+
+fn send_point<T>(conn: &mut T, p: &RistrettoPoint) -> Result<()>
+where
+    T: BinarySend,
+{
+    conn.send(p.compress().as_bytes())?;
+    Ok(())
+}
+
+/// Hashes a group element onto the group, the hasher is already seeded with the sender's public key.
+fn hash_to_point<D>(mut hasher: D, p: &RistrettoPoint) -> RistrettoPoint
+where
+    D: ArbitraryDigest,
+{
+    hasher.input(b"h2p");
+    hasher.input(p.compress().as_bytes());
+    let v = hasher.result(64);
+    RistrettoPoint::from_uniform_bytes(array_ref![v, 0, 64])
+}
+
+/// Derives the symmetric key from the whole transcript of one transfer so that any tampering
+/// with the exchanged elements results in unrelated keys.
+fn derive_key<D, L>(
+    mut hasher: D,
+    r0: &RistrettoPoint,
+    r1: &RistrettoPoint,
+    shared: &RistrettoPoint,
+) -> GenericArray<u8, L>
+where
+    D: Digest<OutputSize = L>,
+    L: ArrayLength<u8>,
+{
+    hasher.input(b"key");
+    hasher.input(r0.compress().as_bytes());
+    hasher.input(r1.compress().as_bytes());
+    hasher.input(shared.compress().as_bytes());
+    hasher.result()
+}
+
+#[derive(Clone)]
+pub struct MasnyRindalOTSender<T, D, L, S>
+where
+    T: BinarySend + BinaryReceive,
+    D: Digest<OutputSize = L> + ArbitraryDigest + Clone,
+    L: ArrayLength<u8>,
+    S: SymmetricEncryptor<L>,
+{
+    pub conn: T,
+    hasher: D,
+    encryptor: S,
+    b: Scalar,
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        D: Digest<OutputSize = L> + ArbitraryDigest + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > GetConn<T> for MasnyRindalOTSender<T, D, L, S>
+{
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+impl<
+        T: BinarySend + BinaryReceive,
+        D: Digest<OutputSize = L> + ArbitraryDigest + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > MasnyRindalOTSender<T, D, L, S>
+{
+    /// Like `new`, with the hash function bound to `session`, see `Session::check_base_ot`.
+    pub fn in_session<R>(
+        conn: T,
+        session: &Session,
+        hasher: D,
+        encryptor: S,
+        rng: R,
+    ) -> Result<Self>
+    where
+        R: RngCore + CryptoRng,
+        D: Separable,
+    {
+        session.check_base_ot::<D, S>(session::MASNY_RINDAL)?;
+        Self::new(conn, session.bind(hasher), encryptor, rng)
+    }
+
+    pub fn new<R>(mut conn: T, mut hasher: D, encryptor: S, mut rng: R) -> Result<Self>
+    where
+        R: RngCore + CryptoRng,
+    {
+        let b = random_scalar(&mut rng);
+        let b_point = &b * RISTRETTO_BASEPOINT_TABLE;
+        send_point(&mut conn, &b_point)?;
+        // every hash of this instance is bound to our public key
+        Digest::input(&mut hasher, b_point.compress().as_bytes());
+        Ok(MasnyRindalOTSender {
+            conn,
+            hasher,
+            encryptor,
+            b,
+        })
+    }
+
+    pub fn compute_keys(&mut self) -> Result<[GenericArray<u8, L>; 2]> {
+        let mut keys = self.compute_keys_many(1)?;
+        Ok(keys.remove(0))
+    }
+
+    /// Computes the keys of `count` transfers whose points the receiver sent in one frame.
+    pub fn compute_keys_many(&mut self, count: usize) -> Result<Vec<[GenericArray<u8, L>; 2]>> {
+        let v = self.conn.receive()?;
+        if v.len() != 64 * count {
+            return Err(ErrorKind::PointError.into());
+        }
+        v.chunks(64).map(|p| self.keys_for_points(p)).collect()
+    }
+
+    fn keys_for_points(&self, v: &[u8]) -> Result<[GenericArray<u8, L>; 2]> {
+        let r0 = decompress_ristretto(&v[..32])?;
+        let r1 = decompress_ristretto(&v[32..])?;
+        // m_i = r_i + H(r_{1-i}), the receiver knows the discrete logarithm of at most one of them
+        let m0 = r0 + hash_to_point(self.hasher.clone(), &r1);
+        let m1 = r1 + hash_to_point(self.hasher.clone(), &r0);
+        Ok([
+            derive_key(self.hasher.clone(), &r0, &r1, &(self.b * m0)),
+            derive_key(self.hasher.clone(), &r0, &r1, &(self.b * m1)),
+        ])
+    }
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        D: Digest<OutputSize = L> + ArbitraryDigest + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > super::BaseOTSender for MasnyRindalOTSender<T, D, L, S>
+{
+    fn send(&mut self, values: Vec<&[u8]>) -> Result<()> {
+        if values.len() != 2 {
+            bail!("Masny-Rindal OT only supports 1-out-of-2 transfers");
+        }
+        let keys = self.compute_keys()?;
+        for (key, value) in keys.iter().zip(values) {
+            let buf = self.encryptor.encrypt(key, value.to_owned())?;
+            self.conn.send(&buf)?;
+        }
+        Ok(())
+    }
+
+    fn send_many(&mut self, values: Vec<Vec<&[u8]>>) -> Result<()> {
+        if values.iter().any(|v| v.len() != 2) {
+            bail!("Masny-Rindal OT only supports 1-out-of-2 transfers");
+        }
+        let keys = self.compute_keys_many(values.len())?;
+        let mut ciphertexts = Vec::with_capacity(2 * values.len());
+        for (keys, values) in keys.iter().zip(values) {
+            for (key, value) in keys.iter().zip(values) {
+                ciphertexts.push(self.encryptor.encrypt(key, value.to_owned())?);
+            }
+        }
+        self.conn.send(&pack(&ciphertexts))
+    }
+}
+
+#[derive(Clone)]
+pub struct MasnyRindalOTReceiver<T, R, D, L, S>
+where
+    T: BinaryReceive + BinarySend,
+    R: RngCore + CryptoRng,
+    D: Digest<OutputSize = L> + ArbitraryDigest + Clone,
+    L: ArrayLength<u8>,
+    S: SymmetricDecryptor<L>,
+{
+    pub conn: T,
+    hasher: D,
+    decryptor: S,
+    rng: R,
+    b_point: RistrettoPoint,
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + ArbitraryDigest + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > MasnyRindalOTReceiver<T, R, D, L, S>
+{
+    /// Like `new`, with the hash function bound to `session`, see `Session::check_base_ot`.
+    pub fn in_session(conn: T, session: &Session, hasher: D, decryptor: S, rng: R) -> Result<Self>
+    where
+        D: Separable,
+    {
+        session.check_base_ot::<D, S>(session::MASNY_RINDAL)?;
+        Self::new(conn, session.bind(hasher), decryptor, rng)
+    }
+
+    pub fn new(mut conn: T, mut hasher: D, decryptor: S, rng: R) -> Result<Self> {
+        // receive_point rejects the identity, otherwise a malicious sender could fix all keys
+        let b_point = receive_point(&mut conn)?;
+        Digest::input(&mut hasher, b_point.compress().as_bytes());
+        Ok(MasnyRindalOTReceiver {
+            conn,
+            hasher,
+            decryptor,
+            rng,
+            b_point,
+        })
+    }
+
+    pub fn compute_key(&mut self, c: usize) -> Result<GenericArray<u8, L>> {
+        let mut keys = self.compute_keys_many(&[c])?;
+        Ok(keys.remove(0))
+    }
+
+    /// Sends the points of one transfer for every choice in a single frame and returns the keys.
+    pub fn compute_keys_many(&mut self, cs: &[usize]) -> Result<Vec<GenericArray<u8, L>>> {
+        if cs.iter().any(|&c| c > 1) {
+            bail!("Masny-Rindal OT only supports 1-out-of-2 transfers");
+        }
+        let mut buf = Vec::with_capacity(64 * cs.len());
+        let mut keys = Vec::with_capacity(cs.len());
+        for &c in cs {
+            let a = random_scalar(&mut self.rng);
+            let a_point = &a * RISTRETTO_BASEPOINT_TABLE;
+            // r_{1-c} is uniformly random, r_c is chosen such that r_c + H(r_{1-c}) = aG
+            let mut r = [RistrettoPoint::identity(); 2];
+            r[1 - c] = random_ristretto(&mut self.rng);
+            r[c] = a_point - hash_to_point(self.hasher.clone(), &r[1 - c]);
+
+            buf.extend_from_slice(r[0].compress().as_bytes());
+            buf.extend_from_slice(r[1].compress().as_bytes());
+            keys.push(derive_key(
+                self.hasher.clone(),
+                &r[0],
+                &r[1],
+                &(a * self.b_point),
+            ));
+        }
+        self.conn.send(&buf)?;
+        Ok(keys)
+    }
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + ArbitraryDigest + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > super::BaseOTReceiver for MasnyRindalOTReceiver<T, R, D, L, S>
+{
+    fn receive(&mut self, index: usize, n: usize) -> Result<Vec<u8>> {
+        if n != 2 {
+            bail!("Masny-Rindal OT only supports 1-out-of-2 transfers");
+        }
+        let key = self.compute_key(index)?;
+        let mut buffers = [self.conn.receive()?, self.conn.receive()?];
+        let buf = std::mem::take(&mut buffers[index]);
+        self.decryptor.decrypt(&key, buf)
+    }
+
+    fn receive_many(&mut self, indices: &[usize], n: usize) -> Result<Vec<Vec<u8>>> {
+        if n != 2 {
+            bail!("Masny-Rindal OT only supports 1-out-of-2 transfers");
+        }
+        let keys = self.compute_keys_many(indices)?;
+        let ciphertexts = unpack(&self.conn.receive()?)?;
+        if ciphertexts.len() != 2 * indices.len() {
+            bail!("Received wrong number of ciphertexts");
+        }
+        let mut result = Vec::with_capacity(indices.len());
+        for ((key, &index), pair) in keys.iter().zip(indices).zip(ciphertexts.chunks(2)) {
+            result.push(self.decryptor.decrypt(key, pair[index].clone())?);
+        }
+        Ok(result)
+    }
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + ArbitraryDigest + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > GetConn<T> for MasnyRindalOTReceiver<T, R, D, L, S>
+{
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::digest::sha3::SHA3_256;
+    use crate::common::util::{
+        create_random_strings, generate_random_choices, generate_random_string_pairs,
+    };
+    use rand::{thread_rng, ChaChaRng, FromEntropy, Rng};
+    use std::thread;
+    use crate::sync::base_ot::{BaseOTReceiver, BaseOTSender};
+    use crate::sync::communication::adversary::{AdversaryChannel, Attack};
+    use crate::sync::communication::memory::{channel_pair, MemoryChannel};
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::ot_extension::kos::{KOSExtendedOTReceiver, KOSExtendedOTSender};
+    use crate::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
+
+    fn honest(conn: MemoryChannel) -> AdversaryChannel<MemoryChannel> {
+        AdversaryChannel::new(conn, Vec::new())
+    }
+
+    // flips the sign bit of the first received frame, which makes every ristretto encoding non-canonical
+    fn corrupt_first_point(conn: MemoryChannel) -> AdversaryChannel<MemoryChannel> {
+        AdversaryChannel::new(conn, vec![Attack::FlipBit { frame: 0, bit: 0 }])
+    }
+
+    // flips a bit in the second received frame, i.e. the ciphertext of the first value
+    fn corrupt_ciphertext(conn: MemoryChannel) -> AdversaryChannel<MemoryChannel> {
+        AdversaryChannel::new(conn, vec![Attack::FlipBit { frame: 1, bit: 0 }])
+    }
+
+    fn transfer<F, G>(
+        c: usize,
+        sender_conn: F,
+        receiver_conn: G,
+    ) -> (Result<()>, Result<Vec<u8>>, Vec<String>)
+    where
+        F: FnOnce(MemoryChannel) -> AdversaryChannel<MemoryChannel> + Send + 'static,
+        G: FnOnce(MemoryChannel) -> AdversaryChannel<MemoryChannel> + Send + 'static,
+    {
+        let values = create_random_strings(2, 64);
+        let vals = values.clone();
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let stream = server_conn;
+            let mut ot = MasnyRindalOTSender::new(
+                sender_conn(stream),
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            )?;
+            ot.send(vals.iter().map(|s| s.as_bytes()).collect())
+        });
+        let client = thread::spawn(move || {
+            let stream = client_conn;
+            let mut ot = MasnyRindalOTReceiver::new(
+                receiver_conn(stream),
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            )?;
+            ot.receive(c, 2)
+        });
+        let client_result = client.join().unwrap();
+        let server_result = server.join().unwrap();
+        (server_result, client_result, values)
+    }
+
+    #[test]
+    fn mr_ot_key_exchange() {
+        let c = thread_rng().gen_range(0, 2);
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let stream = server_conn;
+            let mut ot = MasnyRindalOTSender::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).unwrap();
+            ot.compute_keys().unwrap()
+        });
+        let client = thread::spawn(move || {
+            let stream = client_conn;
+            let mut ot = MasnyRindalOTReceiver::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).unwrap();
+            ot.compute_key(c).unwrap()
+        });
+        let keys = server.join().unwrap();
+        let key = client.join().unwrap();
+        assert_eq!(keys[c], key);
+        assert_ne!(keys[1 - c], key);
+    }
+
+    #[test]
+    fn memory_with_aes_encryption() {
+        let c = thread_rng().gen_range(0, 2);
+        let (server_result, client_result, values) =
+            transfer(c, honest, honest);
+        server_result.unwrap();
+        let result = String::from_utf8(client_result.unwrap()).unwrap();
+        assert_eq!(result, values[c]);
+    }
+
+    #[test]
+    fn many_transfers_at_once() {
+        let choices: Vec<usize> = (0..10).map(|_| thread_rng().gen_range(0, 2)).collect();
+        let values: Vec<Vec<String>> = (0..10).map(|_| create_random_strings(2, 64)).collect();
+        let choices2 = choices.clone();
+        let vals = values.clone();
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let mut ot = MasnyRindalOTSender::new(
+                server_conn,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).unwrap();
+            ot.send_many(
+                vals.iter()
+                    .map(|v| v.iter().map(|s| s.as_bytes()).collect())
+                    .collect(),
+            ).unwrap();
+        });
+        let client = thread::spawn(move || {
+            let mut ot = MasnyRindalOTReceiver::new(
+                client_conn,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).unwrap();
+            ot.receive_many(&choices2, 2).unwrap()
+        });
+        server.join().unwrap();
+        let received = client.join().unwrap();
+        for ((c, v), r) in choices.iter().zip(&values).zip(received) {
+            assert_eq!(v[*c].as_bytes(), r.as_slice());
+        }
+    }
+
+    #[test]
+    fn kos_over_masny_rindal() {
+        let choices = generate_random_choices(100);
+        let values = generate_random_string_pairs(20, 100);
+        let choices2 = choices.clone();
+        let values2 = values.clone();
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let rng = ChaChaRng::from_entropy();
+            let ot = MasnyRindalOTSender::new(
+                server_conn,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = KOSExtendedOTReceiver::new(SHA3_256::default(), ot, rng, 5).unwrap();
+            ot_ext.receive(&choices2).unwrap()
+        });
+        let client = thread::spawn(move || {
+            let rng = ChaChaRng::from_entropy();
+            let ot = MasnyRindalOTReceiver::new(
+                client_conn,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = KOSExtendedOTSender::new(SHA3_256::default(), ot, rng, 5).unwrap();
+            ot_ext
+                .send(
+                    values2
+                        .iter()
+                        .map(|(s1, s2)| (s1.as_bytes(), s2.as_bytes()))
+                        .collect(),
+                ).unwrap();
+        });
+        let received = server.join().unwrap();
+        client.join().unwrap();
+        for (i, choice) in choices.iter().enumerate() {
+            let expected = if choice { &values[i].1 } else { &values[i].0 };
+            assert_eq!(expected.as_bytes(), received[i].as_slice());
+        }
+    }
+
+    #[test]
+    fn tampered_sender_point_is_rejected() {
+        let (_, client_result, _) = transfer(0, honest, corrupt_first_point);
+        match client_result {
+            Err(Error(ErrorKind::PointError, _)) => (),
+            r => panic!("tampered point was not rejected: {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn tampered_receiver_points_are_rejected() {
+        let (server_result, client_result, _) = transfer(1, corrupt_first_point, honest);
+        match server_result {
+            Err(Error(ErrorKind::PointError, _)) => (),
+            r => panic!("tampered points were not rejected: {:?}", r),
+        }
+        assert!(client_result.is_err());
+    }
+
+    #[test]
+    fn tampered_ciphertext_is_rejected() {
+        let (_, client_result, _) = transfer(0, honest, corrupt_ciphertext);
+        assert!(client_result.is_err());
+    }
+}
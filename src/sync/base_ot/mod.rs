@@ -3,13 +3,25 @@
 //! Implementations of  1-out-of-n oblivious transfer protocols.
 //! As these rely on expensive public-key-cryptography one is advised
 //! to compose these with extended OT.
-use errors::*;
+use crate::errors::*;
 
 pub mod chou;
+pub mod chou_ristretto;
+pub mod masny_rindal;
+pub mod naor_pinkas;
 
 /// This is the base trait for sending all base-ot protocols in this library implement.
 pub trait BaseOTSender {
     fn send(&mut self, values: Vec<&[u8]>) -> Result<()>;
+
+    /// Performs one transfer for every entry of `values`. Protocols that can combine
+    /// the messages of several transfers override this, by default they run one after another.
+    fn send_many(&mut self, values: Vec<Vec<&[u8]>>) -> Result<()> {
+        for v in values {
+            self.send(v)?;
+        }
+        Ok(())
+    }
 }
 
 // TODO: is this interface good?!
@@ -19,4 +31,9 @@ pub trait BaseOTSender {
 /// This is the base trait for sending all base-ot protocols in this library implement.
 pub trait BaseOTReceiver {
     fn receive(&mut self, index: usize, n: usize) -> Result<Vec<u8>>;
+
+    /// Performs one 1-out-of-n transfer for every index, see `BaseOTSender::send_many`.
+    fn receive_many(&mut self, indices: &[usize], n: usize) -> Result<Vec<Vec<u8>>> {
+        indices.iter().map(|&index| self.receive(index, n)).collect()
+    }
 }
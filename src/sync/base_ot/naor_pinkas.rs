@@ -0,0 +1,404 @@
+//! Naor and Pinkas' 1-out-of-n OT [https://dl.acm.org/citation.cfm?id=365502]
+//!
+//! This is the base OT used by many other MPC frameworks (e.g. ABY), it is secure against a
+//! semi-honest sender and a malicious receiver under the CDH assumption in the random oracle model.
+//! For every transfer the sender picks n - 1 random elements C_1, ..., C_{n-1}. The receiver
+//! answers with PK_0 such that it knows the discrete logarithm of PK_c = C_c - PK_0 (with C_0 = 0)
+//! but of no other PK_i. The sender then encrypts value i with H(rPK_i) and sends rG.
+//! We operate in the prime order Ristretto group so no cofactor handling is needed.
+//!
+//! Only the protocol is the same as in those frameworks, the implementation is not wire-compatible
+//! with ABY or libOTe: they use other groups (e.g. NIST curves), hash functions and message framing,
+//! so both parties have to use this library.
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_TABLE;
+use curve25519_dalek::ristretto::*;
+use curve25519_dalek::scalar::*;
+
+use crate::common::digest::{Digest, Separable};
+use crate::common::util::{decompress_ristretto, pack, random_ristretto, random_scalar, unpack};
+use crate::errors::*;
+use generic_array::{ArrayLength, GenericArray};
+use rand::{CryptoRng, RngCore};
+use std::vec::Vec;
+use crate::sync::communication::{BinaryReceive, BinarySend, GetConn};
+use crate::sync::crypto::{SymmetricDecryptor, SymmetricEncryptor};
+use crate::sync::session::{self, Session};
+
+/// Receives exactly `count` concatenated points in one message.
+fn receive_points<T>(conn: &mut T, count: usize) -> Result<Vec<RistrettoPoint>>
+where
+    T: BinaryReceive,
+{
+    let buf = conn.receive()?;
+    if buf.len() != 32 * count {
+        return Err(ErrorKind::PointError.into());
+    }
+    buf.chunks(32).map(decompress_ristretto).collect()
+}
+
+fn send_points<'a, T, I>(conn: &mut T, points: I) -> Result<()>
+where
+    T: BinarySend,
+    I: Iterator<Item = &'a RistrettoPoint>,
+{
+    let mut buf = Vec::new();
+    for p in points {
+        buf.extend_from_slice(p.compress().as_bytes());
+    }
+    conn.send(&buf)
+}
+
+/// Derives the key of value `i` from the shared element rPK_i, the hasher is seeded with rG.
+fn derive_key<D, L>(mut hasher: D, i: usize, shared: &RistrettoPoint) -> GenericArray<u8, L>
+where
+    D: Digest<OutputSize = L>,
+    L: ArrayLength<u8>,
+{
+    hasher.input(&(i as u64).to_be_bytes());
+    hasher.input(shared.compress().as_bytes());
+    hasher.result()
+}
+
+#[derive(Clone)]
+pub struct NaorPinkasOTSender<T, R, D, L, S>
+where
+    T: BinarySend + BinaryReceive,
+    R: RngCore + CryptoRng,
+    D: Digest<OutputSize = L> + Clone,
+    L: ArrayLength<u8>,
+    S: SymmetricEncryptor<L>,
+{
+    pub conn: T,
+    hasher: D,
+    encryptor: S,
+    rng: R,
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > GetConn<T> for NaorPinkasOTSender<T, R, D, L, S>
+{
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+impl<
+        T: BinarySend + BinaryReceive,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > NaorPinkasOTSender<T, R, D, L, S>
+{
+    /// Like `new`, with the hash function bound to `session`, see `Session::check_base_ot`.
+    pub fn in_session(conn: T, session: &Session, hasher: D, encryptor: S, rng: R) -> Result<Self>
+    where
+        D: Separable,
+    {
+        session.check_base_ot::<D, S>(session::NAOR_PINKAS)?;
+        Self::new(conn, session.bind(hasher), encryptor, rng)
+    }
+
+    /// Contrary to SimpleOT no messages are exchanged during setup.
+    pub fn new(conn: T, hasher: D, encryptor: S, rng: R) -> Result<Self> {
+        Ok(NaorPinkasOTSender {
+            conn,
+            hasher,
+            encryptor,
+            rng,
+        })
+    }
+
+    /// Computes the keys for one 1-out-of-n transfer for every entry of `ns`.
+    /// The random elements of all transfers, the answers of the receiver
+    /// and our elements rG are each exchanged in a single message.
+    pub fn compute_keys_many(&mut self, ns: &[usize]) -> Result<Vec<Vec<GenericArray<u8, L>>>> {
+        if ns.contains(&0) {
+            bail!("Can not transfer one out of zero values");
+        }
+        let cs: Vec<Vec<RistrettoPoint>> = ns
+            .iter()
+            .map(|&n| {
+                (1..n)
+                    .map(|_| random_ristretto(&mut self.rng))
+                    .collect()
+            })
+            .collect();
+        send_points(&mut self.conn, cs.iter().flatten())?;
+        let pk0s = receive_points(&mut self.conn, ns.len())?;
+
+        let rs: Vec<Scalar> = ns.iter().map(|_| random_scalar(&mut self.rng)).collect();
+        let grs: Vec<RistrettoPoint> = rs.iter().map(|r| r * RISTRETTO_BASEPOINT_TABLE).collect();
+        send_points(&mut self.conn, grs.iter())?;
+
+        Ok(izip!(cs, pk0s, rs, grs)
+            .map(|(c, pk0, r, gr)| {
+                let mut hasher = self.hasher.clone();
+                hasher.input(gr.compress().as_bytes());
+                let rpk0 = r * pk0;
+                // rPK_i = rC_i - rPK_0
+                let mut keys = vec![derive_key(hasher.clone(), 0, &rpk0)];
+                for (i, c) in c.iter().enumerate() {
+                    keys.push(derive_key(hasher.clone(), i + 1, &(r * c - rpk0)));
+                }
+                keys
+            })
+            .collect())
+    }
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricEncryptor<L>,
+    > super::BaseOTSender for NaorPinkasOTSender<T, R, D, L, S>
+{
+    fn send(&mut self, values: Vec<&[u8]>) -> Result<()> {
+        self.send_many(vec![values])
+    }
+
+    fn send_many(&mut self, values: Vec<Vec<&[u8]>>) -> Result<()> {
+        let ns: Vec<usize> = values.iter().map(|v| v.len()).collect();
+        let keys = self.compute_keys_many(&ns)?;
+        let mut ciphertexts = Vec::with_capacity(ns.iter().sum());
+        for (keys, values) in keys.into_iter().zip(values) {
+            for (key, value) in keys.into_iter().zip(values) {
+                ciphertexts.push(self.encryptor.encrypt(&key, value.to_owned())?);
+            }
+        }
+        self.conn.send(&pack(&ciphertexts))
+    }
+}
+
+#[derive(Clone)]
+pub struct NaorPinkasOTReceiver<T, R, D, L, S>
+where
+    T: BinaryReceive + BinarySend,
+    R: RngCore + CryptoRng,
+    D: Digest<OutputSize = L> + Clone,
+    L: ArrayLength<u8>,
+    S: SymmetricDecryptor<L>,
+{
+    pub conn: T,
+    hasher: D,
+    decryptor: S,
+    rng: R,
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > NaorPinkasOTReceiver<T, R, D, L, S>
+{
+    /// Like `new`, with the hash function bound to `session`, see `Session::check_base_ot`.
+    pub fn in_session(conn: T, session: &Session, hasher: D, decryptor: S, rng: R) -> Result<Self>
+    where
+        D: Separable,
+    {
+        session.check_base_ot::<D, S>(session::NAOR_PINKAS)?;
+        Self::new(conn, session.bind(hasher), decryptor, rng)
+    }
+
+    /// Contrary to SimpleOT no messages are exchanged during setup.
+    pub fn new(conn: T, hasher: D, decryptor: S, rng: R) -> Result<Self> {
+        Ok(NaorPinkasOTReceiver {
+            conn,
+            hasher,
+            decryptor,
+            rng,
+        })
+    }
+
+    /// Computes the key of value `indices[j]` of the j-th 1-out-of-n transfer,
+    /// see `NaorPinkasOTSender::compute_keys_many`.
+    pub fn compute_keys_many(
+        &mut self,
+        indices: &[usize],
+        n: usize,
+    ) -> Result<Vec<GenericArray<u8, L>>> {
+        if n == 0 || indices.iter().any(|&index| index >= n) {
+            bail!("index out of bounds");
+        }
+        let cs = receive_points(&mut self.conn, (n - 1) * indices.len())?;
+        let ks: Vec<Scalar> = indices
+            .iter()
+            .map(|_| random_scalar(&mut self.rng))
+            .collect();
+        let pk0s: Vec<RistrettoPoint> = izip!(indices, &ks)
+            .enumerate()
+            .map(|(j, (&index, k))| {
+                let pk = k * RISTRETTO_BASEPOINT_TABLE;
+                // we know the discrete logarithm of PK_index only
+                if index == 0 {
+                    pk
+                } else {
+                    cs[j * (n - 1) + index - 1] - pk
+                }
+            })
+            .collect();
+        send_points(&mut self.conn, pk0s.iter())?;
+        let grs = receive_points(&mut self.conn, indices.len())?;
+
+        Ok(izip!(indices, ks, grs)
+            .map(|(&index, k, gr)| {
+                let mut hasher = self.hasher.clone();
+                hasher.input(gr.compress().as_bytes());
+                derive_key(hasher, index, &(k * gr))
+            })
+            .collect())
+    }
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > super::BaseOTReceiver for NaorPinkasOTReceiver<T, R, D, L, S>
+{
+    fn receive(&mut self, index: usize, n: usize) -> Result<Vec<u8>> {
+        Ok(self.receive_many(&[index], n)?.remove(0))
+    }
+
+    fn receive_many(&mut self, indices: &[usize], n: usize) -> Result<Vec<Vec<u8>>> {
+        let keys = self.compute_keys_many(indices, n)?;
+        let ciphertexts = unpack(&self.conn.receive()?)?;
+        if ciphertexts.len() != n * indices.len() {
+            bail!("Received wrong number of ciphertexts");
+        }
+        let mut result = Vec::with_capacity(indices.len());
+        for ((key, &index), chunk) in keys.iter().zip(indices).zip(ciphertexts.chunks(n)) {
+            result.push(self.decryptor.decrypt(key, chunk[index].clone())?);
+        }
+        Ok(result)
+    }
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        R: RngCore + CryptoRng,
+        D: Digest<OutputSize = L> + Clone,
+        L: ArrayLength<u8>,
+        S: SymmetricDecryptor<L>,
+    > GetConn<T> for NaorPinkasOTReceiver<T, R, D, L, S>
+{
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::digest::sha3::SHA3_256;
+    use crate::common::util::{create_random_strings, generate_random_choices, generate_random_string_pairs};
+    use rand::{thread_rng, ChaChaRng, FromEntropy, Rng};
+    use std::thread;
+    use crate::sync::base_ot::{BaseOTReceiver, BaseOTSender};
+    use crate::sync::communication::memory::channel_pair;
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
+    use crate::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
+
+    fn transfer(n: usize, indices: Vec<usize>) {
+        let values = create_random_strings(n, 64);
+        let values2 = values.clone();
+        let num = indices.len();
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let stream = server_conn;
+            let mut ot = NaorPinkasOTSender::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_entropy(),
+            ).unwrap();
+            let values: Vec<&[u8]> = values2.iter().map(|s| s.as_bytes()).collect();
+            ot.send(values.clone()).unwrap();
+            ot.send_many(vec![values; num]).unwrap();
+        });
+        let stream = client_conn;
+        let mut ot = NaorPinkasOTReceiver::new(
+            stream,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            ChaChaRng::from_entropy(),
+        ).unwrap();
+        let single = ot.receive(indices[0], n).unwrap();
+        let many = ot.receive_many(&indices, n).unwrap();
+        server.join().unwrap();
+        assert_eq!(single, values[indices[0]].as_bytes());
+        for (r, &i) in many.iter().zip(&indices) {
+            assert_eq!(r.as_slice(), values[i].as_bytes());
+        }
+    }
+
+    #[test]
+    fn np_one_out_of_two() {
+        let indices = (0..10).map(|_| thread_rng().gen_range(0, 2)).collect();
+        transfer(2, indices);
+    }
+
+    #[test]
+    fn np_one_out_of_n() {
+        let n = 10;
+        let indices = (0..10).map(|_| thread_rng().gen_range(0, n)).collect();
+        transfer(n, indices);
+    }
+
+    #[test]
+    fn np_as_iknp_base_ot() {
+        let n = 100;
+        let choices = generate_random_choices(n);
+        let values = generate_random_string_pairs(32, n);
+        let choices2 = choices.clone();
+        let values2 = values.clone();
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let stream = server_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = NaorPinkasOTSender::new(
+                stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, rng, 16).unwrap();
+            ot_ext.receive(&choices2).unwrap()
+        });
+        let stream = client_conn;
+        let rng = ChaChaRng::from_entropy();
+        let ot = NaorPinkasOTReceiver::new(
+            stream,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            rng.clone(),
+        ).unwrap();
+        let mut ot_ext = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng, 16).unwrap();
+        ot_ext
+            .send(
+                values2
+                    .iter()
+                    .map(|(s1, s2)| (s1.as_bytes(), s2.as_bytes()))
+                    .collect(),
+            )
+            .unwrap();
+        let result = server.join().unwrap();
+        for (i, choice) in choices.iter().enumerate() {
+            let expected = if choice { &values[i].1 } else { &values[i].0 };
+            assert_eq!(expected.as_bytes(), result[i].as_slice());
+        }
+    }
+}
@@ -0,0 +1,974 @@
+//! An active adversary on the connection between two parties.
+//!
+//! `AdversaryChannel` wraps the connection of the attacked party and manipulates the frames it
+//! receives according to a list of `Attack`s, which covers both a malicious peer and an attacker
+//! on the network. Frames are counted from 0 in the order they arrive from the connection, so an
+//! attack can target e.g. the third message of a protocol. Frames the party sends are forwarded
+//! unchanged, but can be observed with `set_eavesdropper`.
+use super::{BinaryReceive, BinarySend};
+use curve25519_dalek::constants::EIGHT_TORSION;
+use crate::errors::*;
+use std::collections::VecDeque;
+use std::fmt;
+
+/// A manipulation of the received frames.
+pub enum Attack {
+    /// Flips the given bit (counted from the least significant bit of the first byte) of a frame.
+    FlipBit { frame: usize, bit: usize },
+    /// Delivers another copy of the earlier frame `of` in place of `frame`.
+    Replay { frame: usize, of: usize },
+    /// Cuts a frame down to `len` bytes.
+    Truncate { frame: usize, len: usize },
+    /// Never delivers a frame.
+    Drop { frame: usize },
+    /// Delivers `frame` after the one following it.
+    Swap { frame: usize },
+    /// Delivers `data` right before `frame`.
+    Inject { frame: usize, data: Vec<u8> },
+    /// Overwrites the 32 bytes at `offset` of a frame with the encoding of a point of order 8
+    /// on the Edwards curve, which is no valid encoding of a Ristretto point.
+    LowOrderPoint { frame: usize, offset: usize },
+    /// Is called with the index of every received frame and the frames which will be delivered
+    /// in its place by now (usually just the frame itself), which it can change arbitrarily.
+    Custom(Tamper),
+}
+
+/// The closure of `Attack::Custom`.
+pub type Tamper = Box<dyn FnMut(usize, &mut Vec<Vec<u8>>) + Send>;
+
+impl fmt::Debug for Attack {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Attack::FlipBit { frame, bit } => {
+                write!(f, "FlipBit {{ frame: {}, bit: {} }}", frame, bit)
+            }
+            Attack::Replay { frame, of } => write!(f, "Replay {{ frame: {}, of: {} }}", frame, of),
+            Attack::Truncate { frame, len } => {
+                write!(f, "Truncate {{ frame: {}, len: {} }}", frame, len)
+            }
+            Attack::Drop { frame } => write!(f, "Drop {{ frame: {} }}", frame),
+            Attack::Swap { frame } => write!(f, "Swap {{ frame: {} }}", frame),
+            Attack::Inject { frame, ref data } => {
+                write!(f, "Inject {{ frame: {}, data: {:?} }}", frame, data)
+            }
+            Attack::LowOrderPoint { frame, offset } => {
+                write!(
+                    f,
+                    "LowOrderPoint {{ frame: {}, offset: {} }}",
+                    frame, offset
+                )
+            }
+            Attack::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+impl Attack {
+    pub fn custom<F>(f: F) -> Self
+    where
+        F: FnMut(usize, &mut Vec<Vec<u8>>) + Send + 'static,
+    {
+        Attack::Custom(Box::new(f))
+    }
+
+    /// Modifies every received frame in place, `f` gets the index of the frame.
+    pub fn modify<F>(mut f: F) -> Self
+    where
+        F: FnMut(usize, &mut Vec<u8>) + Send + 'static,
+    {
+        Attack::custom(move |index, frames| {
+            for frame in frames.iter_mut() {
+                f(index, frame);
+            }
+        })
+    }
+}
+
+/// Observes every frame sent over an `AdversaryChannel`.
+pub type Eavesdropper = Box<dyn FnMut(&[u8]) + Send>;
+
+/// Wraps a connection and attacks the frames received over it.
+pub struct AdversaryChannel<C: BinarySend + BinaryReceive> {
+    conn: C,
+    attacks: Vec<Attack>,
+    eavesdropper: Option<Eavesdropper>,
+    // every frame received so far, for replays
+    history: Vec<Vec<u8>>,
+    // frames which were received but not yet delivered
+    pending: VecDeque<Vec<u8>>,
+    // frames held back by a swap
+    held: Vec<Vec<u8>>,
+    frames_sent: usize,
+}
+
+impl<C: BinarySend + BinaryReceive> AdversaryChannel<C> {
+    /// Attacks are applied in the given order, without any attacks all frames are just forwarded.
+    pub fn new(conn: C, attacks: Vec<Attack>) -> Self {
+        AdversaryChannel {
+            conn,
+            attacks,
+            eavesdropper: None,
+            history: Vec::new(),
+            pending: VecDeque::new(),
+            held: Vec::new(),
+            frames_sent: 0,
+        }
+    }
+
+    /// `eavesdropper` is called with every frame before it is sent.
+    pub fn set_eavesdropper<F: FnMut(&[u8]) + Send + 'static>(&mut self, eavesdropper: F) {
+        self.eavesdropper = Some(Box::new(eavesdropper));
+    }
+
+    /// Number of frames sent by the attacked party.
+    pub fn frames_sent(&self) -> usize {
+        self.frames_sent
+    }
+
+    /// Number of frames received from the connection, before any attack.
+    pub fn frames_received(&self) -> usize {
+        self.history.len()
+    }
+
+    pub fn into_inner(self) -> C {
+        self.conn
+    }
+
+    fn attack(&mut self, index: usize, frames: &mut Vec<Vec<u8>>) {
+        for attack in self.attacks.iter_mut() {
+            match *attack {
+                Attack::FlipBit { frame, bit } if frame == index => {
+                    for f in frames.iter_mut().filter(|f| bit / 8 < f.len()) {
+                        f[bit / 8] ^= 1 << (bit % 8);
+                    }
+                }
+                Attack::Replay { frame, of } if frame == index && of < index => {
+                    *frames = vec![self.history[of].clone()];
+                }
+                Attack::Truncate { frame, len } if frame == index => {
+                    for f in frames.iter_mut() {
+                        f.truncate(len);
+                    }
+                }
+                Attack::Drop { frame } if frame == index => frames.clear(),
+                Attack::Swap { frame } if frame == index => self.held.append(frames),
+                Attack::Swap { frame } if frame + 1 == index => frames.append(&mut self.held),
+                Attack::Inject { frame, ref data } if frame == index => {
+                    frames.insert(0, data.clone())
+                }
+                Attack::LowOrderPoint { frame, offset } if frame == index => {
+                    let point = EIGHT_TORSION[1].compress();
+                    for f in frames.iter_mut().filter(|f| offset + 32 <= f.len()) {
+                        f[offset..offset + 32].copy_from_slice(point.as_bytes());
+                    }
+                }
+                Attack::Custom(ref mut f) => f(index, frames),
+                _ => (),
+            }
+        }
+    }
+}
+
+impl<C: BinarySend + BinaryReceive> BinarySend for AdversaryChannel<C> {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        if let Some(ref mut eavesdropper) = self.eavesdropper {
+            eavesdropper(data);
+        }
+        self.frames_sent += 1;
+        self.conn.send(data)
+    }
+}
+
+impl<C: BinarySend + BinaryReceive> BinaryReceive for AdversaryChannel<C> {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        loop {
+            if let Some(frame) = self.pending.pop_front() {
+                return Ok(frame);
+            }
+            let frame = self.conn.receive()?;
+            let index = self.history.len();
+            self.history.push(frame.clone());
+            let mut frames = vec![frame];
+            self.attack(index, &mut frames);
+            self.pending.extend(frames);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bit_vec::BitVec;
+    use crate::common::digest::aes::AesHash;
+    use crate::common::digest::sha3::SHA3_256;
+    use rand::{ChaChaRng, FromEntropy};
+    use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use crate::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+    use crate::sync::base_ot::chou_ristretto::{
+        ChouOrlandiRistrettoOTReceiver, ChouOrlandiRistrettoOTSender,
+    };
+    use crate::sync::base_ot::masny_rindal::{MasnyRindalOTReceiver, MasnyRindalOTSender};
+    use crate::sync::base_ot::naor_pinkas::{NaorPinkasOTReceiver, NaorPinkasOTSender};
+    use crate::sync::base_ot::{BaseOTReceiver, BaseOTSender};
+    use crate::sync::communication::memory::channel_pair;
+    use crate::sync::communication::{GetConn, GetConnMut};
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::ot_extension::alsz::{ALSZExtendedOTReceiver, ALSZExtendedOTSender};
+    use crate::sync::ot_extension::beaver::{derandomize_receive, derandomize_send};
+    use crate::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
+    use crate::sync::ot_extension::kk13::{KK13ExtendedNOTReceiver, KK13ExtendedNOTSender};
+    use crate::sync::ot_extension::kos::{KOSExtendedOTReceiver, KOSExtendedOTSender};
+    use crate::sync::ot_extension::one_out_of_n::{OneOutOfNReceiver, OneOutOfNSender};
+    use crate::sync::ot_extension::{
+        ExtendedNOTReceiver, ExtendedNOTSender, ExtendedOTReceiver, ExtendedOTSender,
+        RandomOTReceiver, RandomOTSender,
+    };
+
+    #[test]
+    fn attacks_change_received_frames() {
+        let (a, mut b) = channel_pair();
+        let mut conn = AdversaryChannel::new(
+            a,
+            vec![
+                Attack::FlipBit { frame: 0, bit: 9 },
+                Attack::Drop { frame: 1 },
+                Attack::Swap { frame: 2 },
+                Attack::Inject {
+                    frame: 3,
+                    data: vec![7],
+                },
+                Attack::Replay { frame: 4, of: 0 },
+                Attack::Truncate { frame: 5, len: 1 },
+            ],
+        );
+        for i in 0..6 {
+            b.send(&[i, i]).unwrap();
+        }
+        let received: Vec<Vec<u8>> = (0..6).map(|_| conn.receive().unwrap()).collect();
+        assert_eq!(
+            received,
+            vec![
+                vec![0, 2],
+                vec![7],
+                vec![3, 3],
+                vec![2, 2],
+                vec![0, 0],
+                vec![5],
+            ]
+        );
+        assert_eq!(conn.frames_received(), 6);
+        conn.send(&[]).unwrap();
+        assert_eq!(conn.frames_sent(), 1);
+    }
+
+    // The suite runs every protocol with a list of attacks aimed at its frames and checks how
+    // both parties end: invalid points have to be rejected as such, the actively secure
+    // extensions have to detect tampered columns in their consistency check, and a party which
+    // finishes has to return exactly what it returns in an honest run.
+
+    const N: usize = 40;
+    const L: usize = 16;
+    const BASE_N: usize = 4;
+
+    type Conn = AdversaryChannel<WatchedChannel>;
+
+    // Without timeouts a party waiting for a dropped frame would wait forever, if the other
+    // party waits as well. This connection detects that and makes both of them fail instead.
+    #[derive(Default)]
+    struct Link {
+        waiting: usize,
+        in_flight: usize,
+        deadlocked: bool,
+    }
+
+    struct WatchedChannel {
+        sender: Sender<Vec<u8>>,
+        receiver: Receiver<Vec<u8>>,
+        link: Arc<Mutex<Link>>,
+    }
+
+    fn watched_pair() -> (WatchedChannel, WatchedChannel) {
+        let (sender_a, receiver_b) = channel();
+        let (sender_b, receiver_a) = channel();
+        let link = Arc::new(Mutex::new(Link::default()));
+        (
+            WatchedChannel {
+                sender: sender_a,
+                receiver: receiver_a,
+                link: Arc::clone(&link),
+            },
+            WatchedChannel {
+                sender: sender_b,
+                receiver: receiver_b,
+                link,
+            },
+        )
+    }
+
+    impl BinarySend for WatchedChannel {
+        fn send(&mut self, data: &[u8]) -> Result<()> {
+            self.link.lock().unwrap().in_flight += 1;
+            self.sender
+                .send(data.to_vec())
+                .map_err(|_| ErrorKind::CommunicationError.into())
+        }
+    }
+
+    impl BinaryReceive for WatchedChannel {
+        fn receive(&mut self) -> Result<Vec<u8>> {
+            let mut waiting = false;
+            let result = loop {
+                match self.receiver.recv_timeout(Duration::from_millis(10)) {
+                    Ok(frame) => break Ok(frame),
+                    Err(RecvTimeoutError::Disconnected) => {
+                        break Err(ErrorKind::CommunicationError.into())
+                    }
+                    Err(RecvTimeoutError::Timeout) => {
+                        let mut link = self.link.lock().unwrap();
+                        if !waiting {
+                            waiting = true;
+                            link.waiting += 1;
+                        }
+                        if link.waiting == 2 && link.in_flight == 0 {
+                            link.deadlocked = true;
+                        }
+                        if link.deadlocked {
+                            break Err(ErrorKind::CommunicationError.into());
+                        }
+                    }
+                }
+            };
+            let mut link = self.link.lock().unwrap();
+            if waiting {
+                link.waiting -= 1;
+            }
+            if result.is_ok() {
+                link.in_flight -= 1;
+            }
+            result
+        }
+    }
+    type Party = fn(Conn) -> Result<Vec<Vec<u8>>>;
+
+    #[derive(Clone)]
+    struct Protocol {
+        name: &'static str,
+        sender: Party,
+        receiver: Party,
+        transfers: usize,
+        messages: usize,
+        scenarios: fn() -> Vec<Scenario>,
+    }
+
+    /// How a party has to end.
+    #[derive(Clone, Copy, Debug)]
+    enum Outcome {
+        /// Returns the output of an honest run.
+        Correct,
+        /// Fails with `ErrorKind::PointError`.
+        InvalidPoint,
+        /// Fails with `ErrorKind::ConsistencyCheckFailed`.
+        CheatingDetected,
+        /// Fails with any error, usually because the other party gave up.
+        Aborts,
+    }
+
+    impl Outcome {
+        fn matches(self, result: &Result<Vec<Vec<u8>>>, honest: &[Vec<u8>]) -> bool {
+            match (self, result) {
+                (Outcome::Correct, Ok(values)) => values.as_slice() == honest,
+                (Outcome::InvalidPoint, Err(Error(ErrorKind::PointError, _))) => true,
+                (Outcome::CheatingDetected, Err(Error(ErrorKind::ConsistencyCheckFailed, _))) => {
+                    true
+                }
+                (Outcome::Aborts, Err(_)) => true,
+                _ => false,
+            }
+        }
+    }
+
+    /// Attacks on the frames one party receives and how both parties have to end.
+    struct Scenario {
+        on_sender: bool,
+        attacks: Vec<Attack>,
+        sender: Outcome,
+        receiver: Outcome,
+    }
+
+    fn honest() -> Scenario {
+        Scenario {
+            on_sender: false,
+            attacks: Vec::new(),
+            sender: Outcome::Correct,
+            receiver: Outcome::Correct,
+        }
+    }
+
+    fn on_sender(attack: Attack, sender: Outcome, receiver: Outcome) -> Scenario {
+        Scenario {
+            on_sender: true,
+            attacks: vec![attack],
+            sender,
+            receiver,
+        }
+    }
+
+    fn on_receiver(attack: Attack, sender: Outcome, receiver: Outcome) -> Scenario {
+        Scenario {
+            on_sender: false,
+            attacks: vec![attack],
+            sender,
+            receiver,
+        }
+    }
+
+    fn value(i: usize, j: usize) -> Vec<u8> {
+        vec![(i * 4 + j) as u8; L]
+    }
+
+    fn pairs(n: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..n).map(|i| (value(i, 0), value(i, 1))).collect()
+    }
+
+    fn choices(n: usize, messages: usize) -> Vec<usize> {
+        (0..n).map(|i| (i * 7 + i / 3) % messages).collect()
+    }
+
+    fn choice_bits(n: usize) -> BitVec {
+        choices(n, 2).into_iter().map(|c| c == 1).collect()
+    }
+
+    fn expected(n: usize, messages: usize) -> Vec<Vec<u8>> {
+        choices(n, messages)
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| value(i, c))
+            .collect()
+    }
+
+    fn base_values(messages: usize) -> Vec<Vec<Vec<u8>>> {
+        (0..BASE_N)
+            .map(|i| (0..messages).map(|j| value(i, j)).collect())
+            .collect()
+    }
+
+    macro_rules! base_ot {
+        ($name:expr, $sender:ident, $receiver:ident, $scenarios:expr) => {{
+            fn sender(conn: Conn) -> Result<Vec<Vec<u8>>> {
+                let mut ot = $sender::new(
+                    conn,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    ChaChaRng::from_entropy(),
+                )?;
+                let values = base_values(2);
+                ot.send_many(
+                    values
+                        .iter()
+                        .map(|v| v.iter().map(|s| s.as_slice()).collect())
+                        .collect(),
+                )?;
+                Ok(Vec::new())
+            }
+            fn receiver(conn: Conn) -> Result<Vec<Vec<u8>>> {
+                let mut ot = $receiver::new(
+                    conn,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    ChaChaRng::from_entropy(),
+                )?;
+                ot.receive_many(&choices(BASE_N, 2), 2)
+            }
+            Protocol {
+                name: $name,
+                sender,
+                receiver,
+                transfers: BASE_N,
+                messages: 2,
+                scenarios: $scenarios,
+            }
+        }};
+    }
+
+    fn base_sender(conn: Conn) -> Result<impl BaseOTSender + GetConn<Conn>> {
+        ChouOrlandiOTSender::new(
+            conn,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            ChaChaRng::from_entropy(),
+        )
+    }
+
+    fn base_receiver(conn: Conn) -> Result<impl BaseOTReceiver + GetConn<Conn>> {
+        ChouOrlandiOTReceiver::new(
+            conn,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            ChaChaRng::from_entropy(),
+        )
+    }
+
+    fn send_pairs<S: ExtendedOTSender>(ot: &mut S) -> Result<Vec<Vec<u8>>> {
+        let values = pairs(N);
+        ot.send(
+            values
+                .iter()
+                .map(|(s0, s1)| (s0.as_slice(), s1.as_slice()))
+                .collect(),
+        )?;
+        Ok(Vec::new())
+    }
+
+    fn send_n<S: ExtendedNOTSender>(ot: &mut S) -> Result<Vec<Vec<u8>>> {
+        let values: Vec<Vec<Vec<u8>>> = (0..N)
+            .map(|i| (0..4).map(|j| value(i, j)).collect())
+            .collect();
+        ot.send(
+            values
+                .iter()
+                .map(|v| v.iter().map(|s| s.as_slice()).collect())
+                .collect(),
+        )?;
+        Ok(Vec::new())
+    }
+
+    fn iknp_sender(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let ot = base_receiver(conn)?;
+        send_pairs(&mut IKNPExtendedOTSender::new(
+            SHA3_256::default(),
+            ot,
+            ChaChaRng::from_entropy(),
+            16,
+        )?)
+    }
+
+    fn iknp_receiver(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let ot = base_sender(conn)?;
+        IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, ChaChaRng::from_entropy(), 16)?
+            .receive(&choice_bits(N))
+    }
+
+    fn alsz_sender(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let ot = base_receiver(conn)?;
+        send_pairs(&mut ALSZExtendedOTSender::new(
+            SHA3_256::default(),
+            ot,
+            ChaChaRng::from_entropy(),
+            16,
+            5,
+        )?)
+    }
+
+    fn alsz_receiver(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let ot = base_sender(conn)?;
+        ALSZExtendedOTReceiver::new(SHA3_256::default(), ot, ChaChaRng::from_entropy(), 16, 5)?
+            .receive(&choice_bits(N))
+    }
+
+    fn kos_sender(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let ot = base_receiver(conn)?;
+        send_pairs(&mut KOSExtendedOTSender::new(
+            SHA3_256::default(),
+            ot,
+            ChaChaRng::from_entropy(),
+            5,
+        )?)
+    }
+
+    fn kos_receiver(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let ot = base_sender(conn)?;
+        KOSExtendedOTReceiver::new(SHA3_256::default(), ot, ChaChaRng::from_entropy(), 5)?
+            .receive(&choice_bits(N))
+    }
+
+    fn kk13_sender(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let ot = base_receiver(conn)?;
+        send_n(&mut KK13ExtendedNOTSender::new(
+            AesHash::default(),
+            ot,
+            ChaChaRng::from_entropy(),
+        )?)
+    }
+
+    fn kk13_receiver(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let ot = base_sender(conn)?;
+        KK13ExtendedNOTReceiver::new(AesHash::default(), ot, ChaChaRng::from_entropy())?
+            .receive(&choices(N, 4))
+    }
+
+    fn one_out_of_n_sender(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let rng = ChaChaRng::from_entropy();
+        let ot = base_receiver(conn)?;
+        let ot = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng.clone(), 16)?;
+        send_n(&mut OneOutOfNSender::new(ot, SHA3_256::default(), rng))
+    }
+
+    fn one_out_of_n_receiver(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let ot = base_sender(conn)?;
+        let ot =
+            IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, ChaChaRng::from_entropy(), 16)?;
+        ExtendedNOTReceiver::receive(
+            &mut OneOutOfNReceiver::new(ot, SHA3_256::default()),
+            &choices(N, 4),
+        )
+    }
+
+    fn beaver_sender(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let ot = base_receiver(conn)?;
+        let mut ot =
+            IKNPExtendedOTSender::new(SHA3_256::default(), ot, ChaChaRng::from_entropy(), 16)?;
+        let pads = ot.random_send(N, L)?;
+        let values = pairs(N);
+        derandomize_send(
+            ot.get_conn_mut(),
+            &pads,
+            values
+                .iter()
+                .map(|(s0, s1)| (s0.as_slice(), s1.as_slice()))
+                .collect(),
+        )?;
+        Ok(Vec::new())
+    }
+
+    fn beaver_receiver(conn: Conn) -> Result<Vec<Vec<u8>>> {
+        let ot = base_sender(conn)?;
+        let mut ot =
+            IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, ChaChaRng::from_entropy(), 16)?;
+        let random_choices: BitVec = (0..N).map(|i| i % 2 == 0).collect();
+        let pads = ot.random_receive(&random_choices, L)?;
+        derandomize_receive(ot.get_conn_mut(), &random_choices, &pads, &choice_bits(N))
+    }
+
+    use self::Outcome::*;
+
+    // The receiver of a base OT gets the point of the sender in its first frame and answers
+    // with one point per transfer in a single frame, so both parties check points first.
+    fn base_ot_scenarios() -> Vec<Scenario> {
+        vec![
+            honest(),
+            on_sender(Attack::Truncate { frame: 0, len: 5 }, InvalidPoint, Aborts),
+            on_sender(
+                Attack::Inject {
+                    frame: 0,
+                    data: vec![0; 32],
+                },
+                InvalidPoint,
+                Aborts,
+            ),
+            on_sender(
+                Attack::LowOrderPoint {
+                    frame: 0,
+                    offset: 0,
+                },
+                InvalidPoint,
+                Aborts,
+            ),
+            on_receiver(Attack::Truncate { frame: 0, len: 5 }, Aborts, InvalidPoint),
+            on_receiver(
+                Attack::Inject {
+                    frame: 0,
+                    data: vec![0; 32],
+                },
+                Aborts,
+                InvalidPoint,
+            ),
+            on_receiver(
+                Attack::LowOrderPoint {
+                    frame: 0,
+                    offset: 0,
+                },
+                Aborts,
+                InvalidPoint,
+            ),
+            on_receiver(Attack::Swap { frame: 0 }, Aborts, Aborts),
+            on_sender(Attack::Drop { frame: 0 }, Aborts, Aborts),
+        ]
+    }
+
+    // The ciphertexts of all transfers arrive packed in the second frame, the point is moved
+    // behind the length prefix of the first ciphertext.
+    fn chou_scenarios() -> Vec<Scenario> {
+        let mut scenarios = base_ot_scenarios();
+        scenarios.push(on_receiver(
+            Attack::LowOrderPoint {
+                frame: 1,
+                offset: 8,
+            },
+            Correct,
+            Aborts,
+        ));
+        scenarios.push(on_receiver(
+            Attack::FlipBit {
+                frame: 1,
+                bit: 8 * 8,
+            },
+            Correct,
+            Aborts,
+        ));
+        scenarios
+    }
+
+    // The receiver answers with two points per transfer in a single frame, so the second
+    // point of the second transfer starts at 96. The ciphertexts arrive packed as for chou.
+    fn masny_rindal_scenarios() -> Vec<Scenario> {
+        let mut scenarios = base_ot_scenarios();
+        scenarios.push(on_sender(
+            Attack::LowOrderPoint {
+                frame: 0,
+                offset: 96,
+            },
+            InvalidPoint,
+            Aborts,
+        ));
+        scenarios.push(on_receiver(
+            Attack::FlipBit {
+                frame: 1,
+                bit: 8 * 8,
+            },
+            Correct,
+            Aborts,
+        ));
+        scenarios
+    }
+
+    // The receiver gets a frame with one point per transfer before it answers, so every one
+    // of them has to be checked, and the packed ciphertexts come last.
+    fn naor_pinkas_scenarios() -> Vec<Scenario> {
+        let mut scenarios = base_ot_scenarios();
+        scenarios.push(on_receiver(
+            Attack::LowOrderPoint {
+                frame: 0,
+                offset: 64,
+            },
+            Aborts,
+            InvalidPoint,
+        ));
+        scenarios.push(on_receiver(
+            Attack::FlipBit {
+                frame: 2,
+                bit: 8 * 8,
+            },
+            Correct,
+            Aborts,
+        ));
+        scenarios
+    }
+
+    // The sender of an extension is the receiver of the base OTs: it gets the point of the
+    // base sender, then all base OT ciphertexts in one packed frame and then the columns.
+    fn extension_scenarios() -> Vec<Scenario> {
+        vec![
+            honest(),
+            on_sender(
+                Attack::LowOrderPoint {
+                    frame: 0,
+                    offset: 0,
+                },
+                InvalidPoint,
+                Aborts,
+            ),
+            on_sender(Attack::Truncate { frame: 0, len: 5 }, InvalidPoint, Aborts),
+            // the base OT receiver only decrypts the ciphertext of its random choice,
+            // so both ciphertexts of the first transfer (40 bytes with their prefix) are hit
+            Scenario {
+                on_sender: true,
+                attacks: vec![
+                    Attack::LowOrderPoint {
+                        frame: 1,
+                        offset: 8,
+                    },
+                    Attack::LowOrderPoint {
+                        frame: 1,
+                        offset: 48,
+                    },
+                ],
+                sender: Aborts,
+                receiver: Aborts,
+            },
+            on_receiver(
+                Attack::LowOrderPoint {
+                    frame: 0,
+                    offset: 32,
+                },
+                Aborts,
+                InvalidPoint,
+            ),
+            on_receiver(
+                Attack::Inject {
+                    frame: 0,
+                    data: vec![0; 32],
+                },
+                Aborts,
+                InvalidPoint,
+            ),
+            on_receiver(Attack::Truncate { frame: 0, len: 5 }, Aborts, InvalidPoint),
+            on_sender(Attack::Swap { frame: 0 }, Aborts, Aborts),
+        ]
+    }
+
+    // A flipped bit in a single column only changes the matrix of the sender if its base OT
+    // choice for the column is 1. Flipping the bit of a row in all columns always does, it
+    // contradicts the choice the receiver reports in the correlation check. All 128 columns
+    // of 26 bytes arrive in one frame.
+    fn kos_scenarios() -> Vec<Scenario> {
+        let mut scenarios = extension_scenarios();
+        scenarios.push(Scenario {
+            on_sender: true,
+            attacks: (0..128)
+                .map(|column| Attack::FlipBit {
+                    frame: 2,
+                    bit: column * 26 * 8,
+                })
+                .collect(),
+            sender: CheatingDetected,
+            receiver: Aborts,
+        });
+        scenarios
+    }
+
+    // The pairwise hashes of ALSZ cover both the columns and their xor, so a single flipped
+    // bit is detected whatever the choices of the sender are. All 168 columns of 21 bytes
+    // arrive in one frame.
+    fn alsz_scenarios() -> Vec<Scenario> {
+        let mut scenarios = extension_scenarios();
+        scenarios.push(on_sender(
+            Attack::FlipBit { frame: 2, bit: 0 },
+            CheatingDetected,
+            Aborts,
+        ));
+        scenarios.push(on_sender(
+            Attack::FlipBit {
+                frame: 2,
+                bit: 167 * 21 * 8 + 7,
+            },
+            CheatingDetected,
+            Aborts,
+        ));
+        scenarios
+    }
+
+    fn protocols() -> Vec<Protocol> {
+        let extension = |name, sender, receiver, messages, scenarios| Protocol {
+            name,
+            sender,
+            receiver,
+            transfers: N,
+            messages,
+            scenarios,
+        };
+        vec![
+            base_ot!("chou", ChouOrlandiOTSender, ChouOrlandiOTReceiver, chou_scenarios),
+            base_ot!(
+                "chou_ristretto",
+                ChouOrlandiRistrettoOTSender,
+                ChouOrlandiRistrettoOTReceiver,
+                chou_scenarios
+            ),
+            base_ot!(
+                "masny_rindal",
+                MasnyRindalOTSender,
+                MasnyRindalOTReceiver,
+                masny_rindal_scenarios
+            ),
+            base_ot!(
+                "naor_pinkas",
+                NaorPinkasOTSender,
+                NaorPinkasOTReceiver,
+                naor_pinkas_scenarios
+            ),
+            extension(
+                "iknp",
+                iknp_sender as Party,
+                iknp_receiver as Party,
+                2,
+                extension_scenarios as fn() -> Vec<Scenario>,
+            ),
+            extension("alsz", alsz_sender, alsz_receiver, 2, alsz_scenarios),
+            extension("kos", kos_sender, kos_receiver, 2, kos_scenarios),
+            extension("kk13", kk13_sender, kk13_receiver, 4, extension_scenarios),
+            extension(
+                "one_out_of_n",
+                one_out_of_n_sender,
+                one_out_of_n_receiver,
+                4,
+                extension_scenarios,
+            ),
+            extension("beaver", beaver_sender, beaver_receiver, 2, extension_scenarios),
+        ]
+    }
+
+    #[test]
+    fn protocols_withstand_attacks() {
+        let mut runs = Vec::new();
+        for protocol in protocols() {
+            for scenario in (protocol.scenarios)() {
+                runs.push((protocol.clone(), scenario));
+            }
+        }
+
+        let mut failures = Vec::new();
+        while !runs.is_empty() {
+            let chunk: Vec<_> = runs.drain(..runs.len().min(8)).collect();
+            let handles: Vec<_> = chunk
+                .into_iter()
+                .map(|(protocol, scenario)| {
+                    let description = format!(
+                        "{} with {:?} on the {}",
+                        protocol.name,
+                        scenario.attacks,
+                        if scenario.on_sender {
+                            "sender"
+                        } else {
+                            "receiver"
+                        }
+                    );
+                    let (sender_conn, receiver_conn) = watched_pair();
+                    let (sender_attacks, receiver_attacks) = if scenario.on_sender {
+                        (scenario.attacks, Vec::new())
+                    } else {
+                        (Vec::new(), scenario.attacks)
+                    };
+                    let sender_conn = AdversaryChannel::new(sender_conn, sender_attacks);
+                    let receiver_conn = AdversaryChannel::new(receiver_conn, receiver_attacks);
+                    let (sender, receiver) = (protocol.sender, protocol.receiver);
+                    (
+                        description,
+                        protocol,
+                        (scenario.sender, scenario.receiver),
+                        thread::spawn(move || sender(sender_conn)),
+                        thread::spawn(move || receiver(receiver_conn)),
+                    )
+                })
+                .collect();
+
+            for (description, protocol, (sender_outcome, receiver_outcome), sender, receiver) in
+                handles
+            {
+                let (sender, receiver) = match (sender.join(), receiver.join()) {
+                    (Ok(sender), Ok(receiver)) => (sender, receiver),
+                    _ => {
+                        failures.push(format!("{}: panicked", description));
+                        continue;
+                    }
+                };
+                if !sender_outcome.matches(&sender, &[]) {
+                    failures.push(format!(
+                        "{}: sender should end {:?}, but returned {:?}",
+                        description, sender_outcome, sender
+                    ));
+                }
+                let honest = expected(protocol.transfers, protocol.messages);
+                if !receiver_outcome.matches(&receiver, &honest) {
+                    failures.push(format!(
+                        "{}: receiver should end {:?}, but returned {:?}",
+                        description, receiver_outcome, receiver
+                    ));
+                }
+            }
+        }
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+}
@@ -0,0 +1,250 @@
+//! Deadlines and cancellation for operations on a TCP connection.
+//!
+//! A blocking `read` on a `TcpStream` waits forever for a peer that stopped talking.
+//! `DeadlineStream` gives every operation (e.g. sending or receiving one frame) a deadline:
+//! all reads and writes of an operation together may take at most the configured timeout,
+//! afterwards they fail with `ErrorKind::Timeout`. A `CancelHandle` aborts the current
+//! and all further operations from another thread, they then fail with `ErrorKind::Cancelled`.
+//!
+//! `tcp::FramedTcp` and `websockets::TimedWebSocket` are built on it, so every protocol
+//! running over them can time out and be cancelled.
+use crate::errors::*;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Cancels the operations of a `DeadlineStream`, can be sent to other threads.
+#[derive(Clone)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+    stream: Arc<TcpStream>,
+}
+
+impl CancelHandle {
+    /// Aborts the current operation and all further ones by shutting down the connection.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+        // fails if the connection is already closed, which is just as good
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// A `TcpStream` whose operations time out and can be cancelled.
+pub struct DeadlineStream {
+    stream: TcpStream,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl DeadlineStream {
+    /// Without a timeout operations can still be cancelled, but otherwise block like on a `TcpStream`.
+    pub fn new(stream: TcpStream) -> Self {
+        DeadlineStream {
+            stream,
+            timeout: None,
+            deadline: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// The time each operation may take, it applies from the next call to `start` on.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Starts a new operation, all reads and writes until the next call share its deadline.
+    pub fn start(&mut self) {
+        self.deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+    }
+
+    pub fn cancel_handle(&self) -> Result<CancelHandle> {
+        Ok(CancelHandle {
+            cancelled: self.cancelled.clone(),
+            stream: Arc::new(self.stream.try_clone()?),
+        })
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Turns the error of a failed operation into `ErrorKind::Cancelled` or `ErrorKind::Timeout`
+    /// if it was caused by a cancellation or by the deadline.
+    pub fn check<T>(&self, result: Result<T>) -> Result<T> {
+        result.map_err(|e| {
+            if self.is_cancelled() {
+                Error::with_chain(e, ErrorKind::Cancelled)
+            } else if timed_out(&e) {
+                Error::with_chain(e, ErrorKind::Timeout)
+            } else {
+                e
+            }
+        })
+    }
+
+    pub fn get_ref(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> TcpStream {
+        self.stream
+    }
+
+    /// Time left until the deadline of the current operation.
+    fn remaining(&self) -> io::Result<Option<Duration>> {
+        if self.is_cancelled() {
+            return Err(io::Error::other("Operation was cancelled"));
+        }
+        match self.deadline {
+            None => Ok(None),
+            Some(deadline) => {
+                let now = Instant::now();
+                if now >= deadline {
+                    Err(io::ErrorKind::TimedOut.into())
+                } else {
+                    Ok(Some(deadline - now))
+                }
+            }
+        }
+    }
+}
+
+fn io_timed_out(e: &io::Error) -> bool {
+    // depending on the platform an expired socket timeout is reported as either of them
+    e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock
+}
+
+fn timed_out(e: &Error) -> bool {
+    match *e.kind() {
+        ErrorKind::IO(ref e) => io_timed_out(e),
+        ErrorKind::Websocket(::tungstenite::Error::Io(ref e)) => io_timed_out(e),
+        _ => false,
+    }
+}
+
+impl Read for DeadlineStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timeout = self.remaining()?;
+        self.stream.set_read_timeout(timeout)?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for DeadlineStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let timeout = self.remaining()?;
+        self.stream.set_write_timeout(timeout)?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::digest::sha3::SHA3_256;
+    use rand::{ChaChaRng, FromEntropy};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use std::thread;
+    use crate::sync::base_ot::chou::ChouOrlandiOTReceiver;
+    use crate::sync::communication::tcp::FramedTcp;
+    use crate::sync::communication::websockets::TimedWebSocket;
+    use crate::sync::communication::{BinaryReceive, BinarySend};
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use tungstenite::client;
+    use tungstenite::accept;
+    use url::Url;
+
+    /// A peer that accepts the connection and then stays silent until `done` is dropped.
+    fn silent_peer() -> (TcpStream, mpsc::Sender<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (done, wait) = mpsc::channel::<()>();
+        thread::spawn(move || {
+            let _stream = listener.accept().unwrap().0;
+            let _ = wait.recv();
+        });
+        (TcpStream::connect(addr).unwrap(), done)
+    }
+
+    #[test]
+    fn base_ot_times_out() {
+        let (stream, _done) = silent_peer();
+        let mut conn = FramedTcp::new(stream);
+        conn.set_timeout(Some(Duration::from_millis(200)));
+        let start = Instant::now();
+        let result = ChouOrlandiOTReceiver::new(
+            conn,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            ChaChaRng::from_entropy(),
+        );
+        match result {
+            Err(Error(ErrorKind::Timeout, _)) => (),
+            _ => panic!("Silent peer did not cause a timeout"),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn operations_can_be_cancelled() {
+        let (stream, _done) = silent_peer();
+        let mut conn = FramedTcp::new(stream);
+        let handle = conn.cancel_handle().unwrap();
+        let receiver = thread::spawn(move || {
+            let result = conn.receive().map(|_| ());
+            (result, conn.send(&[1, 2, 3]))
+        });
+        thread::sleep(Duration::from_millis(100));
+        handle.cancel();
+        let (received, sent) = receiver.join().unwrap();
+        for result in [received, sent] {
+            match result {
+                Err(Error(ErrorKind::Cancelled, _)) => (),
+                _ => panic!("Operation was not cancelled"),
+            }
+        }
+    }
+
+    #[test]
+    fn websocket_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (done, wait) = mpsc::channel::<()>();
+        let peer = thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            let mut ws = client(Url::parse("ws://localhost/").unwrap(), stream)
+                .unwrap()
+                .0;
+            BinarySend::send(&mut ws, &[1, 2, 3]).unwrap();
+            let _ = wait.recv();
+        });
+
+        let mut stream = DeadlineStream::new(listener.accept().unwrap().0);
+        stream.set_timeout(Some(Duration::from_millis(200)));
+        stream.start();
+        let mut ws = TimedWebSocket::new(accept(stream).unwrap());
+        assert_eq!(ws.receive().unwrap(), vec![1, 2, 3]);
+        match ws.receive() {
+            Err(Error(ErrorKind::Timeout, _)) => (),
+            _ => panic!("Silent peer did not cause a timeout"),
+        }
+        drop(done);
+        peer.join().unwrap();
+    }
+}
@@ -0,0 +1,97 @@
+//! In-memory connections, backed by the channels of the standard library.
+//!
+//! Both parties of a protocol can run in the same process (in different threads) without any sockets,
+//! which is what the tests and benchmarks of this library do.
+use super::{BinaryReceive, BinarySend};
+use crate::errors::*;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// One endpoint of a connection created with `channel_pair`.
+pub struct MemoryChannel {
+    sender: Sender<Vec<u8>>,
+    receiver: Receiver<Vec<u8>>,
+}
+
+/// Creates two connected endpoints, whatever is sent on one of them is received on the other.
+/// Once an endpoint is dropped, sending and receiving on the other one fails.
+pub fn channel_pair() -> (MemoryChannel, MemoryChannel) {
+    let (sender_a, receiver_b) = channel();
+    let (sender_b, receiver_a) = channel();
+    (
+        MemoryChannel {
+            sender: sender_a,
+            receiver: receiver_a,
+        },
+        MemoryChannel {
+            sender: sender_b,
+            receiver: receiver_b,
+        },
+    )
+}
+
+impl MemoryChannel {
+    /// Splits the endpoint into its sending and receiving half, which can be used from different threads.
+    pub fn split(self) -> (MemorySender, MemoryReceiver) {
+        (MemorySender(self.sender), MemoryReceiver(self.receiver))
+    }
+}
+
+/// The sending half of a `MemoryChannel`.
+pub struct MemorySender(Sender<Vec<u8>>);
+
+/// The receiving half of a `MemoryChannel`.
+pub struct MemoryReceiver(Receiver<Vec<u8>>);
+
+impl BinarySend for MemorySender {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.0
+            .send(data.to_vec())
+            .map_err(|_| ErrorKind::CommunicationError.into())
+    }
+}
+
+impl BinaryReceive for MemoryReceiver {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        self.0
+            .recv()
+            .map_err(|_| ErrorKind::CommunicationError.into())
+    }
+}
+
+impl BinarySend for MemoryChannel {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.sender
+            .send(data.to_vec())
+            .map_err(|_| ErrorKind::CommunicationError.into())
+    }
+}
+
+impl BinaryReceive for MemoryChannel {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        self.receiver
+            .recv()
+            .map_err(|_| ErrorKind::CommunicationError.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn channel_pair_test() {
+        let (mut a, mut b) = channel_pair();
+        let peer = thread::spawn(move || {
+            let frame = b.receive().unwrap();
+            b.send(&frame).unwrap();
+            b.send(&[]).unwrap();
+        });
+        a.send(&[1, 2, 3]).unwrap();
+        assert_eq!(a.receive().unwrap(), vec![1, 2, 3]);
+        assert_eq!(a.receive().unwrap(), Vec::<u8>::new());
+        peer.join().unwrap();
+        assert!(a.receive().is_err());
+        assert!(a.send(&[1]).is_err());
+    }
+}
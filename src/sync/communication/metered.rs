@@ -0,0 +1,279 @@
+//! Traffic accounting for any connection.
+//!
+//! `MeteredChannel` counts the bytes and frames going through it and the rounds of communication,
+//! i.e. the number of times the direction changed. The counters are shared with `Meter` handles,
+//! so they can still be read after the channel was moved into a protocol. Only the payload of
+//! the frames is counted, framing overhead like the length prefix of `TcpStream` is not.
+//! `Stopwatch` measures the time and traffic of the consecutive phases of a protocol.
+use super::{BinaryReceive, BinarySend};
+use crate::errors::*;
+use std::fmt;
+use std::ops::Sub;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The communication counted by a `MeteredChannel` since it was created or last reset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TrafficStats {
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    /// Number of maximal sequences of frames in the same direction,
+    /// a single round trip consists of two rounds.
+    pub rounds: u64,
+}
+
+impl TrafficStats {
+    pub fn bytes(&self) -> u64 {
+        self.bytes_sent + self.bytes_received
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames_sent + self.frames_received
+    }
+}
+
+/// The communication between two snapshots of the same meter.
+/// A round which spans both snapshots only counts for the earlier one.
+/// If the meter was reset in between the counters saturate at zero.
+impl Sub for TrafficStats {
+    type Output = TrafficStats;
+
+    fn sub(self, other: TrafficStats) -> TrafficStats {
+        TrafficStats {
+            bytes_sent: self.bytes_sent.saturating_sub(other.bytes_sent),
+            bytes_received: self.bytes_received.saturating_sub(other.bytes_received),
+            frames_sent: self.frames_sent.saturating_sub(other.frames_sent),
+            frames_received: self.frames_received.saturating_sub(other.frames_received),
+            rounds: self.rounds.saturating_sub(other.rounds),
+        }
+    }
+}
+
+impl fmt::Display for TrafficStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "sent {} bytes in {} frames, received {} bytes in {} frames, {} rounds",
+            self.bytes_sent, self.frames_sent, self.bytes_received, self.frames_received, self.rounds
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Send,
+    Receive,
+}
+
+#[derive(Default)]
+struct Counters {
+    stats: TrafficStats,
+    last: Option<Direction>,
+}
+
+impl Counters {
+    fn count(&mut self, direction: Direction, len: usize) {
+        match direction {
+            Direction::Send => {
+                self.stats.bytes_sent += len as u64;
+                self.stats.frames_sent += 1;
+            }
+            Direction::Receive => {
+                self.stats.bytes_received += len as u64;
+                self.stats.frames_received += 1;
+            }
+        }
+        if self.last != Some(direction) {
+            self.stats.rounds += 1;
+            self.last = Some(direction);
+        }
+    }
+}
+
+/// A handle to the counters of a `MeteredChannel`.
+#[derive(Clone)]
+pub struct Meter {
+    counters: Arc<Mutex<Counters>>,
+}
+
+impl Meter {
+    pub fn snapshot(&self) -> TrafficStats {
+        self.counters.lock().unwrap().stats
+    }
+
+    /// Sets all counters to zero. The next frame starts a new round,
+    /// so every phase between two resets counts at least one round if anything is sent.
+    pub fn reset(&self) {
+        *self.counters.lock().unwrap() = Counters::default();
+    }
+
+    /// Returns the snapshot and resets the counters at once, e.g. at the end of a phase.
+    pub fn take(&self) -> TrafficStats {
+        let mut counters = self.counters.lock().unwrap();
+        let stats = counters.stats;
+        *counters = Counters::default();
+        stats
+    }
+}
+
+/// The time and communication of one phase measured by a `Stopwatch`.
+#[derive(Clone, Copy, Debug)]
+pub struct Lap {
+    pub duration: Duration,
+    pub traffic: TrafficStats,
+}
+
+impl fmt::Display for Lap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}, {}", self.duration, self.traffic)
+    }
+}
+
+/// Measures consecutive phases of a protocol, e.g. base OT setup, extension setup and transfer.
+pub struct Stopwatch {
+    meter: Meter,
+    start: Instant,
+}
+
+impl Stopwatch {
+    /// Starts the first phase, the counters of `meter` are reset.
+    pub fn start(meter: Meter) -> Self {
+        meter.reset();
+        Stopwatch {
+            meter,
+            start: Instant::now(),
+        }
+    }
+
+    /// Ends the current phase and starts the next one.
+    pub fn lap(&mut self) -> Lap {
+        let lap = Lap {
+            duration: self.start.elapsed(),
+            traffic: self.meter.take(),
+        };
+        self.start = Instant::now();
+        lap
+    }
+}
+
+/// Wraps a connection and counts its traffic.
+pub struct MeteredChannel<C: BinarySend + BinaryReceive> {
+    conn: C,
+    meter: Meter,
+}
+
+impl<C: BinarySend + BinaryReceive> MeteredChannel<C> {
+    pub fn new(conn: C) -> Self {
+        MeteredChannel {
+            conn,
+            meter: Meter {
+                counters: Arc::new(Mutex::new(Counters::default())),
+            },
+        }
+    }
+
+    /// Returns a handle to the counters which stays valid when the channel is moved.
+    pub fn meter(&self) -> Meter {
+        self.meter.clone()
+    }
+
+    pub fn snapshot(&self) -> TrafficStats {
+        self.meter.snapshot()
+    }
+
+    pub fn reset(&self) {
+        self.meter.reset()
+    }
+
+    pub fn into_inner(self) -> C {
+        self.conn
+    }
+}
+
+impl<C: BinarySend + BinaryReceive> BinarySend for MeteredChannel<C> {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.conn.send(data)?;
+        self.meter
+            .counters
+            .lock()
+            .unwrap()
+            .count(Direction::Send, data.len());
+        Ok(())
+    }
+}
+
+impl<C: BinarySend + BinaryReceive> BinaryReceive for MeteredChannel<C> {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        let data = self.conn.receive()?;
+        self.meter
+            .counters
+            .lock()
+            .unwrap()
+            .count(Direction::Receive, data.len());
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use crate::sync::communication::memory::channel_pair;
+
+    #[test]
+    fn metered_channel_counts() {
+        let (a, mut b) = channel_pair();
+        let mut conn = MeteredChannel::new(a);
+        let meter = conn.meter();
+        let peer = thread::spawn(move || {
+            b.receive().unwrap();
+            b.receive().unwrap();
+            b.send(&[0; 5]).unwrap();
+            b.receive().unwrap();
+        });
+        conn.send(&[0; 10]).unwrap();
+        conn.send(&[]).unwrap();
+        let first = meter.snapshot();
+        conn.receive().unwrap();
+        conn.send(&[0; 3]).unwrap();
+        peer.join().unwrap();
+
+        let stats = conn.snapshot();
+        assert_eq!(
+            stats,
+            TrafficStats {
+                bytes_sent: 13,
+                bytes_received: 5,
+                frames_sent: 3,
+                frames_received: 1,
+                rounds: 3,
+            }
+        );
+        assert_eq!(stats.bytes(), 18);
+        assert_eq!(stats.frames(), 4);
+        assert_eq!((stats - first).bytes_sent, 3);
+        assert_eq!((stats - first).rounds, 2);
+
+        assert_eq!(meter.take(), stats);
+        assert_eq!(conn.snapshot(), TrafficStats::default());
+        // a snapshot from before a reset is larger than the current one
+        assert_eq!(conn.snapshot() - stats, TrafficStats::default());
+    }
+
+    #[test]
+    fn stopwatch_takes_traffic_per_phase() {
+        let (a, mut b) = channel_pair();
+        let mut conn = MeteredChannel::new(a);
+        conn.send(&[0; 7]).unwrap();
+        let mut stopwatch = Stopwatch::start(conn.meter());
+        conn.send(&[0; 2]).unwrap();
+        assert_eq!(stopwatch.lap().traffic.bytes_sent, 2);
+        b.send(&[0; 3]).unwrap();
+        conn.receive().unwrap();
+        let lap = stopwatch.lap();
+        assert_eq!(lap.traffic.bytes(), 3);
+        assert_eq!(lap.traffic.rounds, 1);
+    }
+}
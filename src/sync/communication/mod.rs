@@ -3,11 +3,26 @@
 //! While simple TCP and Websocket (courtesy of tungestenite)
 //! implementations of these traits are provided it should
 //! be trivial to implement them for other means of communications.
-use errors::*;
+//! For running both parties in one process `memory::channel_pair` creates a connection without sockets.
+//! `metered::MeteredChannel` counts the traffic of any connection and
+//! `simulated::SimulatedNetworkChannel` emulates latency and bandwidth limits.
+//! With `multiplex` several protocols can share a single connection.
+//! `secure::SecureChannel` authenticates and encrypts a connection.
+//! `adversary::AdversaryChannel` tampers with the frames of a connection, for testing against active adversaries.
+//! `deadline` adds timeouts and cancellation to TCP and websocket connections.
+//! `transcript::RecordingChannel` records the frames of a connection, `transcript::ReplayChannel` plays them back.
+use crate::errors::*;
 use std::vec::Vec;
 
-pub mod corrupted;
+pub mod adversary;
+pub mod deadline;
+pub mod memory;
+pub mod metered;
+pub mod multiplex;
+pub mod secure;
+pub mod simulated;
 pub mod tcp;
+pub mod transcript;
 pub mod websockets;
 
 pub trait BinarySend {
@@ -21,3 +36,8 @@ pub trait BinaryReceive {
 pub trait GetConn<C: BinarySend + BinaryReceive> {
     fn get_conn(self) -> C;
 }
+
+/// Like `GetConn`, but allows to use the connection of a protocol without consuming it.
+pub trait GetConnMut<C: BinarySend + BinaryReceive> {
+    fn get_conn_mut(&mut self) -> &mut C;
+}
@@ -0,0 +1,459 @@
+//! Several independent sessions over a single connection.
+//!
+//! `multiplex` splits a connection into a number of sub-channels, each of which implements
+//! `BinarySend` and `BinaryReceive` and can be moved into its own protocol and thread.
+//! Every frame is prepended with the (32 bit big endian) index of its sub-channel.
+//! Both parties have to create the same number of sub-channels.
+//!
+//! `multiplex` takes the connection as separate sending and receiving halves, because a
+//! sub-channel waiting for a frame must not keep the others from sending. This works for
+//! connections offering their own halves: `memory::MemoryChannel::split` and `TcpStream`
+//! (with `multiplex_tcp`).
+//!
+//! A thread reads all incoming frames and hands them to their sub-channels. It stops as soon as
+//! the connection fails or a frame for an unknown sub-channel arrives, after that every sub-channel
+//! fails to receive. The sending half is dropped with the last sub-channel, `multiplex_tcp` also shuts
+//! the socket down then, which ends the thread and closes the connection.
+//!
+//! Any other connection, e.g. a websocket or one of the wrapping channels, is multiplexed with
+//! `multiplex_shared`. As it can't send while it waits for a frame, a thread owning the connection
+//! takes turns with the one of the peer: each turn it sends a single frame with the pending frames of
+//! all sub-channels and then waits for the frame of the peer. A party without pending frames waits
+//! up to `MAX_IDLE_WAIT` before it passes the turn, so after a pause frames are delayed by up to
+//! twice that. The connection is closed once all sub-channels of one of the parties are dropped.
+//!
+//! The frames of each sub-channel are queued without bound until it receives them,
+//! so a sub-channel which is not read while its peer keeps sending grows without limit.
+use super::{BinaryReceive, BinarySend};
+use byte_tools::{read_u32_be, write_u32_be};
+use crate::common::util::{pack, unpack};
+use crate::errors::*;
+use rand::{thread_rng, Rng};
+use std::cmp;
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The longest time the thread of `multiplex_shared` waits for frames of its sub-channels
+/// before it passes the turn without any. The wait starts at `MIN_IDLE_WAIT` and doubles
+/// with every turn in which neither party had frames to send.
+pub const MAX_IDLE_WAIT: Duration = Duration::from_millis(50);
+const MIN_IDLE_WAIT: Duration = Duration::from_millis(1);
+/// `multiplex_shared` stops adding frames of its sub-channels to a turn beyond this size.
+const MAX_TURN_SIZE: usize = 1 << 24;
+
+type CloseHook = Box<dyn FnOnce() + Send>;
+
+/// The sending half shared by the sub-channels, `close` runs once the last of them is dropped.
+struct SharedSender<S> {
+    sender: Mutex<S>,
+    close: Mutex<Option<CloseHook>>,
+}
+
+impl<S> Drop for SharedSender<S> {
+    fn drop(&mut self) {
+        if let Ok(close) = self.close.get_mut() {
+            if let Some(close) = close.take() {
+                close();
+            }
+        }
+    }
+}
+
+/// One of the channels created by `multiplex`.
+pub struct SubChannel<S: BinarySend> {
+    id: u32,
+    sender: Arc<SharedSender<S>>,
+    receiver: Receiver<Vec<u8>>,
+}
+
+impl<S: BinarySend> SubChannel<S> {
+    /// Index of this sub-channel, the sub-channels with the same index of both parties are connected.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl<S: BinarySend> BinarySend for SubChannel<S> {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        let frame = with_id(self.id, data);
+        match self.sender.sender.lock() {
+            Ok(mut sender) => sender.send(&frame),
+            Err(_) => Err(ErrorKind::CommunicationError.into()),
+        }
+    }
+}
+
+impl<S: BinarySend> BinaryReceive for SubChannel<S> {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        self.receiver
+            .recv()
+            .map_err(|_| ErrorKind::CommunicationError.into())
+    }
+}
+
+/// Prepends the index of a sub-channel to its frame.
+fn with_id(id: u32, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0; 4 + data.len()];
+    write_u32_be(&mut frame[..4], id);
+    frame[4..].copy_from_slice(data);
+    frame
+}
+
+/// Hands a frame to the sub-channel given by its index.
+fn route(queues: &[Sender<Vec<u8>>], mut frame: Vec<u8>) -> Result<()> {
+    if frame.len() < 4 {
+        bail!("Received frame without sub-channel");
+    }
+    let id = read_u32_be(&frame[..4]) as usize;
+    let queue = match queues.get(id) {
+        Some(queue) => queue,
+        None => bail!("Received frame for unknown sub-channel {}", id),
+    };
+    // a sub-channel which was dropped does not stop the others
+    let _ = queue.send(frame.split_off(4));
+    Ok(())
+}
+
+fn demultiplex<R: BinaryReceive>(mut receiver: R, queues: Vec<Sender<Vec<u8>>>) -> Result<()> {
+    loop {
+        route(&queues, receiver.receive()?)?;
+    }
+}
+
+/// Splits the connection given by `sender` and `receiver` into `channels` sub-channels.
+pub fn multiplex<S, R>(sender: S, receiver: R, channels: usize) -> Vec<SubChannel<S>>
+where
+    S: BinarySend,
+    R: BinaryReceive + Send + 'static,
+{
+    multiplex_with_close(sender, receiver, channels, None)
+}
+
+fn multiplex_with_close<S, R>(
+    sender: S,
+    receiver: R,
+    channels: usize,
+    close: Option<CloseHook>,
+) -> Vec<SubChannel<S>>
+where
+    S: BinarySend,
+    R: BinaryReceive + Send + 'static,
+{
+    assert!(channels as u64 <= u32::MAX as u64 + 1);
+    let sender = Arc::new(SharedSender {
+        sender: Mutex::new(sender),
+        close: Mutex::new(close),
+    });
+    let (queues, receivers): (Vec<_>, Vec<_>) = (0..channels).map(|_| channel()).unzip();
+    thread::spawn(move || demultiplex(receiver, queues));
+    receivers
+        .into_iter()
+        .enumerate()
+        .map(|(id, receiver)| SubChannel {
+            id: id as u32,
+            sender: Arc::clone(&sender),
+            receiver,
+        })
+        .collect()
+}
+
+/// Like `multiplex`, the stream is cloned to get its halves.
+/// Dropping the last sub-channel shuts the stream down, which also stops the receiving thread.
+pub fn multiplex_tcp(stream: TcpStream, channels: usize) -> Result<Vec<SubChannel<TcpStream>>> {
+    let receiver = stream.try_clone()?;
+    let closer = stream.try_clone()?;
+    let close: CloseHook = Box::new(move || {
+        // the peer may have closed the connection already
+        let _ = closer.shutdown(Shutdown::Both);
+    });
+    Ok(multiplex_with_close(stream, receiver, channels, Some(close)))
+}
+
+/// One of the channels created by `multiplex_shared`.
+pub struct SharedSubChannel {
+    id: u32,
+    outgoing: Sender<Vec<u8>>,
+    receiver: Receiver<Vec<u8>>,
+}
+
+impl SharedSubChannel {
+    /// Index of this sub-channel, the sub-channels with the same index of both parties are connected.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl BinarySend for SharedSubChannel {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.outgoing
+            .send(with_id(self.id, data))
+            .map_err(|_| ErrorKind::CommunicationError.into())
+    }
+}
+
+impl BinaryReceive for SharedSubChannel {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        self.receiver
+            .recv()
+            .map_err(|_| ErrorKind::CommunicationError.into())
+    }
+}
+
+/// Splits a connection which can't be split into halves into `channels` sub-channels,
+/// see the module documentation. Both parties have to use `multiplex_shared`.
+pub fn multiplex_shared<C>(conn: C, channels: usize) -> Vec<SharedSubChannel>
+where
+    C: BinarySend + BinaryReceive + Send + 'static,
+{
+    assert!(channels as u64 <= u32::MAX as u64 + 1);
+    let (outgoing, frames) = channel();
+    let (queues, receivers): (Vec<_>, Vec<_>) = (0..channels).map(|_| channel()).unzip();
+    thread::spawn(move || take_turns(conn, frames, queues));
+    receivers
+        .into_iter()
+        .enumerate()
+        .map(|(id, receiver)| SharedSubChannel {
+            id: id as u32,
+            outgoing: outgoing.clone(),
+            receiver,
+        })
+        .collect()
+}
+
+/// Waits up to `wait` for a frame of the sub-channels and takes all pending ones,
+/// the flag tells whether all sub-channels have been dropped.
+fn next_turn(frames: &Receiver<Vec<u8>>, wait: Duration) -> (Vec<Vec<u8>>, bool) {
+    let mut turn = match frames.recv_timeout(wait) {
+        Ok(frame) => vec![frame],
+        Err(RecvTimeoutError::Timeout) => return (Vec::new(), false),
+        Err(RecvTimeoutError::Disconnected) => return (Vec::new(), true),
+    };
+    let mut size = turn[0].len();
+    while size < MAX_TURN_SIZE {
+        match frames.try_recv() {
+            Ok(frame) => {
+                size += frame.len();
+                turn.push(frame);
+            }
+            Err(TryRecvError::Empty) => break,
+            Err(TryRecvError::Disconnected) => return (turn, true),
+        }
+    }
+    (turn, false)
+}
+
+/// Sends and receives the frames of all sub-channels of `multiplex_shared` in alternating turns.
+/// Every turn is a frame starting with a byte which is 1 for the last turn, followed by the packed
+/// frames of the sub-channels.
+fn take_turns<C>(mut conn: C, frames: Receiver<Vec<u8>>, queues: Vec<Sender<Vec<u8>>>) -> Result<()>
+where
+    C: BinarySend + BinaryReceive,
+{
+    // the party with the larger random nonce takes the first turn
+    let nonce: [u8; 16] = thread_rng().gen();
+    conn.send(&nonce)?;
+    let other = conn.receive()?;
+    if other.len() != nonce.len() || other[..] == nonce[..] {
+        bail!("Could not decide which party takes the first turn");
+    }
+    let mut our_turn = nonce[..] > other[..];
+    let mut wait = MIN_IDLE_WAIT;
+    loop {
+        let idle;
+        if our_turn {
+            let (turn, last) = next_turn(&frames, wait);
+            idle = turn.is_empty();
+            let mut frame = vec![last as u8];
+            frame.extend_from_slice(&pack(&turn));
+            conn.send(&frame)?;
+            if last {
+                return Ok(());
+            }
+        } else {
+            let frame = conn.receive()?;
+            if frame.is_empty() {
+                bail!("Received empty turn");
+            }
+            let turn = unpack(&frame[1..])?;
+            idle = turn.is_empty();
+            for frame in turn {
+                route(&queues, frame)?;
+            }
+            if frame[0] != 0 {
+                return Ok(());
+            }
+        }
+        wait = if idle {
+            cmp::min(wait * 2, MAX_IDLE_WAIT)
+        } else {
+            MIN_IDLE_WAIT
+        };
+        our_turn = !our_turn;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::digest::sha3::SHA3_256;
+    use crate::common::util::{generate_random_choices, generate_random_string_pairs};
+    use rand::{ChaChaRng, FromEntropy};
+    use crate::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+    use crate::sync::communication::memory::channel_pair;
+    use std::net::TcpListener;
+    use tungstenite::{accept, client};
+    use url::Url;
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
+    use crate::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
+
+    #[test]
+    fn frames_are_routed() {
+        let (a, b) = channel_pair();
+        let (a_sender, a_receiver) = a.split();
+        let (mut b_sender, mut b_receiver) = b.split();
+        let mut subs = multiplex(a_sender, a_receiver, 2);
+
+        subs[1].send(&[1, 2]).unwrap();
+        assert_eq!(b_receiver.receive().unwrap(), vec![0, 0, 0, 1, 1, 2]);
+        b_sender.send(&[0, 0, 0, 1, 3]).unwrap();
+        b_sender.send(&[0, 0, 0, 0, 4]).unwrap();
+        assert_eq!(subs[0].receive().unwrap(), vec![4]);
+        assert_eq!(subs[1].receive().unwrap(), vec![3]);
+
+        b_sender.send(&[0, 0, 0, 2]).unwrap();
+        assert!(subs[0].receive().is_err());
+        assert!(subs[1].receive().is_err());
+    }
+
+    #[test]
+    fn tcp_is_shut_down_with_last_sub_channel() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut subs = multiplex_tcp(TcpStream::connect(addr).unwrap(), 2).unwrap();
+            let frame = subs[1].receive().unwrap();
+            subs[0].send(&frame).unwrap();
+            // the server drops its sub-channels after the echo, which closes the connection
+            assert!(subs[1].receive().is_err());
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let mut subs = multiplex_tcp(stream, 2).unwrap();
+        subs[1].send(&[1, 2, 3]).unwrap();
+        assert_eq!(subs[0].receive().unwrap(), vec![1, 2, 3]);
+        drop(subs);
+        client.join().unwrap();
+    }
+
+    #[test]
+    fn shared_frames_are_routed() {
+        let (a, b) = channel_pair();
+        let mut a_subs = multiplex_shared(a, 3);
+        let mut b_subs = multiplex_shared(b, 3);
+
+        a_subs[2].send(&[1, 2]).unwrap();
+        a_subs[0].send(&[3]).unwrap();
+        b_subs[1].send(&[]).unwrap();
+        assert_eq!(b_subs[0].receive().unwrap(), vec![3]);
+        assert_eq!(b_subs[2].receive().unwrap(), vec![1, 2]);
+        assert_eq!(a_subs[1].receive().unwrap(), Vec::<u8>::new());
+
+        // dropping all sub-channels of one party closes the connection
+        drop(a_subs);
+        assert!(b_subs[0].receive().is_err());
+        assert!(b_subs[1].receive().is_err());
+    }
+
+    #[test]
+    fn concurrent_iknp_sessions() {
+        let sessions = 4;
+        let (server_conn, client_conn) = channel_pair();
+        let (sender, receiver) = server_conn.split();
+        let server_subs = multiplex(sender, receiver, sessions);
+        let (sender, receiver) = client_conn.split();
+        let client_subs = multiplex(sender, receiver, sessions);
+        iknp_sessions(server_subs, client_subs);
+    }
+
+    #[test]
+    fn concurrent_iknp_sessions_over_shared_channel() {
+        let (server_conn, client_conn) = channel_pair();
+        iknp_sessions(multiplex_shared(server_conn, 4), multiplex_shared(client_conn, 4));
+    }
+
+    #[test]
+    fn concurrent_iknp_sessions_over_websocket() {
+        let sessions = 3;
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client_subs = thread::spawn(move || {
+            let stream = TcpStream::connect(addr).unwrap();
+            let ws = client(Url::parse("ws://localhost/").unwrap(), stream)
+                .unwrap()
+                .0;
+            multiplex_shared(ws, sessions)
+        });
+        let ws = accept(listener.accept().unwrap().0).unwrap();
+        let server_subs = multiplex_shared(ws, sessions);
+        iknp_sessions(server_subs, client_subs.join().unwrap());
+    }
+
+    /// Runs an IKNP session over each pair of connected sub-channels at the same time.
+    fn iknp_sessions<S>(server_subs: Vec<S>, client_subs: Vec<S>)
+    where
+        S: BinarySend + BinaryReceive + Send + 'static,
+    {
+        let n = 500;
+        let l = 16;
+        let mut threads = Vec::new();
+        for (server_sub, client_sub) in server_subs.into_iter().zip(client_subs) {
+            let choices = generate_random_choices(n);
+            let values = generate_random_string_pairs(l, n);
+            let choices2 = choices.clone();
+            let values2 = values.clone();
+            let server = thread::spawn(move || {
+                let rng = ChaChaRng::from_entropy();
+                let ot = ChouOrlandiOTSender::new(
+                    server_sub,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    rng.clone(),
+                ).unwrap();
+                let mut ot_ext =
+                    IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, rng, 16).unwrap();
+                ot_ext.receive(&choices2).unwrap()
+            });
+            let client = thread::spawn(move || {
+                let rng = ChaChaRng::from_entropy();
+                let ot = ChouOrlandiOTReceiver::new(
+                    client_sub,
+                    SHA3_256::default(),
+                    AesCryptoProvider::default(),
+                    rng.clone(),
+                ).unwrap();
+                let mut ot_ext =
+                    IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng, 16).unwrap();
+                ot_ext
+                    .send(
+                        values2
+                            .iter()
+                            .map(|(s0, s1)| (s0.as_bytes(), s1.as_bytes()))
+                            .collect(),
+                    )
+                    .unwrap();
+            });
+            threads.push((server, client, choices, values));
+        }
+        for (server, client, choices, values) in threads {
+            let result = server.join().unwrap();
+            client.join().unwrap();
+            for (r, c, (s0, s1)) in izip!(result, &choices, values) {
+                let expected = if c { s1 } else { s0 };
+                assert_eq!(r, expected.into_bytes());
+            }
+        }
+    }
+}
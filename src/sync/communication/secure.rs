@@ -0,0 +1,365 @@
+//! Authenticated and encrypted connections.
+//!
+//! `SecureChannel` runs the handshake of the Noise protocol framework [http://noiseprotocol.org/noise.html]
+//! with the pattern XX, `Noise_XX_25519_ChaChaPoly_SHA256`, over an existing connection.
+//! Both parties have a static X25519 key pair and learn the public key of the other one,
+//! which has to be checked by the application (e.g. against a list of known keys) before trusting it.
+//! After the handshake every frame is encrypted and authenticated with ChaCha20-Poly1305,
+//! so frames cannot be read, modified, dropped, reordered or replayed by the network.
+use super::{BinaryReceive, BinarySend};
+use byte_tools::write_u64_le;
+use curve25519_dalek::constants::X25519_BASEPOINT;
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use crate::errors::*;
+use rand::{CryptoRng, RngCore};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305};
+use ring::digest::{digest, SHA256};
+use ring::hmac::{sign, Key, HMAC_SHA256};
+
+const PROTOCOL_NAME: &[u8; 32] = b"Noise_XX_25519_ChaChaPoly_SHA256";
+const DH_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+
+/// A static X25519 key pair identifying a party.
+#[derive(Clone)]
+pub struct StaticKeypair {
+    secret: [u8; 32],
+    public: [u8; 32],
+}
+
+impl StaticKeypair {
+    pub fn generate<R: RngCore + CryptoRng>(rng: &mut R) -> Self {
+        let mut secret = [0; 32];
+        rng.fill_bytes(&mut secret);
+        StaticKeypair::from_secret(secret)
+    }
+
+    pub fn from_secret(secret: [u8; 32]) -> Self {
+        let public = x25519(&secret, &X25519_BASEPOINT);
+        StaticKeypair { secret, public }
+    }
+
+    pub fn public(&self) -> [u8; 32] {
+        self.public
+    }
+}
+
+fn x25519(secret: &[u8; 32], point: &MontgomeryPoint) -> [u8; 32] {
+    point.mul_clamped(*secret).to_bytes()
+}
+
+/// Diffie-Hellman with a received public key, low order points are rejected.
+fn dh(secret: &[u8; 32], public: &[u8]) -> Result<[u8; 32]> {
+    let shared = x25519(secret, &MontgomeryPoint(*array_ref![public, 0, DH_LEN]));
+    if shared == [0; 32] {
+        return Err(ErrorKind::PointError.into());
+    }
+    Ok(shared)
+}
+
+fn hmac(key: &[u8], data: &[&[u8]]) -> Vec<u8> {
+    let key = Key::new(HMAC_SHA256, key);
+    let data: Vec<u8> = data.iter().flat_map(|d| d.iter().cloned()).collect();
+    sign(&key, &data).as_ref().to_vec()
+}
+
+/// The HKDF function of the specification, returns two outputs.
+fn hkdf(chaining_key: &[u8], input_key_material: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let temp_key = hmac(chaining_key, &[input_key_material]);
+    let output1 = hmac(&temp_key, &[&[1]]);
+    let output2 = hmac(&temp_key, &[&output1, &[2]]);
+    (output1, output2)
+}
+
+fn nonce(n: u64) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    write_u64_le(&mut nonce[4..], n);
+    nonce
+}
+
+/// A key with its nonce counter, one for each direction.
+struct CipherState {
+    key: Vec<u8>,
+    n: u64,
+}
+
+impl CipherState {
+    fn new(key: Vec<u8>) -> Self {
+        CipherState { key, n: 0 }
+    }
+
+    fn next_nonce(&mut self) -> Result<[u8; 12]> {
+        if self.n == u64::MAX {
+            bail!("Nonces of the secure channel are exhausted");
+        }
+        let n = nonce(self.n);
+        self.n += 1;
+        Ok(n)
+    }
+
+    fn encrypt(&mut self, ad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = self.next_nonce()?;
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &self.key)
+            .map(LessSafeKey::new)
+            .chain_err(|| "Couldn't create chacha20-poly1305 encryption key")?;
+        let mut data = plaintext.to_vec();
+        key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(ad), &mut data)
+            .chain_err(|| "Couldn't encrypt frame")?;
+        Ok(data)
+    }
+
+    fn decrypt(&mut self, ad: &[u8], mut ciphertext: Vec<u8>) -> Result<Vec<u8>> {
+        let nonce = self.next_nonce()?;
+        let key = UnboundKey::new(&CHACHA20_POLY1305, &self.key)
+            .map(LessSafeKey::new)
+            .chain_err(|| "Couldn't create chacha20-poly1305 decryption key")?;
+        let len = key
+            .open_in_place(Nonce::assume_unique_for_key(nonce), Aad::from(ad), &mut ciphertext)
+            .chain_err(|| "Couldn't decrypt frame, it was modified or is out of order")?
+            .len();
+        ciphertext.truncate(len);
+        Ok(ciphertext)
+    }
+}
+
+/// The state of the handshake, named as in the specification.
+struct SymmetricState {
+    chaining_key: Vec<u8>,
+    hash: Vec<u8>,
+    cipher: Option<CipherState>,
+}
+
+impl SymmetricState {
+    fn new() -> Self {
+        // the name has exactly the length of a hash, so it is used as it is
+        let mut state = SymmetricState {
+            chaining_key: PROTOCOL_NAME.to_vec(),
+            hash: PROTOCOL_NAME.to_vec(),
+            cipher: None,
+        };
+        // empty prologue
+        state.mix_hash(&[]);
+        state
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        let mut input = self.hash.clone();
+        input.extend_from_slice(data);
+        self.hash = digest(&SHA256, &input).as_ref().to_vec();
+    }
+
+    fn mix_key(&mut self, input_key_material: &[u8]) {
+        let (chaining_key, key) = hkdf(&self.chaining_key, input_key_material);
+        self.chaining_key = chaining_key;
+        self.cipher = Some(CipherState::new(key));
+    }
+
+    fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let ciphertext = match self.cipher {
+            Some(ref mut cipher) => cipher.encrypt(&self.hash, plaintext)?,
+            None => plaintext.to_vec(),
+        };
+        self.mix_hash(&ciphertext);
+        Ok(ciphertext)
+    }
+
+    fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let plaintext = match self.cipher {
+            Some(ref mut cipher) => cipher.decrypt(&self.hash, ciphertext.to_vec())?,
+            None => ciphertext.to_vec(),
+        };
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+
+    /// Returns the ciphers for the initiator to responder and the responder to initiator direction.
+    fn split(&self) -> (CipherState, CipherState) {
+        let (k1, k2) = hkdf(&self.chaining_key, &[]);
+        (CipherState::new(k1[..32].to_vec()), CipherState::new(k2[..32].to_vec()))
+    }
+}
+
+/// A connection which is encrypted and authenticated with the keys established in a Noise handshake.
+pub struct SecureChannel<C: BinarySend + BinaryReceive> {
+    conn: C,
+    send_cipher: CipherState,
+    receive_cipher: CipherState,
+    remote_static: [u8; 32],
+    handshake_hash: Vec<u8>,
+}
+
+impl<C: BinarySend + BinaryReceive> SecureChannel<C> {
+    /// Runs the handshake as initiator, the other party has to call `respond`.
+    pub fn initiate<R: RngCore + CryptoRng>(
+        mut conn: C,
+        keypair: &StaticKeypair,
+        rng: &mut R,
+    ) -> Result<Self> {
+        let mut state = SymmetricState::new();
+        let ephemeral = StaticKeypair::generate(rng);
+
+        // -> e
+        state.mix_hash(&ephemeral.public);
+        let mut message = ephemeral.public.to_vec();
+        message.extend(state.encrypt_and_hash(&[])?);
+        conn.send(&message)?;
+
+        // <- e, ee, s, es
+        let message = conn.receive()?;
+        if message.len() != DH_LEN + DH_LEN + TAG_LEN + TAG_LEN {
+            bail!("Received handshake message has the wrong length");
+        }
+        let remote_ephemeral = &message[..DH_LEN];
+        state.mix_hash(remote_ephemeral);
+        state.mix_key(&dh(&ephemeral.secret, remote_ephemeral)?);
+        let remote_static = state.decrypt_and_hash(&message[DH_LEN..2 * DH_LEN + TAG_LEN])?;
+        state.mix_key(&dh(&ephemeral.secret, &remote_static)?);
+        state.decrypt_and_hash(&message[2 * DH_LEN + TAG_LEN..])?;
+
+        // -> s, se
+        let mut message = state.encrypt_and_hash(&keypair.public)?;
+        state.mix_key(&dh(&keypair.secret, remote_ephemeral)?);
+        message.extend(state.encrypt_and_hash(&[])?);
+        conn.send(&message)?;
+
+        let (send_cipher, receive_cipher) = state.split();
+        Ok(SecureChannel {
+            conn,
+            send_cipher,
+            receive_cipher,
+            remote_static: *array_ref![remote_static, 0, DH_LEN],
+            handshake_hash: state.hash,
+        })
+    }
+
+    /// Runs the handshake as responder, the other party has to call `initiate`.
+    pub fn respond<R: RngCore + CryptoRng>(
+        mut conn: C,
+        keypair: &StaticKeypair,
+        rng: &mut R,
+    ) -> Result<Self> {
+        let mut state = SymmetricState::new();
+
+        // -> e
+        let message = conn.receive()?;
+        if message.len() != DH_LEN {
+            bail!("Received handshake message has the wrong length");
+        }
+        let remote_ephemeral = message;
+        state.mix_hash(&remote_ephemeral);
+        state.decrypt_and_hash(&[])?;
+
+        // <- e, ee, s, es
+        let ephemeral = StaticKeypair::generate(rng);
+        state.mix_hash(&ephemeral.public);
+        let mut message = ephemeral.public.to_vec();
+        state.mix_key(&dh(&ephemeral.secret, &remote_ephemeral)?);
+        message.extend(state.encrypt_and_hash(&keypair.public)?);
+        state.mix_key(&dh(&keypair.secret, &remote_ephemeral)?);
+        message.extend(state.encrypt_and_hash(&[])?);
+        conn.send(&message)?;
+
+        // -> s, se
+        let message = conn.receive()?;
+        if message.len() != DH_LEN + TAG_LEN + TAG_LEN {
+            bail!("Received handshake message has the wrong length");
+        }
+        let remote_static = state.decrypt_and_hash(&message[..DH_LEN + TAG_LEN])?;
+        state.mix_key(&dh(&ephemeral.secret, &remote_static)?);
+        state.decrypt_and_hash(&message[DH_LEN + TAG_LEN..])?;
+
+        let (receive_cipher, send_cipher) = state.split();
+        Ok(SecureChannel {
+            conn,
+            send_cipher,
+            receive_cipher,
+            remote_static: *array_ref![remote_static, 0, DH_LEN],
+            handshake_hash: state.hash,
+        })
+    }
+
+    /// The static public key of the other party, which it proved to own.
+    pub fn remote_static_key(&self) -> &[u8; 32] {
+        &self.remote_static
+    }
+
+    /// A hash of the whole handshake, which is the same for both parties and unique for every connection.
+    pub fn handshake_hash(&self) -> &[u8] {
+        &self.handshake_hash
+    }
+}
+
+impl<C: BinarySend + BinaryReceive> BinarySend for SecureChannel<C> {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        let frame = self.send_cipher.encrypt(&[], data)?;
+        self.conn.send(&frame)
+    }
+}
+
+impl<C: BinarySend + BinaryReceive> BinaryReceive for SecureChannel<C> {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        let frame = self.conn.receive()?;
+        self.receive_cipher.decrypt(&[], frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{ChaChaRng, FromEntropy};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use crate::sync::communication::adversary::{AdversaryChannel, Attack};
+    use crate::sync::communication::memory::channel_pair;
+
+    #[test]
+    fn secure_channel_over_tcp() {
+        let mut rng = ChaChaRng::from_entropy();
+        let server_keys = StaticKeypair::generate(&mut rng);
+        let client_keys = StaticKeypair::generate(&mut rng);
+        let server_public = server_keys.public();
+        let client_public = client_keys.public();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let stream = listener.accept().unwrap().0;
+            let mut rng = ChaChaRng::from_entropy();
+            let mut conn = SecureChannel::respond(stream, &server_keys, &mut rng).unwrap();
+            let frame = conn.receive().unwrap();
+            conn.send(&frame).unwrap();
+            conn.send(&[]).unwrap();
+            (*conn.remote_static_key(), conn.handshake_hash().to_vec())
+        });
+        let stream = TcpStream::connect(addr).unwrap();
+        let mut conn = SecureChannel::initiate(stream, &client_keys, &mut rng).unwrap();
+        conn.send(b"oblivious").unwrap();
+        assert_eq!(conn.receive().unwrap(), b"oblivious".to_vec());
+        assert_eq!(conn.receive().unwrap(), Vec::<u8>::new());
+        let (seen_by_server, hash) = server.join().unwrap();
+
+        assert_eq!(conn.remote_static_key(), &server_public);
+        assert_eq!(seen_by_server, client_public);
+        assert_eq!(conn.handshake_hash(), hash.as_slice());
+    }
+
+    #[test]
+    fn modified_frames_are_rejected() {
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let mut rng = ChaChaRng::from_entropy();
+            let keys = StaticKeypair::generate(&mut rng);
+            let mut conn = SecureChannel::respond(server_conn, &keys, &mut rng).unwrap();
+            conn.send(&[1, 2, 3]).unwrap();
+            conn.send(&[4, 5, 6]).unwrap();
+        });
+        let mut rng = ChaChaRng::from_entropy();
+        let keys = StaticKeypair::generate(&mut rng);
+        // the client receives the second handshake message and then both frames of the server
+        let conn = AdversaryChannel::new(client_conn, vec![Attack::FlipBit { frame: 2, bit: 0 }]);
+        let mut conn = SecureChannel::initiate(conn, &keys, &mut rng).unwrap();
+        server.join().unwrap();
+        assert_eq!(conn.receive().unwrap(), vec![1, 2, 3]);
+        assert!(conn.receive().is_err());
+    }
+}
@@ -0,0 +1,213 @@
+//! Emulation of a network with latency and limited bandwidth, e.g. to compare protocols
+//! under LAN and WAN conditions on a single machine.
+//!
+//! Both ends of a connection have to be wrapped in a `SimulatedNetworkChannel`, as every frame
+//! is prepended with the (wall clock) time at which it is delivered. The sender waits until its
+//! token bucket allows the frame to go out and stamps it with the current time plus latency and jitter,
+//! the receiver then waits until that time has come. So the profile of an end only applies to the
+//! frames it sends, and frames sent without waiting for an answer are delayed only once.
+use super::{BinaryReceive, BinarySend};
+use byte_tools::{read_u64_be, write_u64_be};
+use crate::errors::*;
+use rand::{ChaChaRng, Rng, SeedableRng};
+use std::cmp;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The characteristics of one direction of a simulated link.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NetworkProfile {
+    /// One-way delay of every frame.
+    pub latency: Duration,
+    /// Maximum additional delay, uniformly distributed. Frames are never reordered.
+    pub jitter: Duration,
+    /// Throughput in bytes per second, no limit if `None`.
+    pub bandwidth: Option<u64>,
+    /// Number of bytes which can be sent at once before the bandwidth limit kicks in.
+    pub burst: u64,
+}
+
+impl NetworkProfile {
+    /// 0.25 ms latency and 1 Gbit/s.
+    pub fn lan() -> Self {
+        NetworkProfile {
+            latency: Duration::from_micros(250),
+            jitter: Duration::from_micros(50),
+            bandwidth: Some(125_000_000),
+            burst: 1 << 16,
+        }
+    }
+
+    /// 40 ms latency and 50 Mbit/s.
+    pub fn wan() -> Self {
+        NetworkProfile {
+            latency: Duration::from_millis(40),
+            jitter: Duration::from_millis(5),
+            bandwidth: Some(6_250_000),
+            burst: 1 << 16,
+        }
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_secs() * 1_000_000_000 + duration.subsec_nanos() as u64
+}
+
+fn now() -> u64 {
+    nanos(SystemTime::now().duration_since(UNIX_EPOCH).unwrap())
+}
+
+struct TokenBucket {
+    rate: u64,
+    capacity: u64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    /// Takes `len` tokens and returns how long to wait until they are available.
+    /// A frame larger than the capacity just leaves the bucket in debt.
+    fn take(&mut self, len: usize) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last);
+        self.last = now;
+        let refill = nanos(elapsed) as f64 * self.rate as f64 / 1e9;
+        self.tokens = (self.tokens + refill).min(self.capacity as f64) - len as f64;
+        if self.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_nanos((-self.tokens * 1e9 / self.rate as f64) as u64)
+        }
+    }
+}
+
+/// Wraps a connection and delays the frames sent over it according to a `NetworkProfile`.
+pub struct SimulatedNetworkChannel<C: BinarySend + BinaryReceive> {
+    conn: C,
+    profile: NetworkProfile,
+    bucket: Option<TokenBucket>,
+    rng: ChaChaRng,
+    last_delivery: u64,
+}
+
+impl<C: BinarySend + BinaryReceive> SimulatedNetworkChannel<C> {
+    /// The jitter is drawn from a generator with a fixed seed, so runs are reproducible.
+    pub fn new(conn: C, profile: NetworkProfile) -> Self {
+        let bucket = profile.bandwidth.map(|rate| TokenBucket {
+            rate: cmp::max(rate, 1),
+            capacity: profile.burst,
+            tokens: profile.burst as f64,
+            last: Instant::now(),
+        });
+        SimulatedNetworkChannel {
+            conn,
+            profile,
+            bucket,
+            rng: ChaChaRng::from_seed([0; 32]),
+            last_delivery: 0,
+        }
+    }
+
+    pub fn set_seed(&mut self, seed: [u8; 32]) {
+        self.rng = ChaChaRng::from_seed(seed);
+    }
+
+    pub fn profile(&self) -> NetworkProfile {
+        self.profile
+    }
+
+    pub fn into_inner(self) -> C {
+        self.conn
+    }
+}
+
+impl<C: BinarySend + BinaryReceive> BinarySend for SimulatedNetworkChannel<C> {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        if let Some(ref mut bucket) = self.bucket {
+            thread::sleep(bucket.take(data.len()));
+        }
+        let jitter = nanos(self.profile.jitter);
+        let jitter = if jitter == 0 {
+            0
+        } else {
+            self.rng.gen_range(0, jitter + 1)
+        };
+        // frames stay in order even if a later one has less jitter
+        let delivery = cmp::max(now() + nanos(self.profile.latency) + jitter, self.last_delivery);
+        self.last_delivery = delivery;
+
+        let mut frame = vec![0; 8 + data.len()];
+        write_u64_be(&mut frame[..8], delivery);
+        frame[8..].copy_from_slice(data);
+        self.conn.send(&frame)
+    }
+}
+
+impl<C: BinarySend + BinaryReceive> BinaryReceive for SimulatedNetworkChannel<C> {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        let mut frame = self.conn.receive()?;
+        if frame.len() < 8 {
+            bail!("Received frame without delivery time");
+        }
+        let delivery = read_u64_be(&frame[..8]);
+        let now = now();
+        if delivery > now {
+            thread::sleep(Duration::from_nanos(delivery - now));
+        }
+        Ok(frame.split_off(8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::communication::memory::channel_pair;
+
+    #[test]
+    fn latency_is_added_per_round() {
+        let profile = NetworkProfile {
+            latency: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let (a, b) = channel_pair();
+        let mut a = SimulatedNetworkChannel::new(a, profile);
+        let mut b = SimulatedNetworkChannel::new(b, profile);
+        let peer = thread::spawn(move || {
+            let frame = b.receive().unwrap();
+            b.receive().unwrap();
+            b.send(&frame).unwrap();
+        });
+        let start = Instant::now();
+        // the second frame is sent right after the first one, so it does not add latency
+        a.send(&[1, 2, 3]).unwrap();
+        a.send(&[]).unwrap();
+        assert_eq!(a.receive().unwrap(), vec![1, 2, 3]);
+        let elapsed = start.elapsed();
+        peer.join().unwrap();
+        assert!(elapsed >= Duration::from_millis(400));
+        // latency for the second frame would add another 200ms, the rest is slack for busy machines
+        assert!(elapsed < Duration::from_millis(600));
+    }
+
+    #[test]
+    fn bandwidth_is_limited() {
+        let profile = NetworkProfile {
+            bandwidth: Some(100_000),
+            burst: 1000,
+            ..Default::default()
+        };
+        let (a, b) = channel_pair();
+        let mut a = SimulatedNetworkChannel::new(a, profile);
+        let mut b = SimulatedNetworkChannel::new(b, profile);
+        let start = Instant::now();
+        // the burst goes out at once, the remaining 5000 bytes take 50 ms
+        for _ in 0..6 {
+            a.send(&[0; 1000]).unwrap();
+        }
+        let elapsed = start.elapsed();
+        for _ in 0..6 {
+            assert_eq!(b.receive().unwrap().len(), 1000);
+        }
+        assert!(elapsed >= Duration::from_millis(45));
+    }
+}
@@ -1,31 +1,152 @@
+//! Simple protocol: data gets its length (as 64 bit big endian integer) prepended and sent.
+//!
+//! A peer can announce arbitrary lengths, so received frames are limited in size.
+//! `TcpStream` itself accepts frames up to `DEFAULT_MAX_FRAME_SIZE`, with `FramedTcp`
+//! the limit can be configured.
+//!
+//! `FramedTcp` also supports per-operation timeouts and cancellation, see `deadline`.
+use super::deadline::{CancelHandle, DeadlineStream};
 use super::{BinaryReceive, BinarySend};
-/// simple protocol: data gets it's length prepended and send
-use errors::*;
+use byte_tools::{read_u64_be, write_u64_be};
+use crate::errors::*;
+use std::cmp;
 use std::io::{Read, Write};
-use std::mem::transmute;
 use std::net::TcpStream;
+use std::time::Duration;
 use std::vec::Vec;
 
-// TODO: find alternative for transmute!
+/// Maximum size of a received frame in bytes unless configured otherwise.
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 1 << 28;
+
+fn send_frame<W: Write>(writer: &mut W, data: &[u8]) -> Result<()> {
+    let mut bytes = [0; 8];
+    write_u64_be(&mut bytes, data.len() as u64);
+    writer.write_all(&bytes)?;
+    writer.write_all(data)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn receive_frame<R: Read>(reader: &mut R, max_frame_size: usize) -> Result<Vec<u8>> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    let len = read_u64_be(&bytes);
+    if len > max_frame_size as u64 {
+        return Err(ErrorKind::FrameTooLarge(len, max_frame_size).into());
+    }
+    // the buffer grows with the received data instead of trusting the announced length
+    let len = len as usize;
+    let mut v = Vec::with_capacity(cmp::min(len, 1 << 16));
+    reader.take(len as u64).read_to_end(&mut v)?;
+    if v.len() != len {
+        bail!("Connection closed in the middle of a frame");
+    }
+    Ok(v)
+}
 
 impl BinarySend for TcpStream {
     fn send(&mut self, data: &[u8]) -> Result<()> {
-        let bytes: [u8; 8] = unsafe { transmute((data.len() as u64).to_be()) };
-        self.write_all(&bytes)?;
-        self.write_all(data)?;
-        self.flush()?;
-        Ok(())
+        send_frame(self, data)
     }
 }
 
 impl BinaryReceive for TcpStream {
     fn receive(&mut self) -> Result<Vec<u8>> {
-        let mut bytes: [u8; 8] = Default::default();
-        self.read_exact(&mut bytes)?;
-        let len = unsafe { u64::from_be(transmute(bytes)) as usize };
-        let mut v = Vec::with_capacity(len);
-        v.resize(len, 0);
-        self.read_exact(&mut v)?;
-        Ok(v)
+        receive_frame(self, DEFAULT_MAX_FRAME_SIZE)
+    }
+}
+
+/// A `TcpStream` with a configurable maximum frame size and timeout, the wire format is the same.
+pub struct FramedTcp {
+    stream: DeadlineStream,
+    max_frame_size: usize,
+}
+
+impl FramedTcp {
+    pub fn new(stream: TcpStream) -> Self {
+        FramedTcp {
+            stream: DeadlineStream::new(stream),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+
+    /// Frames larger than `max_frame_size` bytes are neither received nor sent,
+    /// both fail with `ErrorKind::FrameTooLarge`.
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size;
+    }
+
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Sending or receiving a frame fails with `ErrorKind::Timeout` if it takes longer than `timeout`.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.stream.set_timeout(timeout);
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.stream.timeout()
+    }
+
+    /// Pending and further operations fail with `ErrorKind::Cancelled` once the handle is used.
+    pub fn cancel_handle(&self) -> Result<CancelHandle> {
+        self.stream.cancel_handle()
+    }
+
+    /// Returns the underlying stream, e.g. to upgrade it to a websocket.
+    pub fn into_inner(self) -> TcpStream {
+        self.stream.into_inner()
+    }
+}
+
+impl BinarySend for FramedTcp {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() > self.max_frame_size {
+            return Err(ErrorKind::FrameTooLarge(data.len() as u64, self.max_frame_size).into());
+        }
+        self.stream.start();
+        let result = send_frame(&mut self.stream, data);
+        self.stream.check(result)
+    }
+}
+
+impl BinaryReceive for FramedTcp {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        self.stream.start();
+        let result = receive_frame(&mut self.stream, self.max_frame_size);
+        self.stream.check(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn framed_tcp_limits_frames() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let stream = listener.accept().unwrap().0;
+            let mut conn = FramedTcp::new(stream);
+            conn.set_max_frame_size(100);
+            let small = conn.receive().unwrap();
+            let large = conn.receive();
+            (small, large, conn.send(&[0; 101]).is_err())
+        });
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.send(&[1; 100]).unwrap();
+        // only the length is sent, the receiver has to reject it before reading any data
+        stream.write_all(&[0xff; 8]).unwrap();
+        let (small, large, send_failed) = server.join().unwrap();
+        assert_eq!(small, vec![1; 100]);
+        match large {
+            Err(Error(ErrorKind::FrameTooLarge(len, 100), _)) => assert_eq!(len, u64::MAX),
+            _ => panic!("Oversized frame was not rejected"),
+        }
+        assert!(send_failed);
     }
 }
@@ -0,0 +1,384 @@
+//! Recording and replaying the frames exchanged over a connection.
+//!
+//! `RecordingChannel` writes every frame going through it to a transcript, e.g. to find out
+//! what a peer written in another language actually sent. `ReplayChannel` takes the place of
+//! the other party: it answers with the received frames of a transcript and checks that the
+//! frames sent to it are the recorded ones. With the same seeded random number generator as in the
+//! recording a protocol instance then has to behave exactly as before, which makes transcripts
+//! usable as regression tests.
+//!
+//! A transcript starts with the 8 bytes `OTTRANS1`, followed by one record per frame:
+//!
+//! | field     | size         | content                                             |
+//! |-----------|--------------|-----------------------------------------------------|
+//! | direction | 1 byte       | 0 if the frame was sent, 1 if it was received       |
+//! | time      | 8 bytes      | nanoseconds since the recording started, big endian |
+//! | length    | 4 bytes      | length of the frame, big endian                     |
+//! | frame     | length bytes | the frame                                           |
+//!
+//! Only frames which were sent or received successfully are recorded.
+use super::{BinaryReceive, BinarySend};
+use byte_tools::{read_u32_be, read_u64_be, write_u32_be, write_u64_be};
+use crate::errors::*;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const MAGIC: &[u8; 8] = b"OTTRANS1";
+const RECORD_HEADER_LEN: usize = 13;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// A single record of a transcript.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub direction: Direction,
+    /// Time since the recording started.
+    pub time: Duration,
+    pub data: Vec<u8>,
+}
+
+/// Reads all records of a transcript.
+pub fn read_transcript<R: Read>(mut reader: R) -> Result<Vec<Frame>> {
+    let mut magic = [0; 8];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        bail!("Not a transcript");
+    }
+    let mut frames = Vec::new();
+    loop {
+        let mut header = [0; RECORD_HEADER_LEN];
+        // a transcript may only end between two records
+        match reader.read(&mut header[..1])? {
+            0 => return Ok(frames),
+            _ => reader.read_exact(&mut header[1..])?,
+        }
+        let direction = match header[0] {
+            0 => Direction::Sent,
+            1 => Direction::Received,
+            d => bail!("Invalid direction {} in transcript", d),
+        };
+        let nanos = read_u64_be(&header[1..9]);
+        let mut data = vec![0; read_u32_be(&header[9..]) as usize];
+        reader.read_exact(&mut data)?;
+        frames.push(Frame {
+            direction,
+            time: Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32),
+            data,
+        });
+    }
+}
+
+/// Wraps a connection and writes all frames going through it to `writer`.
+pub struct RecordingChannel<C: BinarySend + BinaryReceive, W: Write> {
+    conn: C,
+    writer: W,
+    start: Instant,
+}
+
+impl<C: BinarySend + BinaryReceive, W: Write> RecordingChannel<C, W> {
+    pub fn new(conn: C, mut writer: W) -> Result<Self> {
+        writer.write_all(MAGIC)?;
+        Ok(RecordingChannel {
+            conn,
+            writer,
+            start: Instant::now(),
+        })
+    }
+
+    fn record(&mut self, direction: Direction, data: &[u8]) -> Result<()> {
+        if data.len() > u32::MAX as usize {
+            bail!("Frame too large for a transcript");
+        }
+        let elapsed = self.start.elapsed();
+        let mut header = [0; RECORD_HEADER_LEN];
+        header[0] = match direction {
+            Direction::Sent => 0,
+            Direction::Received => 1,
+        };
+        write_u64_be(
+            &mut header[1..9],
+            elapsed.as_secs() * 1_000_000_000 + elapsed.subsec_nanos() as u64,
+        );
+        write_u32_be(&mut header[9..], data.len() as u32);
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)?;
+        // the transcript is most interesting if the protocol fails, so nothing may stay buffered
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Returns the connection and the writer of the transcript.
+    pub fn into_inner(self) -> (C, W) {
+        (self.conn, self.writer)
+    }
+}
+
+impl<C: BinarySend + BinaryReceive> RecordingChannel<C, BufWriter<File>> {
+    /// Records to a newly created file at `path`, an existing file is overwritten.
+    pub fn create<P: AsRef<Path>>(conn: C, path: P) -> Result<Self> {
+        RecordingChannel::new(conn, BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<C: BinarySend + BinaryReceive, W: Write> BinarySend for RecordingChannel<C, W> {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.conn.send(data)?;
+        self.record(Direction::Sent, data)
+    }
+}
+
+impl<C: BinarySend + BinaryReceive, W: Write> BinaryReceive for RecordingChannel<C, W> {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        let data = self.conn.receive()?;
+        self.record(Direction::Received, &data)?;
+        Ok(data)
+    }
+}
+
+/// Plays the other party of a recorded transcript, the timing of the records is ignored.
+pub struct ReplayChannel {
+    frames: VecDeque<Frame>,
+    position: usize,
+}
+
+impl ReplayChannel {
+    /// `frames` are the records of the party which is replayed into,
+    /// i.e. the frames it sent are expected and the frames it received are returned.
+    pub fn new(frames: Vec<Frame>) -> Self {
+        ReplayChannel {
+            frames: frames.into(),
+            position: 0,
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let frames = read_transcript(BufReader::new(File::open(path)?))?;
+        Ok(ReplayChannel::new(frames))
+    }
+
+    /// Whether all records were replayed. A protocol which stops early
+    /// does not fail on its own, so this should be checked at the end of a replay.
+    pub fn is_finished(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    fn next(&mut self, direction: Direction) -> Result<Vec<u8>> {
+        let frame = match self.frames.pop_front() {
+            Some(frame) => frame,
+            None => bail!("Transcript ended after {} records", self.position),
+        };
+        if frame.direction != direction {
+            bail!(
+                "Record {} of the transcript was {:?}, not {:?}",
+                self.position,
+                frame.direction,
+                direction
+            );
+        }
+        self.position += 1;
+        Ok(frame.data)
+    }
+}
+
+impl BinarySend for ReplayChannel {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        let expected = self.next(Direction::Sent)?;
+        if expected != data {
+            bail!(
+                "Sent frame differs from record {} of the transcript",
+                self.position - 1
+            );
+        }
+        Ok(())
+    }
+}
+
+impl BinaryReceive for ReplayChannel {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        self.next(Direction::Received)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bit_vec::BitVec;
+    use crate::common::digest::sha3::SHA3_256;
+    use rand::{ChaChaRng, SeedableRng};
+    use std::thread;
+    use crate::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+    use crate::sync::base_ot::{BaseOTReceiver, BaseOTSender};
+    use crate::sync::communication::memory::channel_pair;
+    use crate::sync::communication::GetConn;
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
+    use crate::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
+
+    // the golden transcripts were recorded by `record_golden_transcripts`,
+    // they have to be recorded again whenever the messages of a protocol change on purpose
+    const CHOU_RECEIVER_TRANSCRIPT: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/transcripts/chou_orlandi_receiver.bin"
+    ));
+    const IKNP_SENDER_TRANSCRIPT: &[u8] = include_bytes!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/transcripts/iknp_sender.bin"
+    ));
+
+    const CHOU_INDICES: [usize; 6] = [0, 3, 1, 1, 2, 0];
+    const IKNP_N: usize = 64;
+
+    fn chou_values() -> Vec<Vec<Vec<u8>>> {
+        CHOU_INDICES
+            .iter()
+            .enumerate()
+            .map(|(i, _)| (0..4).map(|j| vec![(4 * i + j) as u8; 16]).collect())
+            .collect()
+    }
+
+    fn iknp_values() -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..IKNP_N)
+            .map(|i| (vec![i as u8; 16], vec![i as u8 + 128; 16]))
+            .collect()
+    }
+
+    fn iknp_choices() -> BitVec {
+        (0..IKNP_N).map(|i| i % 3 == 0).collect()
+    }
+
+    fn chou_receiver<C: BinarySend + BinaryReceive>(conn: C) -> Result<(Vec<Vec<u8>>, C)> {
+        let mut ot = ChouOrlandiOTReceiver::new(
+            conn,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            ChaChaRng::from_seed([1; 32]),
+        )?;
+        let result = ot.receive_many(&CHOU_INDICES, 4)?;
+        Ok((result, ot.get_conn()))
+    }
+
+    fn iknp_sender<C: BinarySend + BinaryReceive>(conn: C) -> Result<C> {
+        let rng = ChaChaRng::from_seed([2; 32]);
+        let ot = ChouOrlandiOTReceiver::new(
+            conn,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            rng.clone(),
+        )?;
+        let mut ot_ext = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng, 16)?;
+        let values = iknp_values();
+        ot_ext.send(
+            values
+                .iter()
+                .map(|(s0, s1)| (s0.as_slice(), s1.as_slice()))
+                .collect(),
+        )?;
+        Ok(ot_ext.get_conn())
+    }
+
+    #[test]
+    fn recording_can_be_replayed() {
+        let (a, mut b) = channel_pair();
+        let mut conn = RecordingChannel::new(a, Vec::new()).unwrap();
+        let peer = thread::spawn(move || {
+            let frame = b.receive().unwrap();
+            b.send(&[]).unwrap();
+            b.send(&frame).unwrap();
+        });
+        conn.send(&[1, 2, 3]).unwrap();
+        conn.receive().unwrap();
+        conn.receive().unwrap();
+        peer.join().unwrap();
+        let (_, transcript) = conn.into_inner();
+
+        let frames = read_transcript(transcript.as_slice()).unwrap();
+        let directions: Vec<_> = frames.iter().map(|f| f.direction).collect();
+        assert_eq!(
+            directions,
+            vec![Direction::Sent, Direction::Received, Direction::Received]
+        );
+        assert!(frames[1].time <= frames[2].time);
+        assert!(read_transcript(&transcript[..transcript.len() - 1]).is_err());
+
+        let mut replay = ReplayChannel::new(frames.clone());
+        assert!(replay.receive().is_err());
+        let mut replay = ReplayChannel::new(frames.clone());
+        assert!(replay.send(&[1, 2, 4]).is_err());
+        let mut replay = ReplayChannel::new(frames);
+        replay.send(&[1, 2, 3]).unwrap();
+        assert_eq!(replay.receive().unwrap(), Vec::<u8>::new());
+        assert!(!replay.is_finished());
+        assert_eq!(replay.receive().unwrap(), vec![1, 2, 3]);
+        assert!(replay.is_finished());
+        assert!(replay.receive().is_err());
+    }
+
+    #[test]
+    fn chou_orlandi_receiver_matches_transcript() {
+        let frames = read_transcript(CHOU_RECEIVER_TRANSCRIPT).unwrap();
+        let (result, replay) = chou_receiver(ReplayChannel::new(frames)).unwrap();
+        assert!(replay.is_finished());
+        for (r, i, values) in izip!(result, &CHOU_INDICES, chou_values()) {
+            assert_eq!(r, values[*i]);
+        }
+    }
+
+    #[test]
+    fn iknp_sender_matches_transcript() {
+        let frames = read_transcript(IKNP_SENDER_TRANSCRIPT).unwrap();
+        let replay = iknp_sender(ReplayChannel::new(frames)).unwrap();
+        assert!(replay.is_finished());
+    }
+
+    /// Run with `cargo test record_golden_transcripts -- --ignored` to record the transcripts again.
+    #[test]
+    #[ignore]
+    fn record_golden_transcripts() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/transcripts");
+
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let mut ot = ChouOrlandiOTSender::new(
+                server_conn,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                ChaChaRng::from_seed([3; 32]),
+            ).unwrap();
+            let values = chou_values();
+            ot.send_many(
+                values
+                    .iter()
+                    .map(|v| v.iter().map(|s| s.as_slice()).collect())
+                    .collect(),
+            ).unwrap();
+        });
+        let conn = RecordingChannel::create(client_conn, dir.join("chou_orlandi_receiver.bin"));
+        chou_receiver(conn.unwrap()).unwrap();
+        server.join().unwrap();
+
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let rng = ChaChaRng::from_seed([4; 32]);
+            let ot = ChouOrlandiOTSender::new(
+                server_conn,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, rng, 16).unwrap();
+            ot_ext.receive(&iknp_choices()).unwrap()
+        });
+        let conn = RecordingChannel::create(client_conn, dir.join("iknp_sender.bin"));
+        iknp_sender(conn.unwrap()).unwrap();
+        for (r, c, (s0, s1)) in izip!(server.join().unwrap(), &iknp_choices(), iknp_values()) {
+            assert_eq!(r, if c { s1 } else { s0 });
+        }
+    }
+}
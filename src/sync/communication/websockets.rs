@@ -1,22 +1,72 @@
+use super::deadline::{CancelHandle, DeadlineStream};
 use super::{BinaryReceive, BinarySend};
-use errors::*;
+use crate::errors::*;
 use std::io::{Read, Write};
+use std::time::Duration;
 use tungstenite::{protocol::WebSocket, Message};
 
 impl<S: Read + Write> BinarySend for WebSocket<S> {
     fn send(&mut self, data: &[u8]) -> Result<()> {
         let v = data.to_owned();
-        self.write_message(Message::binary(v))?;
+        WebSocket::send(self, Message::binary(v))?;
         Ok(())
     }
 }
 
 impl<S: Read + Write> BinaryReceive for WebSocket<S> {
     fn receive(&mut self) -> Result<Vec<u8>> {
-        if let Message::Binary(v) = self.read_message()? {
+        if let Message::Binary(v) = self.read()? {
             Ok(v)
         } else {
             Err(ErrorKind::CommunicationError.into())
         }
     }
 }
+
+/// A websocket over TCP whose operations time out and can be cancelled, see `deadline`.
+///
+/// The handshake has to be guarded by the caller: set the timeout on the `DeadlineStream`
+/// and call `DeadlineStream::start` before passing it to e.g. `tungstenite::accept`.
+pub struct TimedWebSocket {
+    ws: WebSocket<DeadlineStream>,
+}
+
+impl TimedWebSocket {
+    pub fn new(ws: WebSocket<DeadlineStream>) -> Self {
+        TimedWebSocket { ws }
+    }
+
+    /// Sending or receiving a message fails with `ErrorKind::Timeout` if it takes longer than `timeout`.
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.ws.get_mut().set_timeout(timeout);
+    }
+
+    pub fn timeout(&self) -> Option<Duration> {
+        self.ws.get_ref().timeout()
+    }
+
+    /// Pending and further operations fail with `ErrorKind::Cancelled` once the handle is used.
+    pub fn cancel_handle(&self) -> Result<CancelHandle> {
+        self.ws.get_ref().cancel_handle()
+    }
+
+    pub fn into_inner(self) -> WebSocket<DeadlineStream> {
+        self.ws
+    }
+}
+
+impl BinarySend for TimedWebSocket {
+    fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.ws.get_mut().start();
+        let result = BinarySend::send(&mut self.ws, data);
+        self.ws.get_ref().check(result)
+    }
+}
+
+impl BinaryReceive for TimedWebSocket {
+    fn receive(&mut self) -> Result<Vec<u8>> {
+        self.ws.get_mut().start();
+        let result = BinaryReceive::receive(&mut self.ws);
+        self.ws.get_ref().check(result)
+    }
+}
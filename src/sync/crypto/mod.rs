@@ -5,7 +5,7 @@
 //! a future flag eventually. If you want to use
 //! another cipher it should be trivial to implement these traits.
 
-use errors::*;
+use crate::errors::*;
 use generic_array::{ArrayLength, GenericArray};
 pub mod aes;
 pub mod dummy;
@@ -14,8 +14,14 @@ pub mod sodium;
 
 // TODO: is this a good interface? should there maybe be only one trait?
 
+/// Common part of the encryptor and decryptor of a cipher.
+pub trait CryptoProvider {
+    /// Identifies the cipher in session headers, e.g. `"aes-256-gcm"`.
+    const NAME: &'static str;
+}
+
 /// Trait for blockciphers to be used in OT
-pub trait SymmetricEncryptor<E>
+pub trait SymmetricEncryptor<E>: CryptoProvider
 where
     E: ArrayLength<u8>,
 {
@@ -23,7 +29,7 @@ where
 }
 
 /// Trait for blockciphers to be used in OT
-pub trait SymmetricDecryptor<E>
+pub trait SymmetricDecryptor<E>: CryptoProvider
 where
     E: ArrayLength<u8>,
 {
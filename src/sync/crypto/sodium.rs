@@ -1,13 +1,17 @@
 use generic_array::{typenum::U32, GenericArray};
 use rust_sodium::crypto::stream;
 
-use super::{SymmetricDecryptor, SymmetricEncryptor};
-use errors::*;
+use super::{CryptoProvider, SymmetricDecryptor, SymmetricEncryptor};
+use crate::errors::*;
 
 #[derive(Default)]
 /// Wrapper around the rust-sodium library
 pub struct SodiumCryptoProvider();
 
+impl CryptoProvider for SodiumCryptoProvider {
+    const NAME: &'static str = "xsalsa20";
+}
+
 impl SymmetricEncryptor<U32> for SodiumCryptoProvider {
     fn encrypt(&mut self, key: &GenericArray<u8, U32>, mut data: Vec<u8>) -> Result<Vec<u8>> {
         // this is save because the type GenericArray<u8, U32> gurantees
@@ -2,3 +2,4 @@ pub mod base_ot;
 pub mod communication;
 pub mod crypto;
 pub mod ot_extension;
+pub mod session;
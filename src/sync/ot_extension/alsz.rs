@@ -1,15 +1,44 @@
 //! Asharaov et al's ot extension with security against active adversaries.
+//! [https://eprint.iacr.org/2015/061.pdf]
+//!
+//! Like IKNP the columns of u and the ciphertexts are sent in chunks of `DEFAULT_CHUNK_SIZE`
+//! OTs per frame, see `set_chunk_size`, and the hashes of the consistency check in one frame.
+//!
+//! Unlike IKNP this protocol is deliberately left on an `ArbitraryDigest` like `SHA3_256`
+//! for expanding the seeds and hashing the rows, it is not generic over `Prg`/`CrHash`:
+//! the consistency check commits to the xor of columns with the collision resistant `Digest`
+//! of the same hasher, which the fixed-key AES of `AesHash` does not provide.
 
+use super::iknp::{receive_columns, receive_pairs, send_columns, DEFAULT_CHUNK_SIZE};
 use super::{ExtendedOTReceiver, ExtendedOTSender};
 use bit_vec::BitVec;
-use common::digest::{ArbitraryDigest, Digest};
-use common::util::{bv_truncate, hash, trunc_hash};
-use errors::*;
-use rand::{CryptoRng, Rng, RngCore};
-use std::iter;
-use sync::base_ot::BaseOTReceiver;
-use sync::base_ot::BaseOTSender;
-use sync::communication::{BinaryReceive, BinarySend, GetConn};
+use crate::common::bitmatrix::BitMatrix;
+use crate::common::digest::{ArbitraryDigest, Digest};
+use crate::common::util::{batch_hasher, hash, pack, trunc_hash, unpack};
+use crate::errors::*;
+use rand::{ChaChaRng, CryptoRng, Rng, RngCore, SeedableRng};
+use std::cmp;
+use crate::sync::base_ot::BaseOTReceiver;
+use crate::sync::base_ot::BaseOTSender;
+use crate::sync::communication::{BinaryReceive, BinarySend, GetConn, GetConnMut};
+use crate::sync::session::{self, Session};
+
+/// Both parties derive the pairs of columns to check from a seed chosen by the sender
+/// after the receiver has committed to its matrix. The pairs form a random cycle, so every column
+/// is compared with two others and no column is ever compared with itself.
+fn check_pairs(seed: &[u8], l: usize) -> Result<Vec<(usize, usize)>> {
+    if seed.len() != 32 {
+        bail!("Received seed for consistency check has the wrong length");
+    }
+    let mut rng = ChaChaRng::from_seed(*array_ref![seed, 0, 32]);
+    let mut perm: Vec<usize> = (0..l).collect();
+    rng.shuffle(&mut perm);
+    Ok((0..l).map(|i| (perm[i], perm[(i + 1) % l])).collect())
+}
+
+fn xor(a: &BitVec, b: &BitVec) -> BitVec {
+    a.iter().zip(b).map(|(a, b)| a ^ b).collect()
+}
 
 pub struct ALSZExtendedOTReceiver<T, A, R>
 where
@@ -23,6 +52,30 @@ where
     initial_pairs: Vec<(Vec<u8>, Vec<u8>)>,
     security_param: usize,
     stat_security_param: usize,
+    batch: usize,
+    chunk_size: usize,
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        A: ArbitraryDigest + Digest + Clone,
+        R: RngCore + CryptoRng,
+    > GetConn<T> for ALSZExtendedOTReceiver<T, A, R>
+{
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        A: ArbitraryDigest + Digest + Clone,
+        R: RngCore + CryptoRng,
+    > GetConnMut<T> for ALSZExtendedOTReceiver<T, A, R>
+{
+    fn get_conn_mut(&mut self) -> &mut T {
+        &mut self.conn
+    }
 }
 
 /// security parameter: number of bytes to use
@@ -32,6 +85,21 @@ impl<
         R: RngCore + CryptoRng,
     > ALSZExtendedOTReceiver<T, A, R>
 {
+    /// Like `new`, in a session negotiated with `SessionParams::alsz`.
+    pub fn in_session<S>(session: &Session, hasher: A, base_ot_sender: S, rng: R) -> Result<Self>
+    where
+        S: BaseOTSender + GetConn<T>,
+    {
+        let params = session.params_for::<A>(session::ALSZ)?;
+        Self::new(
+            session.bind(hasher),
+            base_ot_sender,
+            rng,
+            params.security_param as usize,
+            params.statistical_param as usize,
+        )
+    }
+
     pub fn new<S>(
         hasher: A,
         mut base_ot_sender: S,
@@ -55,18 +123,31 @@ impl<
                 k0.push(rng.gen());
                 k1.push(rng.gen());
             }
-            base_ot_sender.send(vec![&k0, &k1])?;
             initial_pairs.push((k0, k1));
         }
+        base_ot_sender.send_many(
+            initial_pairs
+                .iter()
+                .map(|(k0, k1)| vec![k0.as_slice(), k1.as_slice()])
+                .collect(),
+        )?;
         Ok(ALSZExtendedOTReceiver {
-            hasher: hasher,
+            hasher,
             conn: base_ot_sender.get_conn(),
-            rng: rng,
-            initial_pairs: initial_pairs,
-            security_param: security_param,
-            stat_security_param: stat_security_param,
+            rng,
+            initial_pairs,
+            security_param,
+            stat_security_param,
+            batch: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
         })
     }
+
+    /// Sets the number of OTs whose columns are sent in one frame, it is rounded up
+    /// to a multiple of 8. The sender may use a different chunk size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = cmp::max(chunk_size, 1);
+    }
 }
 
 impl<
@@ -75,7 +156,7 @@ impl<
         R: RngCore + CryptoRng,
     > ExtendedOTReceiver for ALSZExtendedOTReceiver<T, A, R>
 {
-    fn receive(mut self, mut choice_bits: BitVec) -> Result<Vec<Vec<u8>>> {
+    fn receive(&mut self, choice_bits: &BitVec) -> Result<Vec<Vec<u8>>> {
         // to make things more ergonomic, faster and easier to code both security parametes specify
         // the number of bytes. For the protocol we also need the number of bits
         // so we use `kappa`, `rho` and `l` here as in the protocol of ALSZ2015.
@@ -84,6 +165,7 @@ impl<
         let l = kappa + rho;
         let output_size = choice_bits.len();
         let hash_len = output_size + kappa;
+        let hasher = batch_hasher(&self.hasher, &mut self.batch);
 
         // our seeds get hashed
         let hashed_pairs: Vec<(BitVec, BitVec)> = self
@@ -91,75 +173,59 @@ impl<
             .iter()
             .map(|(k0, k1)| {
                 (
-                    trunc_hash(self.hasher.clone(), hash_len, &k0),
-                    trunc_hash(self.hasher.clone(), hash_len, &k1),
+                    trunc_hash(hasher.clone(), hash_len, k0),
+                    trunc_hash(hasher.clone(), hash_len, k1),
                 )
             })
             .collect();
 
         // we mask the choices with random bits so the sender can't cheat in the check phase and calculate some of the bits
-        choice_bits.extend(iter::repeat_with(|| self.rng.gen_bool(0.5)).take(kappa));
+        let mut choice_bits = choice_bits.clone();
+        for _ in 0..kappa {
+            let bit: bool = self.rng.gen();
+            choice_bits.push(bit);
+        }
 
         // now we xor both hashed keys together with our choice bits
-        for (hashed_k0, hashed_k1) in hashed_pairs.iter() {
-            assert_eq!(
-                hashed_k0.len(),
-                hash_len,
-                "internal error, lengths don't match."
-            );
-            assert_eq!(
-                hashed_k0.len(),
-                hashed_k1.len(),
-                "internal error, lengths don't match."
-            );
-            assert_eq!(
-                hashed_k0.len(),
-                choice_bits.len(),
-                "internal error, lengths don't match."
-            );
-            let u: BitVec = izip!(hashed_k0, hashed_k1, &choice_bits)
-                .map(|(t, k, r)| t ^ k ^ r)
-                .collect();
-            self.conn.send(&u.to_bytes())?;
-        }
+        let u_mat: Vec<Vec<u8>> = hashed_pairs
+            .iter()
+            .map(|(hashed_k0, hashed_k1)| {
+                let u: BitVec = izip!(hashed_k0, hashed_k1, &choice_bits)
+                    .map(|(t, k, r)| t ^ k ^ r)
+                    .collect();
+                u.to_bytes()
+            })
+            .collect();
+        send_columns(&mut self.conn, &u_mat, self.chunk_size)?;
+
+        // the sender decides which columns get compared with each other
+        let pairs = check_pairs(&self.conn.receive()?, l)?;
 
         // we xor and hash our keys and send them to the sender for verification
-        for ((k00, k01), (k10, k11)) in hashed_pairs.iter().zip(hashed_pairs.iter()) {
-            self.conn.send(&hash(
-                self.hasher.clone(),
-                k00.iter().zip(k10).map(|(a, b)| a || b).collect(),
-            ))?;
-            self.conn.send(&hash(
-                self.hasher.clone(),
-                k00.iter().zip(k11).map(|(a, b)| a || b).collect(),
-            ))?;
-            self.conn.send(&hash(
-                self.hasher.clone(),
-                k01.iter().zip(k10).map(|(a, b)| a || b).collect(),
-            ))?;
-            self.conn.send(&hash(
-                self.hasher.clone(),
-                k01.iter().zip(k11).map(|(a, b)| a || b).collect(),
-            ))?;
+        let mut hashes = Vec::with_capacity(4 * l);
+        for (alpha, beta) in pairs {
+            let (ref k00, ref k01) = hashed_pairs[alpha];
+            let (ref k10, ref k11) = hashed_pairs[beta];
+            hashes.push(hash(hasher.clone(), xor(k00, k10)).to_vec());
+            hashes.push(hash(hasher.clone(), xor(k00, k11)).to_vec());
+            hashes.push(hash(hasher.clone(), xor(k01, k10)).to_vec());
+            hashes.push(hash(hasher.clone(), xor(k01, k11)).to_vec());
         }
+        self.conn.send(&pack(&hashes))?;
+
+        // transpose key matrix
+        let t_mat = BitMatrix::from_rows(hashed_pairs.iter().map(|(t, _)| t.to_bytes()), hash_len);
+        let t_mat = t_mat.transpose();
 
         let mut result: Vec<Vec<u8>> = Vec::with_capacity(output_size);
 
         // we receive the modified input values of the OT-sender and xor them with the hash of `i` and the transposed first keys
         // this gives us the selected result values.
-        for i in 0..output_size {
-            let ys = [self.conn.receive()?, self.conn.receive()?];
-            if ys[0].len() != ys[1].len() {
-                return Err("Received pairs differ in size".into());
-            }
-            // transpose key matrix
-            let mut bt = BitVec::with_capacity(l);
-            for j in 0..l {
-                bt.push(hashed_pairs[j].0[i]);
-            }
-            let mut hasher = self.hasher.clone();
-            ArbitraryDigest::input(&mut hasher, &(i as u64).to_bytes());
-            ArbitraryDigest::input(&mut hasher, &bt.to_bytes());
+        let pairs = receive_pairs(&mut self.conn, output_size)?;
+        for (i, ys) in pairs.iter().enumerate() {
+            let mut hasher = hasher.clone();
+            ArbitraryDigest::input(&mut hasher, &(i as u64).to_be_bytes());
+            ArbitraryDigest::input(&mut hasher, t_mat.row(i));
             let hashed = ArbitraryDigest::result(hasher, ys[0].len());
             result.push(
                 ys[choice_bits[i] as usize]
@@ -173,24 +239,68 @@ impl<
     }
 }
 
-pub struct ALSZExtendedOTSender<T, A>
+pub struct ALSZExtendedOTSender<T, A, R>
 where
     T: BinaryReceive + BinarySend,
     A: Digest + ArbitraryDigest + Clone,
+    R: RngCore + CryptoRng,
 {
     conn: T,
     hasher: A,
+    rng: R,
     initial: Vec<Vec<u8>>,
     random_choices: BitVec,
     security_param: usize,
     stat_security_param: usize,
+    batch: usize,
+    chunk_size: usize,
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        A: Digest + ArbitraryDigest + Clone,
+        R: RngCore + CryptoRng,
+    > GetConn<T> for ALSZExtendedOTSender<T, A, R>
+{
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+impl<
+        T: BinaryReceive + BinarySend,
+        A: Digest + ArbitraryDigest + Clone,
+        R: RngCore + CryptoRng,
+    > GetConnMut<T> for ALSZExtendedOTSender<T, A, R>
+{
+    fn get_conn_mut(&mut self) -> &mut T {
+        &mut self.conn
+    }
 }
 
 /// security parameter: number of bytes to use
-impl<T: BinaryReceive + BinarySend, A: Digest + ArbitraryDigest + Clone>
-    ALSZExtendedOTSender<T, A>
+impl<
+        T: BinaryReceive + BinarySend,
+        A: Digest + ArbitraryDigest + Clone,
+        R: RngCore + CryptoRng,
+    > ALSZExtendedOTSender<T, A, R>
 {
-    pub fn new<S, R>(
+    /// Like `new`, in a session negotiated with `SessionParams::alsz`.
+    pub fn in_session<S>(session: &Session, hasher: A, base_ot_receiver: S, rng: R) -> Result<Self>
+    where
+        S: BaseOTReceiver + GetConn<T>,
+    {
+        let params = session.params_for::<A>(session::ALSZ)?;
+        Self::new(
+            session.bind(hasher),
+            base_ot_receiver,
+            rng,
+            params.security_param as usize,
+            params.statistical_param as usize,
+        )
+    }
+
+    pub fn new<S>(
         hasher: A,
         mut base_ot_receiver: S,
         mut rng: R,
@@ -199,126 +309,132 @@ impl<T: BinaryReceive + BinarySend, A: Digest + ArbitraryDigest + Clone>
     ) -> Result<Self>
     where
         S: BaseOTReceiver + GetConn<T>,
-        R: RngCore + CryptoRng,
     {
         // To simplify this protocol both security parameters are specified
         // in bytes and as such have to be multiplied by 8 for certain parts of the protocol.
         let l = (security_param * 8) + (stat_security_param * 8);
         // we generate random choices (0 or 1) and use them to receive
         // `l` seeds (of size `security_param`) from the receiver with the base-OT primitive.
-        let mut random_choices = BitVec::with_capacity(security_param);
-        let mut initial = Vec::with_capacity(security_param);
+        let mut random_choices = BitVec::with_capacity(l);
         for _ in 0..l {
-            let mut choice: bool = rng.gen();
-            initial.push(base_ot_receiver.receive(choice as usize, 2)?);
+            let choice: bool = rng.gen();
             random_choices.push(choice);
         }
+        let indices: Vec<usize> = random_choices.iter().map(|c| c as usize).collect();
+        let initial = base_ot_receiver.receive_many(&indices, 2)?;
         Ok(ALSZExtendedOTSender {
             conn: base_ot_receiver.get_conn(),
-            hasher: hasher,
-            initial: initial,
-            random_choices: random_choices,
-            security_param: security_param,
-            stat_security_param: stat_security_param,
+            hasher,
+            rng,
+            initial,
+            random_choices,
+            security_param,
+            stat_security_param,
+            batch: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
         })
     }
+
+    /// Sets the number of OTs whose ciphertexts are sent in one frame.
+    /// The receiver may use a different chunk size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = cmp::max(chunk_size, 1);
+    }
 }
 
-impl<T: BinaryReceive + BinarySend, A: Digest + ArbitraryDigest + Clone> ExtendedOTSender
-    for ALSZExtendedOTSender<T, A>
+impl<
+        T: BinaryReceive + BinarySend,
+        A: Digest + ArbitraryDigest + Clone,
+        R: RngCore + CryptoRng,
+    > ExtendedOTSender for ALSZExtendedOTSender<T, A, R>
 {
-    fn send(mut self, values: Vec<(&[u8], &[u8])>) -> Result<()> {
+    fn send(&mut self, values: Vec<(&[u8], &[u8])>) -> Result<()> {
         // to make things more ergonomic, faster and easier to code both security parametes specify
         // the number of bytes. For the protocol we also need the number of bits
         // so we use `kappa`, `rho` and `l` here as in the protocol of ALSZ2015.
         let kappa = self.security_param * 8;
         let rho = self.stat_security_param * 8;
         let l = kappa + rho;
-        let output_size = self.random_choices.len();
+        let output_size = values.len();
         let hash_len = output_size + kappa;
+        if values.iter().any(|(x0, x1)| x0.len() != x1.len()) {
+            bail!("String pairs do not have same size");
+        }
+        let hasher = batch_hasher(&self.hasher, &mut self.batch);
 
         // we receive the xored keys and selection bits
-        let mut us: Vec<BitVec> = Vec::with_capacity(l);
-        for _ in 0..l {
-            us.push(bv_truncate(&self.conn.receive()?, output_size));
-        }
+        let us = receive_columns(&mut self.conn, l, hash_len)?;
+        let hashed: Vec<BitVec> = self
+            .initial
+            .iter()
+            .map(|k| trunc_hash(hasher.clone(), hash_len, k))
+            .collect();
+
+        // only now that the receiver is bound to its columns we choose the pairs to check
+        let mut seed: [u8; 32] = Default::default();
+        self.rng.fill_bytes(&mut seed);
+        self.conn.send(&seed)?;
+        let pairs = check_pairs(&seed, l)?;
 
         // we verify that the receiver used the same selection bits for all values of u
-        for (alpha, beta) in (0..l).zip(0..l) {
-            let hs = [
-                [self.conn.receive()?, self.conn.receive()?],
-                [self.conn.receive()?, self.conn.receive()?],
-            ];
-            let gk0 = trunc_hash(self.hasher.clone(), hash_len, &self.initial[alpha]);
-            let gk1 = trunc_hash(self.hasher.clone(), hash_len, &self.initial[beta]);
+        let hashes = unpack(&self.conn.receive()?)?;
+        if hashes.len() != 4 * l {
+            bail!("Received wrong number of hashes for the consistency check");
+        }
+        for ((alpha, beta), hs) in pairs.into_iter().zip(hashes.chunks(4)) {
+            let hs = [[&hs[0], &hs[1]], [&hs[2], &hs[3]]];
+            let s_alpha = self.random_choices[alpha];
+            let s_beta = self.random_choices[beta];
+            let expected = xor(&hashed[alpha], &hashed[beta]);
             // TODO: faster comparison https://github.com/saschagrunert/fastcmp
-            // first check
+            // first check: the hash of the two seeds we know has to be correct
+            if hs[s_alpha as usize][s_beta as usize].as_slice()
+                != hash(hasher.clone(), expected.clone()).as_slice()
             {
-                let actual =
-                    &hs[self.random_choices[alpha] as usize][self.random_choices[beta] as usize];
-                let expected = hash(
-                    self.hasher.clone(),
-                    gk0.iter().zip(&gk1).map(|(a, b)| a || b).collect(),
-                );
-                println!("actual: {:?}, expected: {:?}", actual, expected);
-                if actual.as_slice() != expected.as_slice() {
-                    return Err("Cryptographic check (1) failed. Connection corrupted".into());
-                }
+                return Err(ErrorKind::ConsistencyCheckFailed.into());
             }
-            // second check
+            // second check: the hash of the two seeds we don't know has to be consistent with u,
+            // which is only the case if the same choice bits were used for both columns
+            let unknown = xor(&xor(&expected, &us[alpha]), &us[beta]);
+            if hs[!s_alpha as usize][!s_beta as usize].as_slice()
+                != hash(hasher.clone(), unknown).as_slice()
             {
-                let actual =
-                    &hs[!self.random_choices[alpha] as usize][!self.random_choices[beta] as usize];
-                let expected = hash(
-                    self.hasher.clone(),
-                    izip!(&gk0, &gk1, &us[alpha], &us[beta])
-                        .map(|(a, b, u1, u2)| a || b || u1 || u2)
-                        .collect(),
-                );
-                if actual.as_slice() != expected.as_slice() {
-                    return Err("Cryptographic check (2) failed. Connection corrupted".into());
-                }
+                return Err(ErrorKind::ConsistencyCheckFailed.into());
             }
-            // third check
+            // third check: equal columns would reveal the xor of two of our choice bits
             if us[alpha] == us[beta] {
-                return Err("Cryptographic check (3) failed. Connection corrupted".into());
+                return Err(ErrorKind::ConsistencyCheckFailed.into());
             }
+        }
 
-            let q_mat: Vec<BitVec> = izip!(&self.initial, &us, &self.random_choices)
-                .map(|(k, u, s)| {
-                    let gk = trunc_hash(self.hasher.clone(), hash_len, k);
-                    u.iter()
-                        .zip(gk)
-                        .map(|(u, k)| (((s as u8) * (u as u8)) ^ (k as u8)) == 1)
-                        .collect()
-                })
-                .collect();
-            for i in 0..output_size {
-                let n = values[i].0.len();
-                assert_eq!(n, values[i].1.len(), "String pairs do not have same size");
-                let mut qt = BitVec::with_capacity(l);
-                for j in 0..l {
-                    qt.push(q_mat[j][i]);
-                }
-                let mut hasher = self.hasher.clone();
-                ArbitraryDigest::input(&mut hasher, &(i as u64).to_bytes());
+        let q_mat = izip!(&hashed, &us, &self.random_choices).map(|(gk, u, s)| {
+            u.iter()
+                .zip(gk)
+                .map(|(u, k)| (s & u) ^ k)
+                .collect::<BitVec>()
+                .to_bytes()
+        });
+        let q_mat = BitMatrix::from_rows(q_mat, hash_len).transpose();
+        let s = self.random_choices.to_bytes();
+
+        for (c, chunk) in values.chunks(self.chunk_size).enumerate() {
+            let mut ys = Vec::with_capacity(2 * chunk.len());
+            for (j, (x0, x1)) in chunk.iter().enumerate() {
+                let i = c * self.chunk_size + j;
+                let n = x0.len();
+                let qt = q_mat.row(i);
+                let mut hasher = hasher.clone();
+                ArbitraryDigest::input(&mut hasher, &(i as u64).to_be_bytes());
                 let mut hasher2 = hasher.clone();
-                // TODO make this nicer
-                ArbitraryDigest::input(&mut hasher, &qt.to_bytes());
+                ArbitraryDigest::input(&mut hasher, qt);
                 let hq = ArbitraryDigest::result(hasher, n);
-                let y0: Vec<u8> = values[i].0.iter().zip(hq).map(|(x, q)| x ^ q).collect();
-                let mut q2: Vec<u8> = qt
-                    .to_bytes()
-                    .iter()
-                    .zip(self.random_choices.to_bytes())
-                    .map(|(q, s)| q ^ s)
-                    .collect();
+                ys.push(x0.iter().zip(hq).map(|(x, q)| x ^ q).collect());
+                let q2: Vec<u8> = qt.iter().zip(&s).map(|(q, s)| q ^ s).collect();
                 ArbitraryDigest::input(&mut hasher2, &q2);
                 let shq = ArbitraryDigest::result(hasher2, n);
-                let y1: Vec<u8> = values[i].1.iter().zip(shq).map(|(x, q)| x ^ q).collect();
-                self.conn.send(&y0)?;
-                self.conn.send(&y1)?;
+                ys.push(x1.iter().zip(shq).map(|(x, q)| x ^ q).collect());
             }
+            self.conn.send(&pack(&ys))?;
         }
         Ok(())
     }
@@ -326,22 +442,22 @@ impl<T: BinaryReceive + BinarySend, A: Digest + ArbitraryDigest + Clone> Extende
 
 #[cfg(test)]
 mod tests {
-    use common::digest::sha3::SHA3_256;
-    use common::util::{generate_random_choices, generate_random_string_pairs};
+    use crate::common::digest::sha3::SHA3_256;
+    use crate::common::util::{generate_random_choices, generate_random_string_pairs};
+    use crate::errors::*;
     use rand::ChaChaRng;
     use rand::FromEntropy;
-    use std::net::{TcpListener, TcpStream};
     use std::thread;
     use std::time::Instant;
-    use sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
-    use sync::base_ot::{BaseOTReceiver, BaseOTSender};
-    use sync::communication::{BinaryReceive, BinarySend};
-    use sync::crypto::aes::AesCryptoProvider;
-    use sync::ot_extension::alsz::{ALSZExtendedOTReceiver, ALSZExtendedOTSender};
-    use sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
+    use crate::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+    use crate::sync::communication::adversary::{AdversaryChannel, Attack};
+    use crate::sync::communication::memory::channel_pair;
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::ot_extension::alsz::{ALSZExtendedOTReceiver, ALSZExtendedOTSender};
+    use crate::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
 
     #[test]
-    fn iknp_test() {
+    fn alsz_test() {
         let len = 100;
         let n = 10;
         let security_param = 16;
@@ -356,12 +472,9 @@ mod tests {
         );
 
         let choices2 = choices.clone();
+        let (server_conn, client_conn) = channel_pair();
         let server = thread::spawn(move || {
-            let ot_stream = TcpListener::bind("127.0.0.1:1266")
-                .unwrap()
-                .accept()
-                .unwrap()
-                .0;
+            let ot_stream = server_conn;
             let rng = ChaChaRng::from_entropy();
             let mut now = Instant::now();
             let ot = ChouOrlandiOTSender::new(
@@ -372,7 +485,7 @@ mod tests {
             ).unwrap();
             println!("Chou ot sender creation took {:?}", now.elapsed());
             now = Instant::now();
-            let ot_ext = ALSZExtendedOTReceiver::new(
+            let mut ot_ext = ALSZExtendedOTReceiver::new(
                 SHA3_256::default(),
                 ot,
                 rng.clone(),
@@ -380,9 +493,11 @@ mod tests {
                 stat_security_param,
             ).unwrap();
             println!("ALSZ receiver creation took {:?}", now.elapsed());
+            // both parties use different chunk sizes which do not divide the number of OTs
+            ot_ext.set_chunk_size(13);
             now = Instant::now();
             let values: Vec<String> = ot_ext
-                .receive(choices2)
+                .receive(&choices2)
                 .unwrap()
                 .into_iter()
                 .map(|v| String::from_utf8(v).unwrap())
@@ -392,7 +507,7 @@ mod tests {
         });
         let values2 = values.clone();
         let client = thread::spawn(move || {
-            let ot_stream = TcpStream::connect("127.0.0.1:1266").unwrap();
+            let ot_stream = client_conn;
             let rng = ChaChaRng::from_entropy();
             let mut now = Instant::now();
             let ot = ChouOrlandiOTReceiver::new(
@@ -403,7 +518,7 @@ mod tests {
             ).unwrap();
             println!("chou ot receiver creation took {:?}", now.elapsed());
             now = Instant::now();
-            let ot_ext = ALSZExtendedOTSender::new(
+            let mut ot_ext = ALSZExtendedOTSender::new(
                 SHA3_256::default(),
                 ot,
                 rng.clone(),
@@ -411,6 +526,7 @@ mod tests {
                 stat_security_param,
             ).unwrap();
             println!("ALSZ sender creation took {:?}", now.elapsed());
+            ot_ext.set_chunk_size(7);
             now = Instant::now();
             let values: Vec<(&[u8], &[u8])> = values2
                 .iter()
@@ -430,4 +546,81 @@ mod tests {
             }
         }
     }
+
+    // flips one choice bit in the first column the sender receives, as if the receiver
+    // had used different choice bits for this column
+    fn cheat(columns_len: usize) -> Attack {
+        let mut done = false;
+        Attack::modify(move |_, frame| {
+            if !done && frame.len() == columns_len {
+                frame[0] ^= 0x80;
+                done = true;
+            }
+        })
+    }
+
+    #[test]
+    fn alsz_cheating_receiver() {
+        let len = 100;
+        let n = 10;
+        let security_param = 16;
+        let stat_security_param = 5;
+
+        let choices = generate_random_choices(len);
+        let values = generate_random_string_pairs(n, len);
+
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || -> Result<()> {
+            let ot_stream = server_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTSender::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )?;
+            let mut ot_ext = ALSZExtendedOTReceiver::new(
+                SHA3_256::default(),
+                ot,
+                rng,
+                security_param,
+                stat_security_param,
+            )?;
+            ot_ext.receive(&choices)?;
+            Ok(())
+        });
+        let client = thread::spawn(move || -> Result<()> {
+            // all columns fit into a single frame, no other frame has this length
+            let l = (security_param + stat_security_param) * 8;
+            let columns_len = l * (len + security_param * 8).div_ceil(8);
+            let ot_stream = AdversaryChannel::new(client_conn, vec![cheat(columns_len)]);
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTReceiver::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )?;
+            let mut ot_ext = ALSZExtendedOTSender::new(
+                SHA3_256::default(),
+                ot,
+                rng,
+                security_param,
+                stat_security_param,
+            )?;
+            let values: Vec<(&[u8], &[u8])> = values
+                .iter()
+                .map(|(s1, s2)| (s1.as_bytes(), s2.as_bytes()))
+                .collect();
+            ot_ext.send(values)
+        });
+        let sender_result = client.join().unwrap();
+        let receiver_result = server.join().unwrap();
+
+        match sender_result {
+            Err(Error(ErrorKind::ConsistencyCheckFailed, _)) => (),
+            r => panic!("cheating receiver was not detected: {:?}", r),
+        }
+        assert!(receiver_result.is_err());
+    }
 }
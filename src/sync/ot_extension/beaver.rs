@@ -0,0 +1,157 @@
+//! Beaver's derandomization of random OT [https://doi.org/10.1007/3-540-44750-4_8].
+//!
+//! Random OTs can be precomputed before the messages or choices are known. To use one of them
+//! the receiver tells the sender whether its real choice differs from the random one, the sender
+//! then masks its messages with the random pair, swapped if necessary.
+//! Only one bit per OT and the masked messages are sent, both of which reveal nothing.
+
+use bit_vec::BitVec;
+use crate::common::util::bv_truncate;
+use crate::errors::*;
+use crate::sync::communication::{BinaryReceive, BinarySend};
+
+/// Sends `values` using the random pairs `pads` of a `RandomOTSender`.
+/// The messages may not be longer than the pads.
+pub fn derandomize_send<C>(
+    conn: &mut C,
+    pads: &[(Vec<u8>, Vec<u8>)],
+    values: Vec<(&[u8], &[u8])>,
+) -> Result<()>
+where
+    C: BinarySend + BinaryReceive,
+{
+    if values.len() > pads.len() {
+        bail!("Not enough random OTs to send the given values");
+    }
+    let d = conn.receive()?;
+    if d.len() != values.len().div_ceil(8) {
+        bail!("Received choice corrections have the wrong length");
+    }
+    let d = bv_truncate(&d, values.len());
+    for ((x0, x1), (r0, r1), d) in izip!(values, pads, &d) {
+        if x0.len() != x1.len() {
+            bail!("String pairs do not have same size");
+        }
+        if x0.len() > r0.len() {
+            bail!("Values are longer than the random OTs");
+        }
+        let (r0, r1) = if d { (r1, r0) } else { (r0, r1) };
+        let y0: Vec<u8> = x0.iter().zip(r0).map(|(x, r)| x ^ r).collect();
+        let y1: Vec<u8> = x1.iter().zip(r1).map(|(x, r)| x ^ r).collect();
+        conn.send(&y0)?;
+        conn.send(&y1)?;
+    }
+    Ok(())
+}
+
+/// Receives the values selected by `choice_bits` using the output `pads` of a `RandomOTReceiver`
+/// that was run with `random_choices`. The random choices have to be uniformly random and may
+/// not be reused.
+pub fn derandomize_receive<C>(
+    conn: &mut C,
+    random_choices: &BitVec,
+    pads: &[Vec<u8>],
+    choice_bits: &BitVec,
+) -> Result<Vec<Vec<u8>>>
+where
+    C: BinarySend + BinaryReceive,
+{
+    if choice_bits.len() > pads.len() || random_choices.len() != pads.len() {
+        bail!("Not enough random OTs to receive the given choices");
+    }
+    let d: BitVec = choice_bits
+        .iter()
+        .zip(random_choices)
+        .map(|(b, c)| b ^ c)
+        .collect();
+    conn.send(&d.to_bytes())?;
+
+    let mut result = Vec::with_capacity(choice_bits.len());
+    for (b, r) in choice_bits.iter().zip(pads) {
+        let ys = [conn.receive()?, conn.receive()?];
+        if ys[0].len() != ys[1].len() {
+            bail!("Received pairs differ in size");
+        }
+        if ys[0].len() > r.len() {
+            bail!("Received values are longer than the random OTs");
+        }
+        result.push(ys[b as usize].iter().zip(r).map(|(y, r)| y ^ r).collect());
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::digest::sha3::SHA3_256;
+    use crate::common::util::{generate_random_choices, generate_random_string_pairs};
+    use rand::ChaChaRng;
+    use rand::FromEntropy;
+    use std::thread;
+    use crate::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+    use crate::sync::communication::GetConnMut;
+    use crate::sync::communication::memory::channel_pair;
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::ot_extension::beaver::{derandomize_receive, derandomize_send};
+    use crate::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
+    use crate::sync::ot_extension::{RandomOTReceiver, RandomOTSender};
+
+    #[test]
+    fn derandomized_iknp() {
+        let len = 100;
+        let n = 50;
+
+        let choices = generate_random_choices(len);
+        let values = generate_random_string_pairs(n, len);
+        let choices2 = choices.clone();
+        let values2 = values.clone();
+
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let ot_stream = server_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTSender::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, rng, 16).unwrap();
+            // offline phase, the real choices are not known yet
+            let random_choices = generate_random_choices(len);
+            let pads = ot_ext.random_receive(&random_choices, n).unwrap();
+            derandomize_receive(ot_ext.get_conn_mut(), &random_choices, &pads, &choices2).unwrap()
+        });
+        let client = thread::spawn(move || {
+            let ot_stream = client_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTReceiver::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng, 16).unwrap();
+            let pads = ot_ext.random_send(len, n).unwrap();
+            derandomize_send(
+                ot_ext.get_conn_mut(),
+                &pads,
+                values2
+                    .iter()
+                    .map(|(s1, s2)| (s1.as_bytes(), s2.as_bytes()))
+                    .collect(),
+            ).unwrap();
+        });
+        let rec_values = server.join().unwrap();
+        client.join().unwrap();
+
+        for (i, choice) in choices.iter().enumerate() {
+            let expected = if choice { &values[i].1 } else { &values[i].0 };
+            assert_eq!(
+                expected.as_bytes(),
+                rec_values[i].as_slice(),
+                "Values differ at index {}",
+                i
+            );
+        }
+    }
+}
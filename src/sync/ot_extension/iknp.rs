@@ -1,26 +1,45 @@
 //! Ishai et al's semi-honest ot extension protocol.
+//!
+//! Instead of one message per column and two per OT, the columns of u and the ciphertexts
+//! are sent in chunks of `DEFAULT_CHUNK_SIZE` OTs per frame, see `set_chunk_size`.
+//!
+//! The seeds are expanded and the rows hashed with the `Prg` and `CrHash` given at construction,
+//! any `ArbitraryDigest` like `SHA3_256` or the much faster fixed-key AES of `AesHash`.
 
-use super::{ExtendedOTReceiver, ExtendedOTSender};
+use super::{
+    CorrelatedOTReceiver, CorrelatedOTSender, ExtendedOTReceiver, ExtendedOTSender,
+    RandomOTReceiver, RandomOTSender,
+};
 use bit_vec::BitVec;
-use common::digest::ArbitraryDigest;
-use common::util::{bv_truncate, trunc_hash};
-use errors::*;
+use byte_tools::{read_u64_be, write_u64_be};
+use crate::common::bitmatrix::BitMatrix;
+use crate::common::chunks::{column_frames, unpack_pairs, ColumnCollector};
+use crate::common::digest::{CrHash, Prg, Separable};
+use crate::common::util::{bv_truncate, pack};
+use crate::errors::*;
 use rand::{CryptoRng, Rng, RngCore};
-use sync::base_ot::BaseOTReceiver;
-use sync::base_ot::BaseOTSender;
-use sync::communication::{BinaryReceive, BinarySend, GetConn};
+use std::cmp;
+use crate::sync::base_ot::BaseOTReceiver;
+use crate::sync::base_ot::BaseOTSender;
+use crate::sync::communication::{BinaryReceive, BinarySend, GetConn, GetConnMut};
+use crate::sync::session::{self, Session};
+
+/// Number of OTs whose data is sent in a single frame unless configured otherwise.
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 16;
 
 pub struct IKNPExtendedOTReceiver<T, A>
 where
     T: BinaryReceive + BinarySend,
-    A: ArbitraryDigest + Clone,
+    A: Prg + CrHash,
 {
     conn: T,
-    arbitrary_hasher: A,
+    hasher: A,
     initial_pairs: Vec<(Vec<u8>, Vec<u8>)>,
+    batch: usize,
+    chunk_size: usize,
 }
 
-impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone> GetConn<T>
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> GetConn<T>
     for IKNPExtendedOTReceiver<T, A>
 {
     fn get_conn(self) -> T {
@@ -28,10 +47,29 @@ impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone> GetConn<T>
     }
 }
 
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> GetConnMut<T>
+    for IKNPExtendedOTReceiver<T, A>
+{
+    fn get_conn_mut(&mut self) -> &mut T {
+        &mut self.conn
+    }
+}
+
 /// security parameter: number of bytes to use
-impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone> IKNPExtendedOTReceiver<T, A> {
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> IKNPExtendedOTReceiver<T, A> {
+    /// Like `new`, in a session negotiated with `SessionParams::iknp`.
+    pub fn in_session<S, R>(session: &Session, hasher: A, base_ot_sender: S, rng: R) -> Result<Self>
+    where
+        S: BaseOTSender + GetConn<T>,
+        R: RngCore + CryptoRng,
+        A: Separable,
+    {
+        let params = session.params_for::<A>(session::IKNP)?;
+        Self::new(session.bind(hasher), base_ot_sender, rng, params.security_param as usize)
+    }
+
     pub fn new<S, R>(
-        arbitrary_hasher: A,
+        hasher: A,
         mut base_ot_sender: S,
         mut rng: R,
         security_param: usize,
@@ -49,87 +87,146 @@ impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone> IKNPExtendedOTRe
                 k0.push(rng.gen());
                 k1.push(rng.gen());
             }
-            base_ot_sender.send(vec![&k0, &k1])?;
             initial_pairs.push((k0, k1));
         }
+        base_ot_sender.send_many(
+            initial_pairs
+                .iter()
+                .map(|(k0, k1)| vec![k0.as_slice(), k1.as_slice()])
+                .collect(),
+        )?;
         Ok(IKNPExtendedOTReceiver {
-            arbitrary_hasher,
+            hasher,
             conn: base_ot_sender.get_conn(),
             initial_pairs,
+            batch: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
         })
     }
 }
 
-impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone> ExtendedOTReceiver
-    for IKNPExtendedOTReceiver<T, A>
-{
-    fn receive(&mut self, choice_bits: &BitVec) -> Result<Vec<Vec<u8>>> {
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> IKNPExtendedOTReceiver<T, A> {
+    /// Sets the number of OTs whose columns are sent in one frame, it is rounded up
+    /// to a multiple of 8. The sender may use a different chunk size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = cmp::max(chunk_size, 1);
+    }
+
+    /// Sends the masked columns for the given choices and returns the matrix t
+    /// (with one row per OT) together with the number of this batch.
+    fn send_matrix(&mut self, choice_bits: &BitVec) -> Result<(BitMatrix, u64)> {
         let output_size = choice_bits.len();
-        let l = self.initial_pairs.len();
-        //let mut result = Vec::with_capacity(choice_bits);
+        let batch = self.batch as u64;
+        self.batch += 1;
         let t_mat: Vec<BitVec> = self
             .initial_pairs
             .iter()
-            .map(|(k0, _)| trunc_hash(self.arbitrary_hasher.clone(), output_size, k0))
+            .map(|(k0, _)| expand(&self.hasher, batch, k0, output_size))
             .collect();
-        for ((_, k1), t) in self.initial_pairs.iter().zip(&t_mat) {
-            assert_eq!(t.len(), output_size, "internal error, lengths don't match.");
-            let gk = trunc_hash(self.arbitrary_hasher.clone(), output_size, k1);
-            assert_eq!(t.len(), gk.len(), "internal error, lengths don't match.");
-            assert_eq!(
-                t.len(),
-                choice_bits.len(),
-                "internal error, lengths don't match."
-            );
-            let u: BitVec = izip!(t, gk, choice_bits)
-                .map(|(t, k, r)| t ^ k ^ r)
-                .collect();
-            self.conn.send(&u.to_bytes())?;
-        }
+        let u_mat: Vec<Vec<u8>> = self
+            .initial_pairs
+            .iter()
+            .zip(&t_mat)
+            .map(|((_, k1), t)| {
+                let gk = expand(&self.hasher, batch, k1, output_size);
+                let u: BitVec = izip!(t, gk, choice_bits)
+                    .map(|(t, k, r)| t ^ k ^ r)
+                    .collect();
+                u.to_bytes()
+            })
+            .collect();
+        send_columns(&mut self.conn, &u_mat, self.chunk_size)?;
+        let t_mat = BitMatrix::from_rows(t_mat.iter().map(|t| t.to_bytes()), output_size);
+        Ok((t_mat.transpose(), batch))
+    }
+}
 
-        let mut result: Vec<Vec<u8>> = Vec::with_capacity(output_size);
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> ExtendedOTReceiver
+    for IKNPExtendedOTReceiver<T, A>
+{
+    fn receive(&mut self, choice_bits: &BitVec) -> Result<Vec<Vec<u8>>> {
+        let (t_mat, batch) = self.send_matrix(choice_bits)?;
+        let pairs = receive_pairs(&mut self.conn, choice_bits.len())?;
 
-        for i in 0..output_size {
-            let y0 = self.conn.receive()?;
-            let y1 = self.conn.receive()?;
-            let ys = [y0, y1];
-            assert_eq!(
-                ys[0].len(),
-                ys[1].len(),
-                "String pairs do not have same size"
-            );
-            let mut bt = BitVec::with_capacity(l);
-            for t in &t_mat {
-                bt.push(t[i]);
-            }
-            let mut hasher = self.arbitrary_hasher.clone();
-            hasher.input(&(i as u64).to_bytes());
-            hasher.input(&bt.to_bytes());
-            let ht = hasher.result(ys[0].len());
-            result.push(
+        Ok(pairs
+            .iter()
+            .enumerate()
+            .map(|(i, ys)| {
+                let ht = hash_row(&self.hasher, batch, i, t_mat.row(i), ys[0].len());
                 ys[choice_bits[i] as usize]
                     .iter()
                     .zip(ht)
                     .map(|(ht, y)| y ^ ht)
-                    .collect(),
-            );
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> CorrelatedOTReceiver
+    for IKNPExtendedOTReceiver<T, A>
+{
+    fn receive_correlated(&mut self, choice_bits: &BitVec, len: usize) -> Result<Vec<Vec<u8>>> {
+        let output_size = choice_bits.len();
+        let (t_mat, batch) = self.send_matrix(choice_bits)?;
+        let ys = receive_fixed(&mut self.conn, output_size, len)?;
+
+        let mut result: Vec<Vec<u8>> = Vec::with_capacity(output_size);
+        for i in 0..output_size {
+            let ht = hash_row(&self.hasher, batch, i, t_mat.row(i), len);
+            let y = &ys[i * len..(i + 1) * len];
+            if choice_bits[i] {
+                result.push(y.iter().zip(ht).map(|(y, h)| y ^ h).collect());
+            } else {
+                result.push(ht);
+            }
+        }
+        Ok(result)
+    }
+
+    fn receive_correlated_additive(&mut self, choice_bits: &BitVec) -> Result<Vec<u64>> {
+        let output_size = choice_bits.len();
+        let (t_mat, batch) = self.send_matrix(choice_bits)?;
+        let ys = receive_fixed(&mut self.conn, output_size, 8)?;
+
+        let mut result: Vec<u64> = Vec::with_capacity(output_size);
+        for i in 0..output_size {
+            let ht = hash_row_u64(&self.hasher, batch, i, t_mat.row(i));
+            if choice_bits[i] {
+                result.push(read_u64_be(&ys[i * 8..(i + 1) * 8]).wrapping_add(ht));
+            } else {
+                result.push(ht);
+            }
         }
         Ok(result)
     }
 }
 
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> RandomOTReceiver
+    for IKNPExtendedOTReceiver<T, A>
+{
+    fn random_receive(&mut self, choice_bits: &BitVec, len: usize) -> Result<Vec<Vec<u8>>> {
+        let (t_mat, batch) = self.send_matrix(choice_bits)?;
+        Ok((0..choice_bits.len())
+            .map(|i| hash_row(&self.hasher, batch, i, t_mat.row(i), len))
+            .collect())
+    }
+}
+
 pub struct IKNPExtendedOTSender<T, A>
 where
     T: BinaryReceive + BinarySend,
-    A: ArbitraryDigest + Clone,
+    A: Prg + CrHash,
 {
     conn: T,
-    arbitrary_hasher: A,
+    hasher: A,
     initial: Vec<Vec<u8>>,
     random_choices: BitVec,
+    batch: usize,
+    chunk_size: usize,
 }
 
-impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone> GetConn<T>
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> GetConn<T>
     for IKNPExtendedOTSender<T, A>
 {
     fn get_conn(self) -> T {
@@ -137,10 +234,34 @@ impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone> GetConn<T>
     }
 }
 
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> GetConnMut<T>
+    for IKNPExtendedOTSender<T, A>
+{
+    fn get_conn_mut(&mut self) -> &mut T {
+        &mut self.conn
+    }
+}
+
 /// security parameter: number of bytes to use
-impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone> IKNPExtendedOTSender<T, A> {
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> IKNPExtendedOTSender<T, A> {
+    /// Like `new`, in a session negotiated with `SessionParams::iknp`.
+    pub fn in_session<S, R>(
+        session: &Session,
+        hasher: A,
+        base_ot_receiver: S,
+        rng: R,
+    ) -> Result<Self>
+    where
+        S: BaseOTReceiver + GetConn<T>,
+        R: RngCore + CryptoRng,
+        A: Separable,
+    {
+        let params = session.params_for::<A>(session::IKNP)?;
+        Self::new(session.bind(hasher), base_ot_receiver, rng, params.security_param as usize)
+    }
+
     pub fn new<S, R>(
-        arbitrary_hasher: A,
+        hasher: A,
         mut base_ot_receiver: S,
         mut rng: R,
         security_param: usize,
@@ -150,97 +271,499 @@ impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone> IKNPExtendedOTSe
         R: RngCore + CryptoRng,
     {
         let l = security_param * 8;
-        let mut random_choices = BitVec::with_capacity(security_param);
-        let mut initial = Vec::with_capacity(security_param);
+        let mut random_choices = BitVec::with_capacity(l);
         for _ in 0..l {
-            let mut choice: bool = rng.gen();
-            initial.push(base_ot_receiver.receive(choice as usize, 2)?);
+            let choice: bool = rng.gen();
             random_choices.push(choice);
         }
+        let indices: Vec<usize> = random_choices.iter().map(|c| c as usize).collect();
+        let initial = base_ot_receiver.receive_many(&indices, 2)?;
         Ok(IKNPExtendedOTSender {
             conn: base_ot_receiver.get_conn(),
-            arbitrary_hasher,
+            hasher,
             initial,
             random_choices,
+            batch: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
         })
     }
 }
 
-impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone> ExtendedOTSender
-    for IKNPExtendedOTSender<T, A>
-{
-    fn send(&mut self, values: Vec<(&[u8], &[u8])>) -> Result<()> {
-        let output_size = values.len();
-        let l = self.initial.len();
-        let security_parameter = self.initial.len();
-        let mut us: Vec<BitVec> = Vec::with_capacity(security_parameter);
-        for _ in 0..security_parameter {
-            us.push(bv_truncate(&self.conn.receive()?, output_size));
-        }
-        assert_eq!(
-            us.len(),
-            self.initial.len(),
-            "internal error, lengths don't match."
-        );
-        assert_eq!(
-            us.len(),
-            self.random_choices.len(),
-            "internal error, lengths don't match."
-        );
-        let q_mat: Vec<BitVec> = izip!(&self.initial, &us, &self.random_choices)
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> IKNPExtendedOTSender<T, A> {
+    /// Sets the number of OTs whose ciphertexts are sent in one frame.
+    /// The receiver may use a different chunk size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = cmp::max(chunk_size, 1);
+    }
+
+    /// Receives the masked columns of the receiver and returns the matrix q
+    /// (with one row per OT) together with the number of this batch.
+    fn receive_matrix(&mut self, output_size: usize) -> Result<(BitMatrix, u64)> {
+        let batch = self.batch as u64;
+        self.batch += 1;
+        let us = receive_columns(&mut self.conn, self.initial.len(), output_size)?;
+        let q_mat = izip!(&self.initial, &us, &self.random_choices)
             .map(|(k, u, s)| {
-                let gk = trunc_hash(self.arbitrary_hasher.clone(), output_size, k);
+                let gk = expand(&self.hasher, batch, k, output_size);
                 u.iter()
                     .zip(gk)
                     .map(|(u, k)| (((s as u8) * (u as u8)) ^ (k as u8)) == 1)
-                    .collect()
-            })
-            .collect();
+                    .collect::<BitVec>()
+                    .to_bytes()
+            });
+        let q_mat = BitMatrix::from_rows(q_mat, output_size);
+        Ok((q_mat.transpose(), batch))
+    }
+}
 
-        for i in 0..output_size {
-            let n = values[i].0.len();
-            assert_eq!(n, values[i].1.len(), "String pairs do not have same size");
-            let mut qt = BitVec::with_capacity(l);
-            for q in &q_mat {
-                qt.push(q[i]);
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> ExtendedOTSender
+    for IKNPExtendedOTSender<T, A>
+{
+    fn send(&mut self, values: Vec<(&[u8], &[u8])>) -> Result<()> {
+        if values.iter().any(|(x0, x1)| x0.len() != x1.len()) {
+            bail!("String pairs do not have same size");
+        }
+        let (q_mat, batch) = self.receive_matrix(values.len())?;
+        let s = self.random_choices.to_bytes();
+
+        for (c, chunk) in values.chunks(self.chunk_size).enumerate() {
+            let mut ys = Vec::with_capacity(2 * chunk.len());
+            for (j, (x0, x1)) in chunk.iter().enumerate() {
+                let i = c * self.chunk_size + j;
+                let (qt, sqt) = rows(&q_mat, &s, i);
+                let hq = hash_row(&self.hasher, batch, i, qt, x0.len());
+                let shq = hash_row(&self.hasher, batch, i, &sqt, x1.len());
+                ys.push(x0.iter().zip(hq).map(|(x, q)| x ^ q).collect());
+                ys.push(x1.iter().zip(shq).map(|(x, q)| x ^ q).collect());
             }
-            let mut hasher = self.arbitrary_hasher.clone();
-            hasher.input(&(i as u64).to_bytes());
-            let mut hasher2 = hasher.clone();
-            // TODO make this nicer
-            hasher.input(&qt.to_bytes());
-            let hq = hasher.result(n);
-            let y0: Vec<u8> = values[i].0.iter().zip(hq).map(|(x, q)| x ^ q).collect();
-            let mut q2: Vec<u8> = qt
-                .to_bytes()
-                .iter()
-                .zip(self.random_choices.to_bytes())
-                .map(|(q, s)| q ^ s)
-                .collect();
-            hasher2.input(&q2);
-            let shq = hasher2.result(n);
-            let y1: Vec<u8> = values[i].1.iter().zip(shq).map(|(x, q)| x ^ q).collect();
-            self.conn.send(&y0)?;
-            self.conn.send(&y1)?;
+            self.conn.send(&pack(&ys))?;
         }
         Ok(())
     }
 }
 
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> CorrelatedOTSender
+    for IKNPExtendedOTSender<T, A>
+{
+    fn send_correlated(&mut self, n: usize, delta: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let (q_mat, batch) = self.receive_matrix(n)?;
+        let s = self.random_choices.to_bytes();
+
+        let mut result = Vec::with_capacity(n);
+        let mut ys = Vec::with_capacity(n * delta.len());
+        for i in 0..n {
+            // x0 = H(q), the receiver learns x0 ⊕ delta from y = x0 ⊕ delta ⊕ H(q ⊕ s)
+            let (qt, sqt) = rows(&q_mat, &s, i);
+            let x0 = hash_row(&self.hasher, batch, i, qt, delta.len());
+            let shq = hash_row(&self.hasher, batch, i, &sqt, delta.len());
+            ys.extend(izip!(&x0, delta, shq).map(|(x, d, h)| x ^ d ^ h));
+            result.push(x0);
+        }
+        send_fixed(&mut self.conn, &ys, delta.len(), self.chunk_size)?;
+        Ok(result)
+    }
+
+    fn send_correlated_additive(&mut self, deltas: &[u64]) -> Result<Vec<u64>> {
+        let (q_mat, batch) = self.receive_matrix(deltas.len())?;
+        let s = self.random_choices.to_bytes();
+
+        let mut result = Vec::with_capacity(deltas.len());
+        let mut ys = vec![0; 8 * deltas.len()];
+        for (i, delta) in deltas.iter().enumerate() {
+            // x0 = H(q), the receiver learns x0 + delta from y = x0 + delta - H(q ⊕ s)
+            let (qt, sqt) = rows(&q_mat, &s, i);
+            let x0 = hash_row_u64(&self.hasher, batch, i, qt);
+            let shq = hash_row_u64(&self.hasher, batch, i, &sqt);
+            write_u64_be(
+                &mut ys[i * 8..(i + 1) * 8],
+                x0.wrapping_add(*delta).wrapping_sub(shq),
+            );
+            result.push(x0);
+        }
+        send_fixed(&mut self.conn, &ys, 8, self.chunk_size)?;
+        Ok(result)
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> RandomOTSender
+    for IKNPExtendedOTSender<T, A>
+{
+    fn random_send(&mut self, n: usize, len: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let (q_mat, batch) = self.receive_matrix(n)?;
+        let s = self.random_choices.to_bytes();
+        Ok((0..n)
+            .map(|i| {
+                let (qt, sqt) = rows(&q_mat, &s, i);
+                (
+                    hash_row(&self.hasher, batch, i, qt, len),
+                    hash_row(&self.hasher, batch, i, &sqt, len),
+                )
+            })
+            .collect())
+    }
+}
+
+/// Sends the columns of a matrix in frames of `chunk_size` rows.
+pub(super) fn send_columns<T: BinarySend>(
+    conn: &mut T,
+    columns: &[Vec<u8>],
+    chunk_size: usize,
+) -> Result<()> {
+    for frame in column_frames(columns, chunk_size) {
+        conn.send(&frame)?;
+    }
+    Ok(())
+}
+
+/// Receives `count` columns of `rows` bits sent with `send_columns`.
+pub(super) fn receive_columns<T: BinaryReceive>(
+    conn: &mut T,
+    count: usize,
+    rows: usize,
+) -> Result<Vec<BitVec>> {
+    let mut collector = ColumnCollector::new(count, rows);
+    while !collector.is_complete() {
+        collector.push(&conn.receive()?)?;
+    }
+    Ok(collector.into_columns())
+}
+
+/// Receives the ciphertext pairs of `n` OTs, packed into frames of arbitrary many pairs.
+pub(super) fn receive_pairs<T: BinaryReceive>(
+    conn: &mut T,
+    n: usize,
+) -> Result<Vec<[Vec<u8>; 2]>> {
+    let mut pairs = Vec::with_capacity(n);
+    while pairs.len() < n {
+        let remaining = n - pairs.len();
+        pairs.extend(unpack_pairs(&conn.receive()?, remaining)?);
+    }
+    Ok(pairs)
+}
+
+/// Sends the concatenated corrections of `len` bytes each, `chunk_size` of them per frame.
+fn send_fixed<T: BinarySend>(conn: &mut T, ys: &[u8], len: usize, chunk_size: usize) -> Result<()> {
+    for frame in ys.chunks(cmp::max(len * chunk_size, 1)) {
+        conn.send(frame)?;
+    }
+    Ok(())
+}
+
+/// Receives `n` corrections of `len` bytes each sent with `send_fixed`, concatenated.
+fn receive_fixed<T: BinaryReceive>(conn: &mut T, n: usize, len: usize) -> Result<Vec<u8>> {
+    let total = n * len;
+    let mut ys = Vec::with_capacity(total);
+    while ys.len() < total {
+        let frame = conn.receive()?;
+        if frame.is_empty() || frame.len() % len != 0 || ys.len() + frame.len() > total {
+            bail!("Received corrections have the wrong length");
+        }
+        ys.extend_from_slice(&frame);
+    }
+    Ok(ys)
+}
+
+/// The i-th row of q and its xor with the packed random choices s.
+fn rows<'a>(q_mat: &'a BitMatrix, s: &[u8], i: usize) -> (&'a [u8], Vec<u8>) {
+    let qt = q_mat.row(i);
+    let sqt = qt.iter().zip(s).map(|(q, s)| q ^ s).collect();
+    (qt, sqt)
+}
+
+/// Expands a seed to `bits` pseudorandom bits.
+fn expand<A: Prg>(prg: &A, batch: u64, seed: &[u8], bits: usize) -> BitVec {
+    bv_truncate(&prg.expand(batch, seed, bits.div_ceil(8)), bits)
+}
+
+/// H(i || row), truncated to len bytes.
+fn hash_row<A: CrHash>(hasher: &A, batch: u64, i: usize, row: &[u8], len: usize) -> Vec<u8> {
+    hasher.hash(batch, i as u64, row, len)
+}
+
+/// H(i || row) as element of Z_{2^64}.
+fn hash_row_u64<A: CrHash>(hasher: &A, batch: u64, i: usize, row: &[u8]) -> u64 {
+    read_u64_be(&hash_row(hasher, batch, i, row, 8))
+}
+
 #[cfg(test)]
 mod tests {
 
-    use common::digest::sha3::SHA3_256;
-    use common::util::{generate_random_choices, generate_random_string_pairs};
+    use crate::common::digest::aes::AesHash;
+    use crate::common::digest::sha3::SHA3_256;
+    use crate::common::util::{generate_random_choices, generate_random_string_pairs, pack};
     use rand::ChaChaRng;
     use rand::FromEntropy;
-    use std::net::{TcpListener, TcpStream};
+    use std::sync::{Arc, Mutex};
     use std::thread;
     use std::time::Instant;
-    use sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
-    use sync::crypto::aes::AesCryptoProvider;
-    use sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
-    use sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
+    use crate::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+    use crate::sync::communication::adversary::AdversaryChannel;
+    use crate::sync::communication::memory::{channel_pair, MemoryChannel};
+    use crate::sync::communication::{BinaryReceive, BinarySend, GetConnMut};
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
+    use crate::sync::ot_extension::{
+        CorrelatedOTReceiver, CorrelatedOTSender, ExtendedOTReceiver, ExtendedOTSender,
+        RandomOTReceiver, RandomOTSender,
+    };
+
+    type Receiver = IKNPExtendedOTReceiver<MemoryChannel, SHA3_256>;
+    type Sender = IKNPExtendedOTSender<MemoryChannel, SHA3_256>;
+
+    /// Sets up an IKNP receiver and sender in-process and runs the given closures with them.
+    fn with_iknp<F, G, X, Y>(receive: F, send: G) -> (X, Y)
+    where
+        F: FnOnce(&mut Receiver) -> X + Send + 'static,
+        G: FnOnce(&mut Sender) -> Y + Send + 'static,
+        X: Send + 'static,
+        Y: Send + 'static,
+    {
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let ot_stream = server_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTSender::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, rng, 16).unwrap();
+            receive(&mut ot_ext)
+        });
+        let client = thread::spawn(move || {
+            let ot_stream = client_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTReceiver::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng, 16).unwrap();
+            send(&mut ot_ext)
+        });
+        (server.join().unwrap(), client.join().unwrap())
+    }
+
+    #[test]
+    fn iknp_correlated_test() {
+        let choices = generate_random_choices(100);
+        let choices2 = choices.clone();
+        let delta: Vec<u8> = (0..40).collect();
+        let delta2 = delta.clone();
+        let (received, sent) = with_iknp(move |ot| ot.receive_correlated(&choices2, 40).unwrap(),
+            move |ot| ot.send_correlated(100, &delta2).unwrap(),
+        );
+        for (i, choice) in choices.iter().enumerate() {
+            let expected: Vec<u8> = if choice {
+                sent[i].iter().zip(&delta).map(|(x, d)| x ^ d).collect()
+            } else {
+                sent[i].clone()
+            };
+            assert_eq!(expected, received[i], "Values differ at index {}", i);
+        }
+    }
+
+    #[test]
+    fn iknp_correlated_additive_test() {
+        let choices = generate_random_choices(100);
+        let choices2 = choices.clone();
+        let deltas: Vec<u64> = (0..100).map(|i| u64::MAX - i * i).collect();
+        let deltas2 = deltas.clone();
+        let (received, sent) = with_iknp(move |ot| ot.receive_correlated_additive(&choices2).unwrap(),
+            move |ot| ot.send_correlated_additive(&deltas2).unwrap(),
+        );
+        for (i, choice) in choices.iter().enumerate() {
+            let expected = if choice {
+                sent[i].wrapping_add(deltas[i])
+            } else {
+                sent[i]
+            };
+            assert_eq!(expected, received[i], "Values differ at index {}", i);
+        }
+    }
+
+    #[test]
+    fn iknp_random_test() {
+        let choices = generate_random_choices(100);
+        let choices2 = choices.clone();
+        let (received, sent) = with_iknp(move |ot| ot.random_receive(&choices2, 40).unwrap(),
+            |ot| ot.random_send(100, 40).unwrap(),
+        );
+        for (i, choice) in choices.iter().enumerate() {
+            assert_ne!(sent[i].0, sent[i].1);
+            let expected = if choice { &sent[i].1 } else { &sent[i].0 };
+            assert_eq!(expected, &received[i], "Values differ at index {}", i);
+        }
+    }
+
+    #[test]
+    fn iknp_batches_test() {
+        let len = 100;
+        let choices = generate_random_choices(len);
+        let values = generate_random_string_pairs(20, len);
+        let choices2 = choices.clone();
+        let values2 = values.clone();
+
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let frames = Arc::new(Mutex::new(Vec::new()));
+            let recorded = Arc::clone(&frames);
+            let mut ot_stream = AdversaryChannel::new(server_conn, Vec::new());
+            ot_stream.set_eavesdropper(move |frame| {
+                recorded.lock().unwrap().push(frame.to_vec());
+            });
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTSender::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, rng, 16).unwrap();
+            let first = ot_ext.receive(&choices2).unwrap();
+            let second = ot_ext.receive(&choices2).unwrap();
+            let frames = frames.lock().unwrap().clone();
+            (first, second, frames)
+        });
+        let client = thread::spawn(move || {
+            let ot_stream = client_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTReceiver::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng, 16).unwrap();
+            let values: Vec<(&[u8], &[u8])> = values2
+                .iter()
+                .map(|(s1, s2)| (s1.as_bytes(), s2.as_bytes()))
+                .collect();
+            ot_ext.send(values.clone()).unwrap();
+            ot_ext.send(values).unwrap();
+        });
+        let (first, second, frames) = server.join().unwrap();
+        client.join().unwrap();
+
+        for rec_values in &[first, second] {
+            for (i, choice) in choices.iter().enumerate() {
+                let expected = if choice { &values[i].1 } else { &values[i].0 };
+                assert_eq!(expected.as_bytes(), rec_values[i].as_slice());
+            }
+        }
+        // all 128 u-columns of a batch fit into a single frame
+        let column_len = len.div_ceil(8);
+        let matrices: Vec<&Vec<u8>> = frames
+            .iter()
+            .filter(|f| f.len() == 128 * column_len)
+            .collect();
+        assert_eq!(matrices.len(), 2);
+        for (u1, u2) in matrices[0]
+            .chunks(column_len)
+            .zip(matrices[1].chunks(column_len))
+        {
+            assert_ne!(u1, u2, "u-columns of two batches are equal");
+        }
+    }
+
+    #[test]
+    fn iknp_chunked_test() {
+        let len = 100;
+        let choices = generate_random_choices(len);
+        let values = generate_random_string_pairs(20, len);
+        let choices2 = choices.clone();
+        let values2 = values.clone();
+        // both parties use different chunk sizes which do not divide the number of OTs
+        let (received, _) = with_iknp(move |ot| {
+                ot.set_chunk_size(13);
+                let values = ot.receive(&choices2).unwrap();
+                let correlated = ot.receive_correlated(&choices2, 5).unwrap();
+                (values, correlated)
+            },
+            move |ot| {
+                ot.set_chunk_size(7);
+                let values: Vec<(&[u8], &[u8])> = values2
+                    .iter()
+                    .map(|(s1, s2)| (s1.as_bytes(), s2.as_bytes()))
+                    .collect();
+                ot.send(values).unwrap();
+                ot.send_correlated(len, &[0; 5]).unwrap();
+            },
+        );
+        let (rec_values, correlated) = received;
+        for (i, choice) in choices.iter().enumerate() {
+            let expected = if choice { &values[i].1 } else { &values[i].0 };
+            assert_eq!(expected.as_bytes(), rec_values[i].as_slice());
+        }
+        assert_eq!(correlated.len(), len);
+    }
+
+    #[test]
+    fn iknp_aes_test() {
+        let len = 100;
+        let choices = generate_random_choices(len);
+        let values = generate_random_string_pairs(30, len);
+        let choices2 = choices.clone();
+        let values2 = values.clone();
+
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let ot_stream = server_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTSender::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = IKNPExtendedOTReceiver::new(AesHash::default(), ot, rng, 16).unwrap();
+            let values = ot_ext.receive(&choices2).unwrap();
+            let random = ot_ext.random_receive(&choices2, 8).unwrap();
+            (values, random)
+        });
+        let client = thread::spawn(move || {
+            let ot_stream = client_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTReceiver::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = IKNPExtendedOTSender::new(AesHash::default(), ot, rng, 16).unwrap();
+            let values: Vec<(&[u8], &[u8])> = values2
+                .iter()
+                .map(|(s1, s2)| (s1.as_bytes(), s2.as_bytes()))
+                .collect();
+            ot_ext.send(values).unwrap();
+            ot_ext.random_send(len, 8).unwrap()
+        });
+        let (rec_values, rec_random) = server.join().unwrap();
+        let random = client.join().unwrap();
+
+        for (i, choice) in choices.iter().enumerate() {
+            let expected = if choice { &values[i].1 } else { &values[i].0 };
+            assert_eq!(expected.as_bytes(), rec_values[i].as_slice());
+            let expected = if choice { &random[i].1 } else { &random[i].0 };
+            assert_eq!(expected, &rec_random[i]);
+        }
+    }
+
+    #[test]
+    fn iknp_malformed_ciphertexts_test() {
+        let choices = generate_random_choices(100);
+        let (result, _) = with_iknp(move |ot| ot.receive(&choices).map(|_| ()),
+            |ot| {
+                let conn = ot.get_conn_mut();
+                conn.receive().unwrap();
+                // a pair of ciphertexts with different lengths
+                conn.send(&pack(&[vec![0; 4], vec![0; 5]])).unwrap();
+            },
+        );
+        assert!(result.is_err());
+    }
 
     #[test]
     fn iknp_test() {
@@ -257,12 +780,9 @@ mod tests {
         );
 
         let choices2 = choices.clone();
+        let (server_conn, client_conn) = channel_pair();
         let server = thread::spawn(move || {
-            let ot_stream = TcpListener::bind("127.0.0.1:1256")
-                .unwrap()
-                .accept()
-                .unwrap()
-                .0;
+            let ot_stream = server_conn;
             let rng = ChaChaRng::from_entropy();
             let mut now = Instant::now();
             let ot = ChouOrlandiOTSender::new(
@@ -278,7 +798,7 @@ mod tests {
                     .unwrap();
             println!("IKNP receiver creation took {:?}", now.elapsed());
             now = Instant::now();
-            let mut values: Vec<String> = ot_ext
+            let values: Vec<String> = ot_ext
                 .receive(&choices2)
                 .unwrap()
                 .into_iter()
@@ -289,7 +809,7 @@ mod tests {
         });
         let values2 = values.clone();
         let client = thread::spawn(move || {
-            let ot_stream = TcpStream::connect("127.0.0.1:1256").unwrap();
+            let ot_stream = client_conn;
             let rng = ChaChaRng::from_entropy();
             let mut now = Instant::now();
             let ot = ChouOrlandiOTReceiver::new(
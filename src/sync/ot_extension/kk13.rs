@@ -0,0 +1,382 @@
+//! Kolesnikov and Kumaresan's 1-out-of-n ot extension with security against passive adversaries.
+//! [https://eprint.iacr.org/2013/491.pdf]
+//!
+//! The protocol is IKNP where the repetition code, which encodes a choice bit as all zeros or ones,
+//! is replaced by the Walsh-Hadamard code. Its codewords have a length of 256 bits and a distance of 128,
+//! so choices between up to 256 messages are supported with 256 base OTs and 128 bits of security.
+//! Every message costs the sender one hash, the receiver only computes one hash per OT.
+
+use super::iknp::DEFAULT_CHUNK_SIZE;
+use super::{ExtendedNOTReceiver, ExtendedNOTSender};
+use bit_vec::BitVec;
+use crate::common::bitmatrix::BitMatrix;
+use crate::common::chunks::{column_frames, ColumnCollector};
+use crate::common::digest::{CrHash, Prg, Separable};
+use crate::common::util::{pack, unpack};
+use crate::errors::*;
+use rand::{CryptoRng, Rng, RngCore};
+use std::cmp;
+use crate::sync::base_ot::BaseOTReceiver;
+use crate::sync::base_ot::BaseOTSender;
+use crate::sync::communication::{BinaryReceive, BinarySend, GetConn, GetConnMut};
+use crate::sync::session::{self, Session};
+
+/// Length of the Walsh-Hadamard codewords, i.e. the number of base OTs.
+pub const CODE_LENGTH: usize = 256;
+/// Maximum number of messages per OT.
+pub const MAX_N: usize = 256;
+/// Number of bytes of the seeds transferred with the base OTs.
+const SEED_LEN: usize = 16;
+
+/// The Walsh-Hadamard codeword of `x`, its j-th bit is the parity of `x & j`.
+fn codeword(x: usize) -> Vec<u8> {
+    let mut word = vec![0; CODE_LENGTH / 8];
+    for j in 0..CODE_LENGTH {
+        if (x & j).count_ones() % 2 == 1 {
+            word[j / 8] |= 0x80 >> (j % 8);
+        }
+    }
+    word
+}
+
+pub struct KK13ExtendedNOTReceiver<T, A>
+where
+    T: BinaryReceive + BinarySend,
+    A: Prg + CrHash,
+{
+    conn: T,
+    hasher: A,
+    initial_pairs: Vec<(Vec<u8>, Vec<u8>)>,
+    batch: usize,
+    chunk_size: usize,
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> GetConn<T> for KK13ExtendedNOTReceiver<T, A> {
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> GetConnMut<T>
+    for KK13ExtendedNOTReceiver<T, A>
+{
+    fn get_conn_mut(&mut self) -> &mut T {
+        &mut self.conn
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> KK13ExtendedNOTReceiver<T, A> {
+    /// Like `new`, in a session negotiated with `SessionParams::kk13`.
+    pub fn in_session<S, R>(session: &Session, hasher: A, base_ot_sender: S, rng: R) -> Result<Self>
+    where
+        S: BaseOTSender + GetConn<T>,
+        R: RngCore + CryptoRng,
+        A: Separable,
+    {
+        session.params_for::<A>(session::KK13)?;
+        Self::new(session.bind(hasher), base_ot_sender, rng)
+    }
+
+    /// Transfers the seeds with the base OT, just like `IKNPExtendedOTReceiver`
+    /// but with one pair of seeds per bit of the codewords.
+    pub fn new<S, R>(hasher: A, mut base_ot_sender: S, mut rng: R) -> Result<Self>
+    where
+        S: BaseOTSender + GetConn<T>,
+        R: RngCore + CryptoRng,
+    {
+        let initial_pairs: Vec<(Vec<u8>, Vec<u8>)> = (0..CODE_LENGTH)
+            .map(|_| {
+                let k0 = (0..SEED_LEN).map(|_| rng.gen()).collect();
+                let k1 = (0..SEED_LEN).map(|_| rng.gen()).collect();
+                (k0, k1)
+            })
+            .collect();
+        base_ot_sender.send_many(
+            initial_pairs
+                .iter()
+                .map(|(k0, k1)| vec![k0.as_slice(), k1.as_slice()])
+                .collect(),
+        )?;
+        Ok(KK13ExtendedNOTReceiver {
+            conn: base_ot_sender.get_conn(),
+            hasher,
+            initial_pairs,
+            batch: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Sets the number of OTs whose columns are sent in one frame, it is rounded up
+    /// to a multiple of 8. The sender may use a different chunk size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = cmp::max(chunk_size, 1);
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> ExtendedNOTReceiver
+    for KK13ExtendedNOTReceiver<T, A>
+{
+    fn receive(&mut self, choices: &[usize]) -> Result<Vec<Vec<u8>>> {
+        if choices.iter().any(|&c| c >= MAX_N) {
+            bail!("Choice is out of range");
+        }
+        let m = choices.len();
+        let len = m.div_ceil(8);
+        let batch = self.batch as u64;
+        self.batch += 1;
+
+        // the columns of the matrix whose rows are the codewords of our choices
+        let codes = BitMatrix::from_rows(choices.iter().map(|&c| codeword(c)), CODE_LENGTH).transpose();
+        let t_mat: Vec<Vec<u8>> = self
+            .initial_pairs
+            .iter()
+            .map(|(k0, _)| self.hasher.expand(batch, k0, len))
+            .collect();
+        let u_mat: Vec<Vec<u8>> = izip!(&self.initial_pairs, &t_mat)
+            .enumerate()
+            .map(|(j, ((_, k1), t))| {
+                let gk = self.hasher.expand(batch, k1, len);
+                izip!(t, gk, codes.row(j))
+                    .map(|(t, k, c)| t ^ k ^ c)
+                    .collect()
+            })
+            .collect();
+        for frame in column_frames(&u_mat, self.chunk_size) {
+            self.conn.send(&frame)?;
+        }
+        let t_mat = BitMatrix::from_rows(&t_mat, m).transpose();
+
+        // every frame contains the packed ciphertexts of some OTs
+        let mut result: Vec<Vec<u8>> = Vec::with_capacity(m);
+        while result.len() < m {
+            let ots = unpack(&self.conn.receive()?)?;
+            if ots.is_empty() || result.len() + ots.len() > m {
+                bail!("Received wrong number of OTs");
+            }
+            for ot in ots {
+                let i = result.len();
+                let ys = unpack(&ot)?;
+                let y = match ys.get(choices[i]) {
+                    Some(y) => y,
+                    None => bail!("Received too few ciphertexts"),
+                };
+                let ht = self.hasher.hash(batch, i as u64, t_mat.row(i), y.len());
+                result.push(y.iter().zip(ht).map(|(y, h)| y ^ h).collect());
+            }
+        }
+        Ok(result)
+    }
+}
+
+pub struct KK13ExtendedNOTSender<T, A>
+where
+    T: BinaryReceive + BinarySend,
+    A: Prg + CrHash,
+{
+    conn: T,
+    hasher: A,
+    initial: Vec<Vec<u8>>,
+    random_choices: BitVec,
+    batch: usize,
+    chunk_size: usize,
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> GetConn<T> for KK13ExtendedNOTSender<T, A> {
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> GetConnMut<T>
+    for KK13ExtendedNOTSender<T, A>
+{
+    fn get_conn_mut(&mut self) -> &mut T {
+        &mut self.conn
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> KK13ExtendedNOTSender<T, A> {
+    /// Like `new`, in a session negotiated with `SessionParams::kk13`.
+    pub fn in_session<S, R>(
+        session: &Session,
+        hasher: A,
+        base_ot_receiver: S,
+        rng: R,
+    ) -> Result<Self>
+    where
+        S: BaseOTReceiver + GetConn<T>,
+        R: RngCore + CryptoRng,
+        A: Separable,
+    {
+        session.params_for::<A>(session::KK13)?;
+        Self::new(session.bind(hasher), base_ot_receiver, rng)
+    }
+
+    pub fn new<S, R>(hasher: A, mut base_ot_receiver: S, mut rng: R) -> Result<Self>
+    where
+        S: BaseOTReceiver + GetConn<T>,
+        R: RngCore + CryptoRng,
+    {
+        let random_choices: BitVec = (0..CODE_LENGTH).map(|_| rng.gen::<bool>()).collect();
+        let indices: Vec<usize> = random_choices.iter().map(|c| c as usize).collect();
+        let initial = base_ot_receiver.receive_many(&indices, 2)?;
+        Ok(KK13ExtendedNOTSender {
+            conn: base_ot_receiver.get_conn(),
+            hasher,
+            initial,
+            random_choices,
+            batch: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Sets the number of OTs whose ciphertexts are sent in one frame.
+    /// The receiver may use a different chunk size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = cmp::max(chunk_size, 1);
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: Prg + CrHash> ExtendedNOTSender
+    for KK13ExtendedNOTSender<T, A>
+{
+    fn send(&mut self, values: Vec<Vec<&[u8]>>) -> Result<()> {
+        if values.iter().any(|v| v.is_empty() || v.len() > MAX_N) {
+            bail!("Every OT needs between 1 and 256 messages");
+        }
+        let m = values.len();
+        let len = m.div_ceil(8);
+        let batch = self.batch as u64;
+        self.batch += 1;
+
+        let mut collector = ColumnCollector::new(CODE_LENGTH, m);
+        while !collector.is_complete() {
+            collector.push(&self.conn.receive()?)?;
+        }
+        // q^j = t^j ⊕ s_j * c^j, so row i of q is t_i ⊕ (C(r_i) ∧ s)
+        let q_mat: Vec<Vec<u8>> = izip!(&self.initial, collector.into_columns(), &self.random_choices)
+            .map(|(k, u, s)| {
+                let gk = self.hasher.expand(batch, k, len);
+                if s {
+                    gk.iter().zip(u.to_bytes()).map(|(k, u)| k ^ u).collect()
+                } else {
+                    gk
+                }
+            })
+            .collect();
+        let q_mat = BitMatrix::from_rows(&q_mat, m).transpose();
+
+        // the key of message x is H(i, q_i ⊕ (C(x) ∧ s)), which is H(i, t_i) for x = r_i
+        let s = self.random_choices.to_bytes();
+        let n = values.iter().map(|v| v.len()).max().unwrap_or(0);
+        let masks: Vec<Vec<u8>> = (0..n)
+            .map(|x| codeword(x).iter().zip(&s).map(|(c, s)| c & s).collect())
+            .collect();
+        for (c, chunk) in values.chunks(self.chunk_size).enumerate() {
+            let mut ots = Vec::with_capacity(chunk.len());
+            for (j, messages) in chunk.iter().enumerate() {
+                let i = c * self.chunk_size + j;
+                let qt = q_mat.row(i);
+                let ys: Vec<Vec<u8>> = messages
+                    .iter()
+                    .zip(&masks)
+                    .map(|(x, mask)| {
+                        let key: Vec<u8> = qt.iter().zip(mask).map(|(q, m)| q ^ m).collect();
+                        let h = self.hasher.hash(batch, i as u64, &key, x.len());
+                        x.iter().zip(h).map(|(x, h)| x ^ h).collect()
+                    })
+                    .collect();
+                ots.push(pack(&ys));
+            }
+            self.conn.send(&pack(&ots))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::digest::aes::AesHash;
+    use crate::common::digest::sha3::SHA3_256;
+    use rand::distributions::range::Range;
+    use rand::{thread_rng, ChaChaRng, FromEntropy};
+    use std::thread;
+    use crate::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+    use crate::sync::communication::memory::channel_pair;
+    use crate::sync::crypto::aes::AesCryptoProvider;
+
+    #[test]
+    fn walsh_hadamard_distance() {
+        for x in 0..MAX_N {
+            for y in 0..x {
+                let distance: u32 = codeword(x)
+                    .iter()
+                    .zip(codeword(y))
+                    .map(|(a, b)| (a ^ b).count_ones())
+                    .sum();
+                assert_eq!(distance as usize, CODE_LENGTH / 2);
+            }
+        }
+    }
+
+    #[test]
+    fn kk13_test() {
+        // every OT has its own number of messages
+        let mut rng = thread_rng();
+        let values: Vec<Vec<Vec<u8>>> = (0..100)
+            .map(|i| {
+                let n = [2, 3, 16, 100, 256][i % 5];
+                (0..n).map(|_| (0..20).map(|_| rng.gen()).collect()).collect()
+            })
+            .collect();
+        let choices: Vec<usize> = values
+            .iter()
+            .map(|v| rng.sample(Range::new(0, v.len())))
+            .collect();
+        let choices2 = choices.clone();
+        let values2 = values.clone();
+
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let ot_stream = server_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTSender::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = KK13ExtendedNOTReceiver::new(AesHash::default(), ot, rng).unwrap();
+            ot_ext.set_chunk_size(30);
+            let first = ot_ext.receive(&choices2).unwrap();
+            let second = ot_ext.receive(&choices2).unwrap();
+            (first, second)
+        });
+        let client = thread::spawn(move || {
+            let ot_stream = client_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTReceiver::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext = KK13ExtendedNOTSender::new(AesHash::default(), ot, rng).unwrap();
+            ot_ext.set_chunk_size(7);
+            let values: Vec<Vec<&[u8]>> = values2
+                .iter()
+                .map(|v| v.iter().map(|x| x.as_slice()).collect())
+                .collect();
+            ot_ext.send(values.clone()).unwrap();
+            ot_ext.send(values).unwrap();
+        });
+        let (first, second) = server.join().unwrap();
+        client.join().unwrap();
+        for received in &[first, second] {
+            for (i, &choice) in choices.iter().enumerate() {
+                assert_eq!(values[i][choice], received[i], "Values differ at index {}", i);
+            }
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! Keller, Orsini and Scholl's ot extension with security against active adversaries.
+//! [https://eprint.iacr.org/2015/546.pdf]
+//!
+//! The protocol is IKNP with an additional correlation check: after the receiver has sent its matrix
+//! the sender chooses random field elements and the receiver has to prove that it used the same
+//! choice bits in every column by revealing a random linear combination of its rows in GF(2^128).
+//! As the check is done in GF(2^128) the (computational) security parameter is fixed to 16 bytes.
+//!
+//! Like IKNP the columns of u and the ciphertexts are sent in chunks of `DEFAULT_CHUNK_SIZE`
+//! OTs per frame, see `set_chunk_size`.
+//!
+//! The seeds are expanded and the rows hashed with an `ArbitraryDigest` like `SHA3_256`. Switching
+//! to the `Prg`/`CrHash` of `AesHash` as IKNP does is deliberately left out: the security proof
+//! of KOS15 assumes a random oracle for the row hashes, which fixed-key AES is not.
+
+use super::iknp::{receive_columns, receive_pairs, send_columns, DEFAULT_CHUNK_SIZE};
+use super::{ExtendedOTReceiver, ExtendedOTSender};
+use bit_vec::BitVec;
+use crate::common::digest::ArbitraryDigest;
+use crate::common::gf128::{gf128_from_bits, gf128_from_bytes, gf128_mul, gf128_to_bytes};
+use crate::common::util::{batch_hasher, pack, trunc_hash};
+use crate::errors::*;
+use rand::{ChaChaRng, CryptoRng, Rng, RngCore, SeedableRng};
+use std::cmp;
+use crate::sync::base_ot::BaseOTReceiver;
+use crate::sync::base_ot::BaseOTSender;
+use crate::sync::communication::{BinaryReceive, BinarySend, GetConn, GetConnMut};
+use crate::sync::session::{self, Session};
+
+/// KOS15 is only defined for a security parameter of 128 bits.
+pub const KOS_SECURITY_PARAM: usize = 16;
+
+/// Both parties derive the random field elements for the correlation check from a seed
+/// which the sender chooses after it received the receiver's matrix.
+fn check_elements(seed: &[u8], n: usize) -> Result<Vec<u128>> {
+    if seed.len() != 32 {
+        bail!("Received seed for correlation check has the wrong length");
+    }
+    let mut rng = ChaChaRng::from_seed(*array_ref![seed, 0, 32]);
+    Ok((0..n)
+        .map(|_| {
+            let mut bytes: [u8; 16] = Default::default();
+            rng.fill_bytes(&mut bytes);
+            gf128_from_bytes(&bytes)
+        })
+        .collect())
+}
+
+/// The i-th row of a matrix given by its columns, as element of GF(2^128).
+fn row(columns: &[BitVec], i: usize) -> u128 {
+    gf128_from_bits(columns.iter().map(|c| c[i]))
+}
+
+fn hash_row<A: ArbitraryDigest + Clone>(hasher: &A, i: usize, row: u128, len: usize) -> Vec<u8> {
+    let mut hasher = hasher.clone();
+    hasher.input(&(i as u64).to_be_bytes());
+    hasher.input(&gf128_to_bytes(row));
+    hasher.result(len)
+}
+
+pub struct KOSExtendedOTReceiver<T, A, R>
+where
+    T: BinaryReceive + BinarySend,
+    A: ArbitraryDigest + Clone,
+    R: RngCore + CryptoRng,
+{
+    conn: T,
+    hasher: A,
+    rng: R,
+    initial_pairs: Vec<(Vec<u8>, Vec<u8>)>,
+    stat_security_param: usize,
+    batch: usize,
+    chunk_size: usize,
+}
+
+impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone, R: RngCore + CryptoRng> GetConn<T>
+    for KOSExtendedOTReceiver<T, A, R>
+{
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone, R: RngCore + CryptoRng> GetConnMut<T>
+    for KOSExtendedOTReceiver<T, A, R>
+{
+    fn get_conn_mut(&mut self) -> &mut T {
+        &mut self.conn
+    }
+}
+
+/// statistical security parameter: number of bytes to use
+impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone, R: RngCore + CryptoRng>
+    KOSExtendedOTReceiver<T, A, R>
+{
+    /// Like `new`, in a session negotiated with `SessionParams::kos`.
+    pub fn in_session<S>(session: &Session, hasher: A, base_ot_sender: S, rng: R) -> Result<Self>
+    where
+        S: BaseOTSender + GetConn<T>,
+    {
+        let params = session.params_for::<A>(session::KOS)?;
+        Self::new(session.bind(hasher), base_ot_sender, rng, params.statistical_param as usize)
+    }
+
+    pub fn new<S>(
+        hasher: A,
+        mut base_ot_sender: S,
+        mut rng: R,
+        stat_security_param: usize,
+    ) -> Result<Self>
+    where
+        S: BaseOTSender + GetConn<T>,
+    {
+        let l = KOS_SECURITY_PARAM * 8;
+        let mut initial_pairs = Vec::with_capacity(l);
+        for _ in 0..l {
+            let mut k0: Vec<u8> = Vec::with_capacity(KOS_SECURITY_PARAM);
+            let mut k1: Vec<u8> = Vec::with_capacity(KOS_SECURITY_PARAM);
+            for _ in 0..KOS_SECURITY_PARAM {
+                k0.push(rng.gen());
+                k1.push(rng.gen());
+            }
+            initial_pairs.push((k0, k1));
+        }
+        base_ot_sender.send_many(
+            initial_pairs
+                .iter()
+                .map(|(k0, k1)| vec![k0.as_slice(), k1.as_slice()])
+                .collect(),
+        )?;
+        Ok(KOSExtendedOTReceiver {
+            conn: base_ot_sender.get_conn(),
+            hasher,
+            rng,
+            initial_pairs,
+            stat_security_param,
+            batch: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Sets the number of OTs whose columns are sent in one frame, it is rounded up
+    /// to a multiple of 8. The sender may use a different chunk size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = cmp::max(chunk_size, 1);
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone, R: RngCore + CryptoRng>
+    ExtendedOTReceiver for KOSExtendedOTReceiver<T, A, R>
+{
+    fn receive(&mut self, choice_bits: &BitVec) -> Result<Vec<Vec<u8>>> {
+        let output_size = choice_bits.len();
+        // we pad our choices with kappa + rho random bits, these hide
+        // the real choices in the linear combination we reveal in the check
+        let extended_size = output_size + KOS_SECURITY_PARAM * 8 + self.stat_security_param * 8;
+        let hasher = batch_hasher(&self.hasher, &mut self.batch);
+        let mut choice_bits = choice_bits.clone();
+        while choice_bits.len() < extended_size {
+            let bit: bool = self.rng.gen();
+            choice_bits.push(bit);
+        }
+
+        let t_mat: Vec<BitVec> = self
+            .initial_pairs
+            .iter()
+            .map(|(k0, _)| trunc_hash(hasher.clone(), extended_size, k0))
+            .collect();
+        let u_mat: Vec<Vec<u8>> = self
+            .initial_pairs
+            .iter()
+            .zip(&t_mat)
+            .map(|((_, k1), t)| {
+                let gk = trunc_hash(hasher.clone(), extended_size, k1);
+                let u: BitVec = izip!(t, gk, &choice_bits)
+                    .map(|(t, k, r)| t ^ k ^ r)
+                    .collect();
+                u.to_bytes()
+            })
+            .collect();
+        send_columns(&mut self.conn, &u_mat, self.chunk_size)?;
+
+        // correlation check: we reveal x = sum r_i * chi_i and t = sum t_i * chi_i
+        let chis = check_elements(&self.conn.receive()?, extended_size)?;
+        let mut x = 0;
+        let mut t = 0;
+        for (i, (chi, r)) in chis.iter().zip(&choice_bits).enumerate() {
+            if r {
+                x ^= chi;
+            }
+            t ^= gf128_mul(row(&t_mat, i), *chi);
+        }
+        let mut check = Vec::with_capacity(32);
+        check.extend_from_slice(&gf128_to_bytes(x));
+        check.extend_from_slice(&gf128_to_bytes(t));
+        self.conn.send(&check)?;
+
+        let mut result: Vec<Vec<u8>> = Vec::with_capacity(output_size);
+        let pairs = receive_pairs(&mut self.conn, output_size)?;
+        for (i, ys) in pairs.iter().enumerate() {
+            let ht = hash_row(&hasher, i, row(&t_mat, i), ys[0].len());
+            result.push(
+                ys[choice_bits[i] as usize]
+                    .iter()
+                    .zip(ht)
+                    .map(|(y, h)| y ^ h)
+                    .collect(),
+            );
+        }
+        Ok(result)
+    }
+}
+
+pub struct KOSExtendedOTSender<T, A, R>
+where
+    T: BinaryReceive + BinarySend,
+    A: ArbitraryDigest + Clone,
+    R: RngCore + CryptoRng,
+{
+    conn: T,
+    hasher: A,
+    rng: R,
+    initial: Vec<Vec<u8>>,
+    random_choices: BitVec,
+    stat_security_param: usize,
+    batch: usize,
+    chunk_size: usize,
+}
+
+impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone, R: RngCore + CryptoRng> GetConn<T>
+    for KOSExtendedOTSender<T, A, R>
+{
+    fn get_conn(self) -> T {
+        self.conn
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone, R: RngCore + CryptoRng> GetConnMut<T>
+    for KOSExtendedOTSender<T, A, R>
+{
+    fn get_conn_mut(&mut self) -> &mut T {
+        &mut self.conn
+    }
+}
+
+/// statistical security parameter: number of bytes to use
+impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone, R: RngCore + CryptoRng>
+    KOSExtendedOTSender<T, A, R>
+{
+    /// Like `new`, in a session negotiated with `SessionParams::kos`.
+    pub fn in_session<S>(session: &Session, hasher: A, base_ot_receiver: S, rng: R) -> Result<Self>
+    where
+        S: BaseOTReceiver + GetConn<T>,
+    {
+        let params = session.params_for::<A>(session::KOS)?;
+        Self::new(session.bind(hasher), base_ot_receiver, rng, params.statistical_param as usize)
+    }
+
+    pub fn new<S>(
+        hasher: A,
+        mut base_ot_receiver: S,
+        mut rng: R,
+        stat_security_param: usize,
+    ) -> Result<Self>
+    where
+        S: BaseOTReceiver + GetConn<T>,
+    {
+        let l = KOS_SECURITY_PARAM * 8;
+        let mut random_choices = BitVec::with_capacity(l);
+        for _ in 0..l {
+            let choice: bool = rng.gen();
+            random_choices.push(choice);
+        }
+        let indices: Vec<usize> = random_choices.iter().map(|c| c as usize).collect();
+        let initial = base_ot_receiver.receive_many(&indices, 2)?;
+        Ok(KOSExtendedOTSender {
+            conn: base_ot_receiver.get_conn(),
+            hasher,
+            rng,
+            initial,
+            random_choices,
+            stat_security_param,
+            batch: 0,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Sets the number of OTs whose ciphertexts are sent in one frame.
+    /// The receiver may use a different chunk size.
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = cmp::max(chunk_size, 1);
+    }
+}
+
+impl<T: BinaryReceive + BinarySend, A: ArbitraryDigest + Clone, R: RngCore + CryptoRng>
+    ExtendedOTSender for KOSExtendedOTSender<T, A, R>
+{
+    fn send(&mut self, values: Vec<(&[u8], &[u8])>) -> Result<()> {
+        let output_size = values.len();
+        let extended_size = output_size + KOS_SECURITY_PARAM * 8 + self.stat_security_param * 8;
+        if values.iter().any(|(x0, x1)| x0.len() != x1.len()) {
+            bail!("String pairs do not have same size");
+        }
+        let hasher = batch_hasher(&self.hasher, &mut self.batch);
+
+        let us = receive_columns(&mut self.conn, self.initial.len(), extended_size)?;
+        let q_mat: Vec<BitVec> = izip!(&self.initial, &us, &self.random_choices)
+            .map(|(k, u, s)| {
+                let gk = trunc_hash(hasher.clone(), extended_size, k);
+                u.iter().zip(gk).map(|(u, k)| (s & u) ^ k).collect()
+            })
+            .collect();
+
+        // only now that the receiver is bound to its matrix we choose the random field elements
+        let mut seed: [u8; 32] = Default::default();
+        self.rng.fill_bytes(&mut seed);
+        self.conn.send(&seed)?;
+        let chis = check_elements(&seed, extended_size)?;
+
+        // q_i = t_i + r_i * delta holds for every row if the receiver was honest,
+        // so the linear combinations have to satisfy q = t + x * delta
+        let check = self.conn.receive()?;
+        if check.len() != 32 {
+            bail!("Received correlation check of the wrong length");
+        }
+        let x = gf128_from_bytes(array_ref![check, 0, 16]);
+        let t = gf128_from_bytes(array_ref![check, 16, 16]);
+        let delta = gf128_from_bits(&self.random_choices);
+        let q = chis
+            .iter()
+            .enumerate()
+            .fold(0, |acc, (i, chi)| acc ^ gf128_mul(row(&q_mat, i), *chi));
+        if q != t ^ gf128_mul(x, delta) {
+            return Err(ErrorKind::ConsistencyCheckFailed.into());
+        }
+
+        for (c, chunk) in values.chunks(self.chunk_size).enumerate() {
+            let mut ys = Vec::with_capacity(2 * chunk.len());
+            for (j, (x0, x1)) in chunk.iter().enumerate() {
+                let i = c * self.chunk_size + j;
+                let q = row(&q_mat, i);
+                let hq = hash_row(&hasher, i, q, x0.len());
+                let shq = hash_row(&hasher, i, q ^ delta, x1.len());
+                ys.push(x0.iter().zip(hq).map(|(x, h)| x ^ h).collect());
+                ys.push(x1.iter().zip(shq).map(|(x, h)| x ^ h).collect());
+            }
+            self.conn.send(&pack(&ys))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::digest::sha3::SHA3_256;
+    use crate::common::util::{generate_random_choices, generate_random_string_pairs};
+    use crate::errors::*;
+    use rand::ChaChaRng;
+    use rand::FromEntropy;
+    use std::thread;
+    use crate::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+    use crate::sync::communication::adversary::{AdversaryChannel, Attack};
+    use crate::sync::communication::memory::channel_pair;
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::ot_extension::kos::{KOSExtendedOTReceiver, KOSExtendedOTSender};
+    use crate::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
+
+    // flips the choice bit of the first OT in 64 columns. A flip in column j only
+    // affects the sender if its j-th base OT choice is 1, so flipping in a single column
+    // would go unnoticed half of the time.
+    fn cheat(column_len: usize) -> Attack {
+        let mut done = false;
+        Attack::modify(move |_, frame| {
+            if !done && frame.len() == 128 * column_len {
+                for j in 0..64 {
+                    frame[j * column_len] ^= 0x80;
+                }
+                done = true;
+            }
+        })
+    }
+
+    fn kos_transfer(cheating: bool) -> (Result<Vec<Vec<u8>>>, Result<()>) {
+        let len = 100;
+        let n = 20;
+        let stat_security_param = 5;
+
+        let choices = generate_random_choices(len);
+        let values = generate_random_string_pairs(n, len);
+        let choices2 = choices.clone();
+        let values2 = values.clone();
+
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let ot_stream = server_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTSender::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )?;
+            let mut ot_ext =
+                KOSExtendedOTReceiver::new(SHA3_256::default(), ot, rng, stat_security_param)?;
+            // the cheating sender expects all columns in one frame
+            if !cheating {
+                ot_ext.set_chunk_size(13);
+            }
+            ot_ext.receive(&choices2)
+        });
+        let client = thread::spawn(move || {
+            // all columns fit into a single frame, no other frame has this length
+            let column_len = if cheating {
+                (len + 128 + stat_security_param * 8).div_ceil(8)
+            } else {
+                0
+            };
+            let ot_stream = AdversaryChannel::new(client_conn, vec![cheat(column_len)]);
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTReceiver::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            )?;
+            let mut ot_ext =
+                KOSExtendedOTSender::new(SHA3_256::default(), ot, rng, stat_security_param)?;
+            ot_ext.set_chunk_size(7);
+            ot_ext.send(
+                values2
+                    .iter()
+                    .map(|(s1, s2)| (s1.as_bytes(), s2.as_bytes()))
+                    .collect(),
+            )
+        });
+        let received = server.join().unwrap().map(|rec_values| {
+            for (i, choice) in choices.iter().enumerate() {
+                let expected = if choice { &values[i].1 } else { &values[i].0 };
+                assert_eq!(
+                    expected.as_bytes(),
+                    rec_values[i].as_slice(),
+                    "Values differ at index {}",
+                    i
+                );
+            }
+            rec_values
+        });
+        (received, client.join().unwrap())
+    }
+
+    #[test]
+    fn kos_test() {
+        let (received, sent) = kos_transfer(false);
+        sent.unwrap();
+        assert_eq!(received.unwrap().len(), 100);
+    }
+
+    #[test]
+    fn kos_cheating_receiver() {
+        let (received, sent) = kos_transfer(true);
+        match sent {
+            Err(Error(ErrorKind::ConsistencyCheckFailed, _)) => (),
+            r => panic!("cheating receiver was not detected: {:?}", r),
+        }
+        assert!(received.is_err());
+    }
+}
@@ -4,12 +4,18 @@
 //! without using expensive public-key-cryptography.
 //! While BaseOT implements 1-out-of-n OT, this implements n 1-out-of-2 OTs
 //! because that's how most OT extension protocols work.
-//! It is trivial to implement 1-out-of-n OT with n 1-out-of-2 OTs.
+//! For choices between more than two messages `kk13` provides a native 1-out-of-n extension,
+//! which is much faster than building each transfer from log n 1-out-of-2 OTs.
+//! Still, `one_out_of_n` does exactly that for any of the 1-out-of-2 extensions.
 use bit_vec::BitVec;
-use errors::*;
+use crate::errors::*;
 
-//pub mod alsz;
+pub mod alsz;
+pub mod beaver;
 pub mod iknp;
+pub mod kk13;
+pub mod kos;
+pub mod one_out_of_n;
 
 /// This is the base trait for sending all ot-extension protocols in this library implement.
 pub trait ExtendedOTSender {
@@ -20,3 +26,47 @@ pub trait ExtendedOTSender {
 pub trait ExtendedOTReceiver {
     fn receive(&mut self, choice_bits: &BitVec) -> Result<Vec<Vec<u8>>>;
 }
+
+/// Sending side of 1-out-of-n OT extensions, every OT may have its own number of messages.
+pub trait ExtendedNOTSender {
+    fn send(&mut self, values: Vec<Vec<&[u8]>>) -> Result<()>;
+}
+
+/// Receiving side of 1-out-of-n OT extensions, `choices[i]` is the index of the message received in the i-th OT.
+pub trait ExtendedNOTReceiver {
+    fn receive(&mut self, choices: &[usize]) -> Result<Vec<Vec<u8>>>;
+}
+
+/// Correlated OT: the sender does not choose its messages but only how they are related,
+/// the first message of every pair is random and returned to the sender.
+/// This allows the sender to transmit one correction per OT instead of two full messages.
+pub trait CorrelatedOTSender {
+    /// Transfers `x0_i` and `x1_i = x0_i ⊕ delta` for `n` OTs with a global `delta`, returns the `x0_i`.
+    fn send_correlated(&mut self, n: usize, delta: &[u8]) -> Result<Vec<Vec<u8>>>;
+    /// Transfers `x0_i` and `x1_i = x0_i + deltas[i]` in the additive group Z_{2^64}, returns the `x0_i`.
+    /// As reduction modulo 2^k is a homomorphism, results for any smaller k are obtained by reducing
+    /// the outputs of both parties.
+    fn send_correlated_additive(&mut self, deltas: &[u64]) -> Result<Vec<u64>>;
+}
+
+/// Receiving side of `CorrelatedOTSender`.
+pub trait CorrelatedOTReceiver {
+    /// Receives `x0_i` or `x0_i ⊕ delta` of length `len` depending on the i-th choice bit.
+    fn receive_correlated(&mut self, choice_bits: &BitVec, len: usize) -> Result<Vec<Vec<u8>>>;
+    /// Receives `x0_i` or `x0_i + delta_i` (mod 2^64) depending on the i-th choice bit.
+    fn receive_correlated_additive(&mut self, choice_bits: &BitVec) -> Result<Vec<u64>>;
+}
+
+/// Random OT: neither party chooses the transferred messages, the sender gets random pairs
+/// and the receiver the random message belonging to its choice bit. Apart from the
+/// extension itself nothing is sent, see `beaver` to turn these into chosen-message OTs.
+pub trait RandomOTSender {
+    /// Returns `n` pairs of random messages with `len` bytes each.
+    fn random_send(&mut self, n: usize, len: usize) -> Result<Vec<(Vec<u8>, Vec<u8>)>>;
+}
+
+/// Receiving side of `RandomOTSender`.
+pub trait RandomOTReceiver {
+    /// Returns the message with `len` bytes the i-th choice bit selects from the i-th pair.
+    fn random_receive(&mut self, choice_bits: &BitVec, len: usize) -> Result<Vec<Vec<u8>>>;
+}
@@ -0,0 +1,369 @@
+//! 1-out-of-n OT from any 1-out-of-2 OT extension, as described by Naor and Pinkas.
+//! [https://doi.org/10.1145/301250.301312]
+//!
+//! For a transfer of n messages the sender chooses k = ⌈log n⌉ pairs of random keys and
+//! transfers them with k OTs of the extension, the receiver chooses the keys given by the bits
+//! of its index. Every message x is then sent encrypted with `H(i || x || K_1^{x_1} || ... || K_k^{x_k})`,
+//! so the receiver can only decrypt the message of its index.
+//!
+//! Unlike `kk13` this works with any extension, including the actively secure ones,
+//! but the sender has to compute n hashes with k keys each per transfer.
+
+use super::{ExtendedNOTReceiver, ExtendedNOTSender, ExtendedOTReceiver, ExtendedOTSender};
+use bit_vec::BitVec;
+use byte_tools::{read_u64_be, write_u64_be};
+use crate::common::digest::ArbitraryDigest;
+use crate::common::util::{pack, unpack};
+use crate::errors::*;
+use rand::{CryptoRng, Rng, RngCore};
+use std::marker::PhantomData;
+use crate::sync::base_ot::{BaseOTReceiver, BaseOTSender};
+use crate::sync::communication::{BinaryReceive, BinarySend, GetConn, GetConnMut};
+
+/// Number of bytes of the keys transferred with the extension.
+const KEY_LEN: usize = 16;
+
+/// Number of bits needed for an index smaller than `n`.
+fn index_bits(n: usize) -> usize {
+    let mut k = 0;
+    while k < 64 && (1 << k) < n {
+        k += 1;
+    }
+    k
+}
+
+/// `H(i || x || keys)`, truncated to `len` bytes.
+fn hash_keys<'a, A, I>(hasher: &A, i: usize, x: usize, keys: I, len: usize) -> Vec<u8>
+where
+    A: ArbitraryDigest + Clone,
+    I: Iterator<Item = &'a [u8]>,
+{
+    let mut hasher = hasher.clone();
+    hasher.input(&(i as u64).to_be_bytes());
+    hasher.input(&(x as u64).to_be_bytes());
+    for key in keys {
+        hasher.input(key);
+    }
+    hasher.result(len)
+}
+
+/// Sends 1-out-of-n transfers with the 1-out-of-2 extension `O`.
+pub struct OneOutOfNSender<T, O, A, R>
+where
+    T: BinaryReceive + BinarySend,
+    O: ExtendedOTSender + GetConnMut<T>,
+    A: ArbitraryDigest + Clone,
+    R: RngCore + CryptoRng,
+{
+    ot: O,
+    hasher: A,
+    rng: R,
+    conn: PhantomData<T>,
+}
+
+impl<T, O, A, R> OneOutOfNSender<T, O, A, R>
+where
+    T: BinaryReceive + BinarySend,
+    O: ExtendedOTSender + GetConnMut<T>,
+    A: ArbitraryDigest + Clone,
+    R: RngCore + CryptoRng,
+{
+    /// Wraps an already set up extension, no messages are exchanged.
+    pub fn new(ot: O, hasher: A, rng: R) -> Self {
+        OneOutOfNSender {
+            ot,
+            hasher,
+            rng,
+            conn: PhantomData,
+        }
+    }
+}
+
+impl<T, O, A, R> GetConn<T> for OneOutOfNSender<T, O, A, R>
+where
+    T: BinaryReceive + BinarySend,
+    O: ExtendedOTSender + GetConnMut<T> + GetConn<T>,
+    A: ArbitraryDigest + Clone,
+    R: RngCore + CryptoRng,
+{
+    fn get_conn(self) -> T {
+        self.ot.get_conn()
+    }
+}
+
+impl<T, O, A, R> ExtendedNOTSender for OneOutOfNSender<T, O, A, R>
+where
+    T: BinaryReceive + BinarySend,
+    O: ExtendedOTSender + GetConnMut<T>,
+    A: ArbitraryDigest + Clone,
+    R: RngCore + CryptoRng,
+{
+    fn send(&mut self, values: Vec<Vec<&[u8]>>) -> Result<()> {
+        if values.iter().any(|v| v.is_empty()) {
+            bail!("Every transfer needs at least one message");
+        }
+        // the receiver needs to know how many OTs belong to every transfer
+        let mut counts = vec![0; 8 * values.len()];
+        for (count, v) in counts.chunks_mut(8).zip(&values) {
+            write_u64_be(count, v.len() as u64);
+        }
+        self.ot.get_conn_mut().send(&counts)?;
+
+        let mut keys: Vec<Vec<(Vec<u8>, Vec<u8>)>> = Vec::with_capacity(values.len());
+        for v in &values {
+            let rng = &mut self.rng;
+            keys.push(
+                (0..index_bits(v.len()))
+                    .map(|_| {
+                        let k0 = (0..KEY_LEN).map(|_| rng.gen()).collect();
+                        let k1 = (0..KEY_LEN).map(|_| rng.gen()).collect();
+                        (k0, k1)
+                    })
+                    .collect(),
+            );
+        }
+        let pairs: Vec<(&[u8], &[u8])> = keys
+            .iter()
+            .flat_map(|k| k.iter().map(|(k0, k1)| (k0.as_slice(), k1.as_slice())))
+            .collect();
+        if !pairs.is_empty() {
+            self.ot.send(pairs)?;
+        }
+
+        let mut ots = Vec::with_capacity(values.len());
+        for (i, (messages, keys)) in values.iter().zip(&keys).enumerate() {
+            let ys: Vec<Vec<u8>> = messages
+                .iter()
+                .enumerate()
+                .map(|(x, m)| {
+                    let chosen = keys.iter().enumerate().map(|(j, (k0, k1))| {
+                        if (x >> j) & 1 == 1 {
+                            k1.as_slice()
+                        } else {
+                            k0.as_slice()
+                        }
+                    });
+                    let h = hash_keys(&self.hasher, i, x, chosen, m.len());
+                    m.iter().zip(h).map(|(m, h)| m ^ h).collect()
+                })
+                .collect();
+            ots.push(pack(&ys));
+        }
+        self.ot.get_conn_mut().send(&pack(&ots))?;
+        Ok(())
+    }
+}
+
+impl<T, O, A, R> BaseOTSender for OneOutOfNSender<T, O, A, R>
+where
+    T: BinaryReceive + BinarySend,
+    O: ExtendedOTSender + GetConnMut<T>,
+    A: ArbitraryDigest + Clone,
+    R: RngCore + CryptoRng,
+{
+    fn send(&mut self, values: Vec<&[u8]>) -> Result<()> {
+        ExtendedNOTSender::send(self, vec![values])
+    }
+
+    fn send_many(&mut self, values: Vec<Vec<&[u8]>>) -> Result<()> {
+        ExtendedNOTSender::send(self, values)
+    }
+}
+
+/// Receives 1-out-of-n transfers with the 1-out-of-2 extension `O`.
+pub struct OneOutOfNReceiver<T, O, A>
+where
+    T: BinaryReceive + BinarySend,
+    O: ExtendedOTReceiver + GetConnMut<T>,
+    A: ArbitraryDigest + Clone,
+{
+    ot: O,
+    hasher: A,
+    conn: PhantomData<T>,
+}
+
+impl<T, O, A> OneOutOfNReceiver<T, O, A>
+where
+    T: BinaryReceive + BinarySend,
+    O: ExtendedOTReceiver + GetConnMut<T>,
+    A: ArbitraryDigest + Clone,
+{
+    /// Wraps an already set up extension, no messages are exchanged.
+    pub fn new(ot: O, hasher: A) -> Self {
+        OneOutOfNReceiver {
+            ot,
+            hasher,
+            conn: PhantomData,
+        }
+    }
+
+    /// Receives the messages selected by `choices`, if `n` is given every transfer has to consist of `n` messages.
+    fn transfer(&mut self, choices: &[usize], n: Option<usize>) -> Result<Vec<Vec<u8>>> {
+        let counts = self.ot.get_conn_mut().receive()?;
+        if counts.len() != 8 * choices.len() {
+            bail!("Received wrong number of transfers");
+        }
+        let counts: Vec<usize> = counts.chunks(8).map(|c| read_u64_be(c) as usize).collect();
+        for (&c, &count) in choices.iter().zip(&counts) {
+            if n.is_some_and(|n| n != count) {
+                bail!("Received transfer has the wrong number of messages");
+            }
+            if c >= count {
+                bail!("Choice is out of range");
+            }
+        }
+
+        let choice_bits: BitVec = choices
+            .iter()
+            .zip(&counts)
+            .flat_map(|(&c, &count)| (0..index_bits(count)).map(move |j| (c >> j) & 1 == 1))
+            .collect();
+        let keys = if choice_bits.is_empty() {
+            Vec::new()
+        } else {
+            self.ot.receive(&choice_bits)?
+        };
+
+        let ots = unpack(&self.ot.get_conn_mut().receive()?)?;
+        if ots.len() != choices.len() {
+            bail!("Received wrong number of transfers");
+        }
+        let mut keys = keys.iter();
+        let mut result = Vec::with_capacity(choices.len());
+        for (i, (ot, (&c, &count))) in ots.iter().zip(choices.iter().zip(&counts)).enumerate() {
+            let ys = unpack(ot)?;
+            if ys.len() != count {
+                bail!("Received wrong number of ciphertexts");
+            }
+            let chosen = keys.by_ref().take(index_bits(count)).map(|k| k.as_slice());
+            let h = hash_keys(&self.hasher, i, c, chosen, ys[c].len());
+            result.push(ys[c].iter().zip(h).map(|(y, h)| y ^ h).collect());
+        }
+        Ok(result)
+    }
+}
+
+impl<T, O, A> GetConn<T> for OneOutOfNReceiver<T, O, A>
+where
+    T: BinaryReceive + BinarySend,
+    O: ExtendedOTReceiver + GetConnMut<T> + GetConn<T>,
+    A: ArbitraryDigest + Clone,
+{
+    fn get_conn(self) -> T {
+        self.ot.get_conn()
+    }
+}
+
+impl<T, O, A> ExtendedNOTReceiver for OneOutOfNReceiver<T, O, A>
+where
+    T: BinaryReceive + BinarySend,
+    O: ExtendedOTReceiver + GetConnMut<T>,
+    A: ArbitraryDigest + Clone,
+{
+    fn receive(&mut self, choices: &[usize]) -> Result<Vec<Vec<u8>>> {
+        self.transfer(choices, None)
+    }
+}
+
+impl<T, O, A> BaseOTReceiver for OneOutOfNReceiver<T, O, A>
+where
+    T: BinaryReceive + BinarySend,
+    O: ExtendedOTReceiver + GetConnMut<T>,
+    A: ArbitraryDigest + Clone,
+{
+    fn receive(&mut self, index: usize, n: usize) -> Result<Vec<u8>> {
+        Ok(self.transfer(&[index], Some(n))?.remove(0))
+    }
+
+    fn receive_many(&mut self, indices: &[usize], n: usize) -> Result<Vec<Vec<u8>>> {
+        self.transfer(indices, Some(n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::digest::sha3::SHA3_256;
+    use rand::distributions::range::Range;
+    use rand::{thread_rng, ChaChaRng, FromEntropy};
+    use std::thread;
+    use crate::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+    use crate::sync::communication::memory::channel_pair;
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
+
+    #[test]
+    fn index_bits_test() {
+        assert_eq!(index_bits(1), 0);
+        assert_eq!(index_bits(2), 1);
+        assert_eq!(index_bits(3), 2);
+        assert_eq!(index_bits(256), 8);
+        assert_eq!(index_bits(257), 9);
+    }
+
+    #[test]
+    fn one_out_of_n_over_iknp() {
+        let mut rng = thread_rng();
+        let values: Vec<Vec<Vec<u8>>> = (0..50)
+            .map(|i| {
+                let n = [1, 2, 5, 8, 300][i % 5];
+                (0..n).map(|_| (0..20).map(|_| rng.gen()).collect()).collect()
+            })
+            .collect();
+        let choices: Vec<usize> = values
+            .iter()
+            .map(|v| rng.sample(Range::new(0, v.len())))
+            .collect();
+        let index = rng.sample(Range::new(0, 10));
+        let choices2 = choices.clone();
+        let values2 = values.clone();
+
+        let (server_conn, client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let ot_stream = server_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTSender::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let ot_ext = IKNPExtendedOTReceiver::new(SHA3_256::default(), ot, rng, 16).unwrap();
+            let mut ot = OneOutOfNReceiver::new(ot_ext, SHA3_256::default());
+            let received = ExtendedNOTReceiver::receive(&mut ot, &choices2).unwrap();
+            // with the interface of the base OTs
+            let single = BaseOTReceiver::receive(&mut ot, index, 10).unwrap();
+            let wrong_n = BaseOTReceiver::receive(&mut ot, index, 11);
+            (received, single, wrong_n.is_err())
+        });
+        let client = thread::spawn(move || {
+            let ot_stream = client_conn;
+            let rng = ChaChaRng::from_entropy();
+            let ot = ChouOrlandiOTReceiver::new(
+                ot_stream,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let ot_ext = IKNPExtendedOTSender::new(SHA3_256::default(), ot, rng.clone(), 16).unwrap();
+            let mut ot = OneOutOfNSender::new(ot_ext, SHA3_256::default(), rng);
+            let values: Vec<Vec<&[u8]>> = values2
+                .iter()
+                .map(|v| v.iter().map(|x| x.as_slice()).collect())
+                .collect();
+            ExtendedNOTSender::send(&mut ot, values).unwrap();
+            let strings: Vec<Vec<u8>> = (0..10).map(|i| vec![i; 8]).collect();
+            let strings: Vec<&[u8]> = strings.iter().map(|s| s.as_slice()).collect();
+            BaseOTSender::send(&mut ot, strings.clone()).unwrap();
+            // the receiver aborts before the keys are transferred
+            let _ = BaseOTSender::send(&mut ot, strings);
+        });
+        let (received, single, wrong_n) = server.join().unwrap();
+        client.join().unwrap();
+        for (i, &choice) in choices.iter().enumerate() {
+            assert_eq!(values[i][choice], received[i], "Values differ at index {}", i);
+        }
+        assert_eq!(single, vec![index as u8; 8]);
+        assert!(wrong_n);
+    }
+}
@@ -0,0 +1,358 @@
+//! Negotiation of the session parameters before an OT protocol runs.
+//!
+//! The protocols trust that both parties were set up with the same parameters, a mismatch
+//! (e.g. different security parameters of an OT extension) leads to panics or wrong outputs.
+//! With `Session::negotiate` both parties first exchange a header with the protocol, its version,
+//! the security and statistical parameter and the identifiers of digest and cipher,
+//! and fail with `ErrorKind::SessionMismatch` if they differ.
+//!
+//! The header also contains a random nonce of each party, from which the session id is derived.
+//! Feeding the id into the hash functions of the protocols (see `Session::bind`) separates
+//! the hashes of different sessions, even if the same keys were used in both of them.
+//!
+//! The parameters of each protocol are created with its constructor, e.g. `SessionParams::iknp`,
+//! which takes the identifiers of digest and cipher from the `NAME` of their types. The protocols
+//! are set up from the negotiated session with their `in_session` constructors. These bind the hash
+//! functions to the session id and check that the session was negotiated for the types they were
+//! given. The OT extensions also check that it was negotiated for them and take their security
+//! parameters from it. A base OT runs in the session of the extension it is used by, so it only
+//! checks its cipher there and its hash function only in a session of its own.
+use byte_tools::{read_u64_be, write_u64_be};
+use crate::common::digest::sha3::SHA3_256;
+use crate::common::digest::{Digest, Separable};
+use crate::common::util::{pack, unpack};
+use crate::errors::*;
+use rand::{CryptoRng, RngCore};
+use crate::sync::communication::{BinaryReceive, BinarySend};
+use crate::sync::crypto::CryptoProvider;
+use crate::sync::ot_extension::kk13::CODE_LENGTH;
+use crate::sync::ot_extension::kos::KOS_SECURITY_PARAM;
+
+/// Identifies the format of the header, the version of the protocol is part of the parameters.
+const MAGIC: &[u8] = b"OT session 1";
+const NONCE_LEN: usize = 32;
+
+/// Names of the protocols in the session header.
+pub const CHOU_ORLANDI: &str = "chou-orlandi";
+pub const CHOU_ORLANDI_RISTRETTO: &str = "chou-orlandi-ristretto";
+pub const MASNY_RINDAL: &str = "masny-rindal";
+pub const NAOR_PINKAS: &str = "naor-pinkas";
+pub const IKNP: &str = "iknp";
+pub const ALSZ: &str = "alsz";
+pub const KOS: &str = "kos";
+pub const KK13: &str = "kk13";
+
+/// The parameters both parties have to agree on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SessionParams {
+    /// Name of the protocol, e.g. `"iknp"`.
+    pub protocol: String,
+    pub version: u64,
+    /// E.g. the security parameter of an OT extension, 0 if the protocol has none.
+    pub security_param: u64,
+    /// E.g. the statistical security parameter of ALSZ or KOS, 0 if the protocol has none.
+    pub statistical_param: u64,
+    /// Identifier of the hash function, e.g. `"sha3-256"`.
+    pub digest: String,
+    /// Identifier of the symmetric cipher, e.g. `"aes-256-gcm"`, empty if none is used.
+    pub cipher: String,
+}
+
+impl SessionParams {
+    pub fn new(protocol: &str, version: u64) -> Self {
+        SessionParams {
+            protocol: protocol.to_owned(),
+            version,
+            security_param: 0,
+            statistical_param: 0,
+            digest: String::new(),
+            cipher: String::new(),
+        }
+    }
+
+    fn base_ot<H: Separable, C: CryptoProvider>(protocol: &str) -> Self {
+        let mut params = SessionParams::new(protocol, 1);
+        params.digest = H::NAME.to_owned();
+        params.cipher = C::NAME.to_owned();
+        params
+    }
+
+    /// `D` and `C` are the hash function and the symmetric cipher of the base OT.
+    pub fn chou_orlandi<D: Separable, C: CryptoProvider>() -> Self {
+        SessionParams::base_ot::<D, C>(CHOU_ORLANDI)
+    }
+
+    pub fn chou_orlandi_ristretto<D: Separable, C: CryptoProvider>() -> Self {
+        SessionParams::base_ot::<D, C>(CHOU_ORLANDI_RISTRETTO)
+    }
+
+    pub fn masny_rindal<D: Separable, C: CryptoProvider>() -> Self {
+        SessionParams::base_ot::<D, C>(MASNY_RINDAL)
+    }
+
+    pub fn naor_pinkas<D: Separable, C: CryptoProvider>() -> Self {
+        SessionParams::base_ot::<D, C>(NAOR_PINKAS)
+    }
+
+    /// `H` is the hash function of the extension, `C` the cipher of its base OT.
+    pub fn iknp<H: Separable, C: CryptoProvider>(security_param: usize) -> Self {
+        let mut params = SessionParams::base_ot::<H, C>(IKNP);
+        params.security_param = security_param as u64;
+        params
+    }
+
+    pub fn alsz<H: Separable, C: CryptoProvider>(
+        security_param: usize,
+        stat_security_param: usize,
+    ) -> Self {
+        let mut params = SessionParams::base_ot::<H, C>(ALSZ);
+        params.security_param = security_param as u64;
+        params.statistical_param = stat_security_param as u64;
+        params
+    }
+
+    /// The security parameter of KOS is fixed to `KOS_SECURITY_PARAM`.
+    pub fn kos<H: Separable, C: CryptoProvider>(stat_security_param: usize) -> Self {
+        let mut params = SessionParams::base_ot::<H, C>(KOS);
+        params.security_param = KOS_SECURITY_PARAM as u64;
+        params.statistical_param = stat_security_param as u64;
+        params
+    }
+
+    /// The security parameter of KK13 is the number of its base OTs in bytes.
+    pub fn kk13<H: Separable, C: CryptoProvider>() -> Self {
+        let mut params = SessionParams::base_ot::<H, C>(KK13);
+        params.security_param = (CODE_LENGTH / 8) as u64;
+        params
+    }
+
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("protocol", self.protocol.clone()),
+            ("version", self.version.to_string()),
+            ("security_param", self.security_param.to_string()),
+            ("statistical_param", self.statistical_param.to_string()),
+            ("digest", self.digest.clone()),
+            ("cipher", self.cipher.clone()),
+        ]
+    }
+}
+
+fn u64_bytes(x: u64) -> Vec<u8> {
+    let mut bytes = vec![0; 8];
+    write_u64_be(&mut bytes, x);
+    bytes
+}
+
+fn encode(params: &SessionParams, nonce: &[u8]) -> Vec<u8> {
+    pack(&[
+        MAGIC.to_vec(),
+        params.protocol.as_bytes().to_vec(),
+        u64_bytes(params.version),
+        u64_bytes(params.security_param),
+        u64_bytes(params.statistical_param),
+        params.digest.as_bytes().to_vec(),
+        params.cipher.as_bytes().to_vec(),
+        nonce.to_vec(),
+    ])
+}
+
+fn decode(header: &[u8]) -> Result<(SessionParams, Vec<u8>)> {
+    let fields = match unpack(header) {
+        Ok(ref fields) if fields.len() == 8 && fields[0] == MAGIC => fields.clone(),
+        _ => bail!("Received invalid session header"),
+    };
+    if fields[2..5].iter().any(|f| f.len() != 8) || fields[7].len() != NONCE_LEN {
+        bail!("Received invalid session header");
+    }
+    let string = |f: &Vec<u8>| -> Result<String> {
+        String::from_utf8(f.clone()).chain_err(|| "Received invalid session header")
+    };
+    let params = SessionParams {
+        protocol: string(&fields[1])?,
+        version: read_u64_be(&fields[2]),
+        security_param: read_u64_be(&fields[3]),
+        statistical_param: read_u64_be(&fields[4]),
+        digest: string(&fields[5])?,
+        cipher: string(&fields[6])?,
+    };
+    Ok((params, fields[7].clone()))
+}
+
+/// A session whose parameters both parties agreed on.
+pub struct Session {
+    params: SessionParams,
+    id: [u8; 32],
+}
+
+impl Session {
+    /// Sends our header, receives the one of the other party and compares them.
+    pub fn negotiate<C, R>(conn: &mut C, params: SessionParams, rng: &mut R) -> Result<Self>
+    where
+        C: BinarySend + BinaryReceive,
+        R: RngCore + CryptoRng,
+    {
+        let mut nonce = [0; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+        let header = encode(&params, &nonce);
+        conn.send(&header)?;
+        let remote_header = conn.receive()?;
+        let (remote_params, _) = decode(&remote_header)?;
+
+        for ((name, local), (_, remote)) in params.fields().into_iter().zip(remote_params.fields()) {
+            if local != remote {
+                return Err(ErrorKind::SessionMismatch(name.to_owned(), local, remote).into());
+            }
+        }
+
+        // both parties hash the headers in the same order, so they get the same id
+        let (first, second) = if header <= remote_header {
+            (&header, &remote_header)
+        } else {
+            (&remote_header, &header)
+        };
+        let mut hasher = SHA3_256::default();
+        Digest::input(&mut hasher, first);
+        Digest::input(&mut hasher, second);
+        let mut id = [0; 32];
+        id.copy_from_slice(&Digest::result(hasher));
+        Ok(Session { params, id })
+    }
+
+    pub fn params(&self) -> &SessionParams {
+        &self.params
+    }
+
+    /// The session id, the same for both parties and random as long as one of them is honest.
+    pub fn id(&self) -> &[u8; 32] {
+        &self.id
+    }
+
+    /// Binds a hash function to this session, a protocol set up with the returned hasher
+    /// only computes hashes bound to this session.
+    pub fn bind<H: Separable>(&self, hasher: H) -> H {
+        hasher.separate(&self.id)
+    }
+
+    /// Fails unless the session was negotiated for `protocol` with the hash function `H`,
+    /// returns its parameters otherwise.
+    pub fn params_for<H: Separable>(&self, protocol: &str) -> Result<&SessionParams> {
+        self.expect("protocol", &self.params.protocol, protocol)?;
+        self.expect("digest", &self.params.digest, H::NAME)?;
+        Ok(&self.params)
+    }
+
+    /// Fails unless the session was negotiated for the cipher `C`, and for the hash function `D`
+    /// if it was negotiated for the base OT `protocol` itself rather than for an extension.
+    pub fn check_base_ot<D: Separable, C: CryptoProvider>(&self, protocol: &str) -> Result<()> {
+        if self.params.protocol == protocol {
+            self.expect("digest", &self.params.digest, D::NAME)?;
+        }
+        self.expect("cipher", &self.params.cipher, C::NAME)
+    }
+
+    fn expect(&self, parameter: &str, negotiated: &str, used: &str) -> Result<()> {
+        if negotiated != used {
+            bail!(
+                "Session was negotiated with {} {}, not with {}",
+                parameter,
+                negotiated,
+                used
+            );
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::digest::aes::AesHash;
+    use crate::common::util::{generate_random_choices, generate_random_string_pairs};
+    use rand::{ChaChaRng, FromEntropy};
+    use std::thread;
+    use crate::sync::base_ot::chou::{ChouOrlandiOTReceiver, ChouOrlandiOTSender};
+    use crate::sync::communication::memory::channel_pair;
+    use crate::sync::crypto::aes::AesCryptoProvider;
+    use crate::sync::crypto::dummy::DummyCryptoProvider;
+    use crate::sync::ot_extension::iknp::{IKNPExtendedOTReceiver, IKNPExtendedOTSender};
+    use crate::sync::ot_extension::{ExtendedOTReceiver, ExtendedOTSender};
+
+    fn iknp_params(security_param: usize) -> SessionParams {
+        SessionParams::iknp::<SHA3_256, AesCryptoProvider>(security_param)
+    }
+
+    #[test]
+    fn mismatch_is_rejected() {
+        let (mut server_conn, mut client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let mut rng = ChaChaRng::from_entropy();
+            Session::negotiate(&mut server_conn, iknp_params(16), &mut rng).map(|_| ())
+        });
+        let mut rng = ChaChaRng::from_entropy();
+        let client = Session::negotiate(&mut client_conn, iknp_params(32), &mut rng);
+        for result in [server.join().unwrap(), client.map(|_| ())] {
+            match result {
+                Err(Error(ErrorKind::SessionMismatch(ref name, _, _), _)) => {
+                    assert_eq!(name, "security_param")
+                }
+                _ => panic!("Mismatch was not detected"),
+            }
+        }
+    }
+
+    #[test]
+    fn iknp_in_session() {
+        let n = 100;
+        let choices = generate_random_choices(n);
+        let values = generate_random_string_pairs(16, n);
+        let choices2 = choices.clone();
+        let values2 = values.clone();
+
+        let (mut server_conn, mut client_conn) = channel_pair();
+        let server = thread::spawn(move || {
+            let mut rng = ChaChaRng::from_entropy();
+            let session = Session::negotiate(&mut server_conn, iknp_params(16), &mut rng).unwrap();
+            let ot = ChouOrlandiOTSender::in_session(
+                server_conn,
+                &session,
+                SHA3_256::default(),
+                AesCryptoProvider::default(),
+                rng.clone(),
+            ).unwrap();
+            let mut ot_ext =
+                IKNPExtendedOTReceiver::in_session(&session, SHA3_256::default(), ot, rng).unwrap();
+            (*session.id(), ot_ext.receive(&choices2).unwrap())
+        });
+        let mut rng = ChaChaRng::from_entropy();
+        let session = Session::negotiate(&mut client_conn, iknp_params(16), &mut rng).unwrap();
+        assert!(session.params_for::<SHA3_256>(KOS).is_err());
+        assert!(session.params_for::<AesHash>(IKNP).is_err());
+        assert!(session
+            .check_base_ot::<SHA3_256, DummyCryptoProvider>(CHOU_ORLANDI)
+            .is_err());
+        let ot = ChouOrlandiOTReceiver::in_session(
+            client_conn,
+            &session,
+            SHA3_256::default(),
+            AesCryptoProvider::default(),
+            rng.clone(),
+        ).unwrap();
+        let mut ot_ext =
+            IKNPExtendedOTSender::in_session(&session, SHA3_256::default(), ot, rng).unwrap();
+        ot_ext
+            .send(
+                values2
+                    .iter()
+                    .map(|(s0, s1)| (s0.as_bytes(), s1.as_bytes()))
+                    .collect(),
+            )
+            .unwrap();
+        let (id, result) = server.join().unwrap();
+
+        assert_eq!(&id, session.id());
+        for (r, c, (s0, s1)) in izip!(result, &choices, values) {
+            assert_eq!(r, if c { s1 } else { s0 }.into_bytes());
+        }
+    }
+}